@@ -0,0 +1,150 @@
+use ai_log_exporter::{
+    find_marker, normalize_markdown, remove_marked_block, upsert_frontmatter_field,
+    upsert_marked_block, Config, EXPLODED_INDEX_FILE,
+};
+use ai_log_exporter::transcript::{render_messages, Msg, RenderOptions};
+use super::{build_sidechain_section, upsert_sidechain_block, SIDECHAIN_BEGIN, SIDECHAIN_END};
+use anyhow::{Context, Result};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Files are keyed by their 1-based position in the transcript, so re-exporting
+// the same session rewrites the same files instead of adding new ones.
+pub fn message_file_name(index: usize, role: &str) -> String {
+    format!("{:04}-{role}.md", index + 1)
+}
+
+fn is_message_file_name(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    bytes.len() > 8
+        && bytes[..4].iter().all(u8::is_ascii_digit)
+        && bytes[4] == b'-'
+        && name.ends_with(".md")
+}
+
+pub const MESSAGES_BEGIN: &str = "<!-- BEGIN AUTO MESSAGES -->";
+pub const MESSAGES_END: &str = "<!-- END AUTO MESSAGES -->";
+
+// Frontmatter fields the exporter keeps current in an existing index; the
+// rest of the frontmatter is the user's.
+const INDEX_FIELDS: [&str; 4] = ["uid", "tool_call_count", "model", "project_path"];
+
+pub fn build_messages_block(names: &[String]) -> String {
+    let mut out = format!("{MESSAGES_BEGIN}\n## Messages\n\n");
+    for (i, name) in names.iter().enumerate() {
+        let stem = name.trim_end_matches(".md");
+        out.push_str(&format!("{}. [{stem}]({name})\n", i + 1));
+    }
+    out.push_str(MESSAGES_END);
+    out
+}
+
+// A new index is the skeleton plus the message list. An existing one keeps the
+// user's frontmatter and text: only `INDEX_FIELDS` and the message list are
+// updated. An index from before the list had markers loses its old list.
+pub fn update_index(existing: Option<&str>, skeleton: &str, names: &[String]) -> String {
+    let block = build_messages_block(names);
+    let existing = match existing {
+        Some(existing) => existing,
+        None => return format!("{skeleton}{block}\n"),
+    };
+    let unmarked = find_marker(existing, MESSAGES_BEGIN).is_none();
+    let mut index = match existing.find("\n## Messages\n").filter(|_| unmarked) {
+        Some(at) => {
+            let list_end = existing[at + 1..]
+                .lines()
+                .skip(1)
+                .take_while(|l| l.is_empty() || is_list_line(l))
+                .map(|l| l.len() + 1)
+                .sum::<usize>();
+            let rest_at = (at + 1 + "## Messages\n".len() + list_end).min(existing.len());
+            format!("{}\n{block}\n\n{}", &existing[..at], &existing[rest_at..])
+        }
+        None => upsert_marked_block(existing, MESSAGES_BEGIN, MESSAGES_END, &block),
+    };
+    for key in INDEX_FIELDS {
+        if let Some(value) = raw_field(skeleton, key) {
+            index = upsert_frontmatter_field(&index, key, value);
+        }
+    }
+    index
+}
+
+fn is_list_line(line: &str) -> bool {
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    digits > 0 && line[digits..].starts_with(". [")
+}
+
+// `key`'s value as written in the frontmatter, quotes included.
+fn raw_field<'a>(note: &'a str, key: &str) -> Option<&'a str> {
+    note.strip_prefix("---\n")?
+        .lines()
+        .take_while(|l| *l != "---")
+        .find_map(|l| l.strip_prefix(key)?.strip_prefix(": "))
+}
+
+// Writes one file per message plus `index.md` into `dir` and returns the
+// index path. Unchanged files are left untouched to keep git diffs small, and
+// message files no longer backed by the transcript are removed. Sub-agent
+// messages are rendered into the index under their own heading. See
+// `update_index` for what an existing index keeps.
+pub fn write_exploded_session(
+    config: &Config,
+    dir: &Path,
+    skeleton: &str,
+    msgs: &[Msg],
+    sidechain: &[Msg],
+    opts: &RenderOptions,
+) -> Result<PathBuf> {
+    fs::create_dir_all(dir).context("failed to create session folder")?;
+
+    let names: Vec<String> = msgs
+        .iter()
+        .enumerate()
+        .map(|(i, m)| message_file_name(i, m.role))
+        .collect();
+
+    for (name, m) in names.iter().zip(msgs) {
+        let content = render_messages(std::slice::from_ref(m), opts);
+        write_if_changed(
+            config,
+            &dir.join(name),
+            &format!("{}\n", content.trim_end()),
+        )?;
+    }
+
+    for entry in fs::read_dir(dir)
+        .context("failed to list session folder")?
+        .flatten()
+    {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if is_message_file_name(&name) && !names.contains(&name) {
+            config
+                .remove_output(&entry.path())
+                .with_context(|| format!("failed to remove stale {name}"))?;
+        }
+    }
+
+    // Tool activity recorded into the index between exports stays with the
+    // rest of what is already there.
+    let index_path = dir.join(EXPLODED_INDEX_FILE);
+    let old = fs::read_to_string(&index_path).ok();
+    let mut index = update_index(old.as_deref(), skeleton, &names);
+    index = if sidechain.is_empty() {
+        remove_marked_block(&index, SIDECHAIN_BEGIN, SIDECHAIN_END)
+    } else {
+        upsert_sidechain_block(&index, &build_sidechain_section(sidechain, opts))
+    };
+    write_if_changed(config, &index_path, &index)?;
+    Ok(index_path)
+}
+
+fn write_if_changed(config: &Config, path: &Path, content: &str) -> Result<()> {
+    let content = normalize_markdown(content);
+    if fs::read_to_string(path).is_ok_and(|old| old == content) {
+        return Ok(());
+    }
+    config.write_output(path, &content)
+}
@@ -1,174 +1,1282 @@
 use ai_log_exporter::{
-    find_md_file_containing_id, generate_title, git_project_name, safe_id, safe_name, with_lock_file,
-    yaml_quote,
+    append_to_daily_digest, build_claude_note_skeleton, build_footer_block, canonical_role,
+    copy_path_refs, dated_thread_dir, expand_tilde, extract_text, extract_timestamp,
+    filename_budget, find_marker, find_md_file_by_uid, find_md_file_containing_id,
+    find_session_note, find_session_notes, frontmatter_field, generate_summary_with_llm,
+    generate_title, merge_frontmatter_template, normalize_markdown, note_body,
+    note_filename_within, note_identity, note_title, parse_cli_flags, parse_timestamp_in,
+    purge_exported_session, quote_lines, read_frontmatter_template, read_to_string_lossy,
+    remove_cwd_fields, remove_marked_block, repair_markers_with, resolve_created, safe_id,
+    safe_name, synthesized_session_id, take_flag, upsert_aliases_field, upsert_footer_block,
+    upsert_frontmatter_field, upsert_marked_block, upsert_model_field, upsert_project_path_field,
+    upsert_summary_block, with_lock_file, write_result_note, yaml_quote, AssumedZone, Config,
+    EXPLODED_INDEX_FILE, FileStamp, Flavor, Layout, MAX_PATH_LEN, PROCESSED_INDEX_FILE,
+    ProcessedIndex, SidechainMode, TextOptions,
 };
-use anyhow::{Context, Result};
-use chrono::{DateTime, Local, SecondsFormat};
+use ai_log_exporter::index::{index_path, index_session, IndexedMessage};
+use ai_log_exporter::log;
+use ai_log_exporter::transcript::{
+    build_transcript_block, build_transcript_body, is_slash_command, render_messages,
+    with_code_callouts, Msg, RenderOptions, Usage, BEGIN, END,
+};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local, NaiveDate, SecondsFormat};
 use serde_json::Value;
 use std::{
+    collections::HashSet,
     env, fs,
     io::{self, BufRead, BufReader, Read},
     path::{Path, PathBuf},
 };
 
-pub const BEGIN: &str = "<!-- BEGIN AUTO TRANSCRIPT -->";
-pub const END: &str = "<!-- END AUTO TRANSCRIPT -->";
+mod exploded;
+mod render;
+
+use exploded::write_exploded_session;
+use render::{
+    append_message_lines, message_csv, renderer_for_format, update_logseq_page, LogseqRenderer,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    // Drop user entries injected by Claude Code itself (see `is_meta_entry`).
+    pub skip_meta: bool,
+    // Stitch consecutive assistant entries (text split by tool calls) into one message.
+    pub coalesce_assistant: bool,
+    // How content parts are read (`LLM_LOGS_TEXT_TYPES` and friends).
+    pub text: TextOptions,
+    // Zone for timestamps without an offset (`LLM_LOGS_ASSUME_TZ`).
+    pub zone: AssumedZone,
+    // Drop user messages that are only a slash-command (see `is_slash_command`).
+    pub hide_slash: bool,
+    pub sidechain: SidechainMode,
+    // Entry types read as `user`/`assistant` (`LLM_LOGS_ROLE_ALIASES`).
+    pub role_aliases: Vec<(String, String)>,
+    // Only lines of this session are read; lines without a `sessionId` are kept.
+    pub session_id: Option<String>,
+}
+
+impl ParseOptions {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            skip_meta: config.skip_meta,
+            coalesce_assistant: config.coalesce_assistant,
+            text: config.text_options(),
+            zone: config.assume_tz,
+            hide_slash: config.hide_slash,
+            sidechain: config.sidechain,
+            role_aliases: config.role_aliases.clone(),
+            session_id: None,
+        }
+    }
+}
 
-#[derive(Debug, Clone)]
-pub struct Msg {
-    pub role: &'static str,
-    pub text: String,
-    pub ts: Option<DateTime<Local>>,
+#[derive(Debug, Clone, Default)]
+pub struct Transcript {
+    pub msgs: Vec<Msg>,
+    pub tool_call_count: usize,
+    // The model of the latest assistant entry that names one.
+    pub model: Option<String>,
+    // Sub-agent messages, kept apart from `msgs` in `SidechainMode::Section`.
+    pub sidechain: Vec<Msg>,
+    // Tokens of every assistant API call in the transcript, sub-agents included.
+    pub usage: Usage,
+    // Every `sessionId` seen, in order of first appearance.
+    pub session_ids: Vec<String>,
 }
 
+// Misconfiguration (a missing env var, an unwritable vault) and failed exports
+// are errors, so the hook runner reports them; an empty payload or an ignored
+// session is a no-op and exits 0.
 fn main() -> Result<()> {
-    let mut stdin = String::new();
-    io::stdin()
-        .read_to_string(&mut stdin)
-        .context("failed to read stdin")?;
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (verbosity, args) = log::take_verbosity(&args);
+    log::set_verbosity(verbosity);
+    let (force_rebuild, args) = take_force_rebuild(&args);
+    let (validate, args) = take_flag(&args, "--validate-payload");
+    let load_config = || -> Result<Config> {
+        let mut config = Config::from_env()?;
+        config.force_rebuild |= force_rebuild;
+        Ok(config)
+    };
+    if args.first().map(String::as_str) == Some("--print-config") {
+        let cwd = args.get(1).map(String::as_str).unwrap_or(".");
+        print!("{}", Config::from_env()?.describe(cwd));
+        return Ok(());
+    }
+    if args.first().map(String::as_str) == Some("--archive") {
+        let config = Config::from_env()?;
+        for path in config.archive(config.claude_dir_name())? {
+            println!("archived {}", path.display());
+        }
+        return Ok(());
+    }
+    if args.first().map(String::as_str) == Some("--reindex") {
+        let report = Config::from_env()?.reindex()?;
+        println!(
+            "indexed {} notes in {} projects",
+            report.notes, report.projects
+        );
+        return Ok(());
+    }
 
-    let stdin = stdin.trim();
-    if stdin.is_empty() {
+    if args.first().map(String::as_str) == Some("--import") {
+        let dir = args.get(1).context("missing directory for --import")?;
+        let report = import_transcripts(&load_config()?, &expand_tilde(dir))?;
+        println!(
+            "exported {}, skipped {} unchanged, failed {}",
+            report.exported, report.skipped, report.failed
+        );
+        // Failed transcripts are retried on the next run, but the run still fails.
+        if report.failed > 0 {
+            return Err(anyhow!("{} transcripts failed to export", report.failed));
+        }
         return Ok(());
     }
 
-    let payload: Value =
-        serde_json::from_str(stdin).context("failed to parse hook JSON from stdin")?;
+    let config = load_config()?;
+    let (range, args) = take_export_range(&args, config.assume_tz)?;
+    let (merge_into, args) = take_merge_into(&args)?;
+    let input = if args.is_empty() {
+        let mut stdin = String::new();
+        io::stdin()
+            .read_to_string(&mut stdin)
+            .context("failed to read stdin")?;
 
-    let session_id = payload
-        .get("session_id")
-        .and_then(|v| v.as_str())
-        .unwrap_or("unknown-session")
-        .to_string();
-    let session_id_safe = safe_id(&session_id, "unknown-session");
+        let stdin = stdin.trim();
+        if stdin.is_empty() {
+            return Ok(());
+        }
 
-    let transcript_path = payload
-        .get("transcript_path")
-        .and_then(|v| v.as_str())
-        .context("missing transcript_path in hook payload")?;
+        let payload: Value =
+            serde_json::from_str(stdin).context("failed to parse hook JSON from stdin")?;
+        SessionInput::from_payload(&payload)?
+    } else {
+        SessionInput::from_flags(&args)?
+    };
+    let cwd = input.cwd.as_str();
+
+    let project = config.project_name(cwd);
+    let base_dir = config
+        .ai_dir()?
+        .join(config.claude_dir_name())
+        .join(&project);
+    let merge_into = merge_into
+        .map(|raw| config.vault_note_path(&raw))
+        .transpose()?;
+    if validate {
+        let report = validate_session(&config, &project, &base_dir, &input, merge_into.as_deref())?;
+        print!("{report}");
+        return Ok(());
+    }
+
+    if let Some(range) = range {
+        let slice = export_slice(
+            &config,
+            &base_dir,
+            &project,
+            &input.session_id,
+            cwd,
+            &input.transcript_path,
+            &range,
+        )?;
+        match slice {
+            Some(path) => println!("wrote {}", path.display()),
+            None => log::warn(|| "no messages in --export-range".to_string()),
+        }
+        return Ok(());
+    }
+
+    export_session(
+        &config,
+        &base_dir,
+        &project,
+        &input.session_id,
+        cwd,
+        &input.transcript_path,
+        merge_into.as_deref(),
+    )?;
+    Ok(())
+}
+
+// What `export_session` would do with the hook input, for `--validate-payload`.
+// The transcript is only read, to date a new note; nothing is written.
+pub fn validate_session(
+    config: &Config,
+    project: &str,
+    base_dir: &Path,
+    input: &SessionInput,
+    merge_into: Option<&Path>,
+) -> Result<String> {
+    let session_id_safe = safe_id(&input.session_id, "unknown-session");
+    let uid = note_identity("Claude Code", &input.session_id);
+    let md_dir = base_dir.join("Threads");
+    let transcript = &input.transcript_path;
+
+    let status = if config.is_ignored(&input.session_id, &input.cwd) {
+        "ignored (.llmlogsignore)"
+    } else if !transcript.is_file() {
+        "transcript not found"
+    } else {
+        "export"
+    };
+    let note = match merge_into {
+        Some(target) => format!("{} (merge)", target.display()),
+        None => match find_session_note(&md_dir, &session_id_safe, &uid) {
+            Some(path) => path.display().to_string(),
+            None => {
+                let started_at = parse_claude_jsonl(transcript, &ParseOptions::default())
+                    .ok()
+                    .and_then(|t| t.msgs.iter().find_map(|m| m.ts))
+                    .unwrap_or_else(Local::now);
+                let dir =
+                    dated_thread_dir(&md_dir, started_at.date_naive(), config.monthly_folders);
+                format!("{}/ (new note)", dir.display())
+            }
+        },
+    };
+
+    Ok(format!(
+        "session_id: {}\ntranscript: {}\ncwd: {}\nproject: {project}\nnote: {note}\n\
+         status: {status}\n",
+        input.session_id,
+        transcript.display(),
+        input.cwd,
+    ))
+}
+
+// Removes what earlier exports wrote for a session that is now ignored: the
+// note or exploded folder and everything exported with it, its slices, and its
+// sections in `--merge-into` targets.
+pub fn purge_session(config: &Config, base_dir: &Path, session_id: &str) -> Result<()> {
+    let session_id_safe = safe_id(session_id, "unknown-session");
+    let md_dir = base_dir.join("Threads");
+    let uid = note_identity("Claude Code", session_id);
+    let notes = find_session_notes(&md_dir, &session_id_safe, &uid);
+    purge_exported_session(config, base_dir, &notes, session_id, &session_id_safe)?;
+    config.remove_output(&md_dir.join(&session_id_safe))?;
+    for slice in session_slices(&base_dir.join("Slices"), session_id) {
+        config.remove_output(&slice)?;
+    }
+    if let Some(vault) = &config.vault {
+        remove_merged_sections(config, vault, session_id)?;
+    }
+    Ok(())
+}
+
+// The `--export-range` slices taken from the session, found by the
+// `session_id:` in their frontmatter.
+pub fn session_slices(slices_dir: &Path, session_id: &str) -> Vec<PathBuf> {
+    fs::read_dir(slices_dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("md"))
+        .filter(|p| {
+            let note = read_to_string_lossy(p).unwrap_or_default();
+            frontmatter_field(&note, "session_id").as_deref() == Some(session_id)
+        })
+        .collect()
+}
+
+// A `--merge-into` target can be any note in the vault, so every note is
+// searched for the session's section. Returns the notes that changed.
+pub fn remove_merged_sections(
+    config: &Config,
+    vault: &Path,
+    session_id: &str,
+) -> Result<Vec<PathBuf>> {
+    let begin = merge_begin(session_id);
+    let end = merge_end(session_id);
+    let mut changed = Vec::new();
+    let mut stack = vec![vault.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+            if path.is_dir() {
+                stack.push(path);
+                continue;
+            }
+            if !name.ends_with(".md") {
+                continue;
+            }
+            match read_to_string_lossy(&path) {
+                Ok(note) if find_marker(&note, &begin).is_some() => {}
+                _ => continue,
+            }
+            let stem = path.file_stem().and_then(|n| n.to_str()).unwrap_or("note");
+            let lock_path = dir.join(format!(".lock_{}", safe_name(stem)));
+            with_lock_file(&lock_path, || {
+                let existing =
+                    read_to_string_lossy(&path).context("failed to read merge target")?;
+                let updated = normalize_markdown(&remove_marked_block(&existing, &begin, &end));
+                config
+                    .write_output(&path, &updated)
+                    .context("failed to write merge target")
+            })?;
+            changed.push(path);
+        }
+    }
+    Ok(changed)
+}
+
+// Pulls `--force-rebuild` out of the arguments; it works with every mode that
+// exports, so it may appear anywhere.
+pub fn take_force_rebuild(args: &[String]) -> (bool, Vec<String>) {
+    take_flag(args, "--force-rebuild")
+}
+
+// Pulls `--merge-into <note>` out of the arguments so it can be combined with
+// either the stdin payload or the other flags.
+pub fn take_merge_into(args: &[String]) -> Result<(Option<String>, Vec<String>)> {
+    let mut target = None;
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--merge-into" {
+            target = Some(
+                args.next()
+                    .context("missing value for --merge-into")?
+                    .clone(),
+            );
+        } else if let Some(value) = arg.strip_prefix("--merge-into=") {
+            target = Some(value.to_string());
+        } else {
+            rest.push(arg.clone());
+        }
+    }
+    Ok((target, rest))
+}
+
+// `--export-range <start> <end>`: the messages stamped within the window, both
+// ends included, go into a standalone slice note; the session note is left alone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportRange {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    // Messages without a timestamp are left out unless `--include-untimed`.
+    pub include_untimed: bool,
+    // `--slice-out <path>`; `Slices/<note>_slice.md` under the project if unset.
+    pub out: Option<PathBuf>,
+}
+
+impl ExportRange {
+    pub fn contains(&self, ts: Option<DateTime<Local>>) -> bool {
+        match ts {
+            Some(t) => self.start <= t && t <= self.end,
+            None => self.include_untimed,
+        }
+    }
+}
+
+// Pulls `--export-range <start> <end>`, `--include-untimed` and
+// `--slice-out <path>` out of the arguments. Times are RFC 3339, or a
+// `2024-01-01T10:00:00` read in `zone` like transcript timestamps.
+pub fn take_export_range(
+    args: &[String],
+    zone: AssumedZone,
+) -> Result<(Option<ExportRange>, Vec<String>)> {
+    let (include_untimed, args) = take_flag(args, "--include-untimed");
+    let parse_time = |raw: &String| {
+        parse_timestamp_in(raw, zone)
+            .with_context(|| format!("invalid --export-range time: {raw:?}"))
+    };
+    let mut window = None;
+    let mut out = None;
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--export-range" {
+            let start = args.next().context("missing start for --export-range")?;
+            let end = args.next().context("missing end for --export-range")?;
+            window = Some((parse_time(start)?, parse_time(end)?));
+        } else if arg == "--slice-out" {
+            out = Some(expand_tilde(
+                args.next().context("missing value for --slice-out")?,
+            ));
+        } else if let Some(value) = arg.strip_prefix("--slice-out=") {
+            out = Some(expand_tilde(value));
+        } else {
+            rest.push(arg.clone());
+        }
+    }
+    let (start, end) = match window {
+        Some(window) => window,
+        None if include_untimed || out.is_some() => {
+            return Err(anyhow!(
+                "--include-untimed and --slice-out need --export-range"
+            ));
+        }
+        None => return Ok((None, rest)),
+    };
+    if start > end {
+        return Err(anyhow!("--export-range start is after its end"));
+    }
+    let range = ExportRange {
+        start,
+        end,
+        include_untimed,
+        out,
+    };
+    Ok((Some(range), rest))
+}
+
+// Writes the messages of the transcript inside `range` to the slice note and
+// returns its path, or `None` when no message falls inside the window.
+pub fn export_slice(
+    config: &Config,
+    base_dir: &Path,
+    project: &str,
+    session_id: &str,
+    cwd: &str,
+    transcript_path: &Path,
+    range: &ExportRange,
+) -> Result<Option<PathBuf>> {
+    let opts = ParseOptions {
+        session_id: split_session_filter(config, session_id, cwd, transcript_path),
+        ..ParseOptions::from_config(config)
+    };
+    let transcript =
+        parse_claude_jsonl(transcript_path, &opts).context("failed to parse transcript JSONL")?;
+    let mut msgs: Vec<Msg> = transcript
+        .msgs
+        .into_iter()
+        .filter(|m| range.contains(m.ts))
+        .collect();
+    if msgs.is_empty() {
+        return Ok(None);
+    }
+    if let Some(redactor) = config.redactor()? {
+        for m in &mut msgs {
+            m.text = redactor.redact(&m.text);
+        }
+    }
+
+    let session_id_safe = safe_id(session_id, "unknown-session");
+    let uid = note_identity("Claude Code", session_id);
+    let note = find_session_note(&base_dir.join("Threads"), &session_id_safe, &uid);
+    let stem = note
+        .as_deref()
+        .and_then(|p| p.file_stem())
+        .and_then(|n| n.to_str())
+        .unwrap_or(&session_id_safe);
+    let path = match &range.out {
+        Some(out) => out.clone(),
+        None => base_dir.join("Slices").join(format!("{stem}_slice.md")),
+    };
+
+    let stamp = |t: DateTime<Local>| t.to_rfc3339_opts(SecondsFormat::Secs, true);
+    let mut out = String::from("---\n");
+    for (key, value) in [
+        ("tool", "Claude Code"),
+        ("project", project),
+        ("session_id", session_id),
+        ("range_start", &stamp(range.start)),
+        ("range_end", &stamp(range.end)),
+    ] {
+        out.push_str(&format!("{key}: \"{}\"\n", yaml_quote(value)));
+    }
+    if note.is_some() {
+        out.push_str(&format!("source_note: \"[[{}]]\"\n", yaml_quote(stem)));
+    }
+    out.push_str("tags:\n  - ai-log\n  - ai-slice\n---\n\n");
+    out.push_str(&render_messages(&msgs, &RenderOptions::from_config(config)));
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("failed to create slice dir")?;
+    }
+    config
+        .write_output(&path, &normalize_markdown(&out))
+        .context("failed to write slice note")?;
+    Ok(Some(path))
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub exported: usize,
+    pub skipped: usize,
+    pub failed: usize,
+}
 
-    let cwd = payload.get("cwd").and_then(|v| v.as_str()).unwrap_or(".");
+// Exports every `*.jsonl` transcript under `dir` (e.g. `~/.claude/projects`).
+// Transcripts whose size and mtime match the processed index are skipped, and
+// a failing transcript is reported and left for the next run. The index is
+// saved after every export, so an interrupted import resumes where it stopped.
+pub fn import_transcripts(config: &Config, dir: &Path) -> Result<ImportReport> {
+    if !dir.is_dir() {
+        return Err(anyhow!("--import directory not found: {}", dir.display()));
+    }
+    let tool_dir = config.ai_dir()?.join(config.claude_dir_name());
+    fs::create_dir_all(&tool_dir).context("failed to create Claude Code dir")?;
+    let index_path = tool_dir.join(PROCESSED_INDEX_FILE);
+    let mut index = ProcessedIndex::load(&index_path);
+    let mut report = ImportReport::default();
 
-    let vault = env::var("OBSIDIAN_VAULT").context("Missing OBSIDIAN_VAULT env var")?;
-    let ai_root = env::var("OBSIDIAN_AI_ROOT").context("Missing OBSIDIAN_AI_ROOT env var")?;
+    for path in find_transcripts(dir) {
+        let stamp = match FileStamp::of(&path) {
+            Ok(stamp) => stamp,
+            Err(_) => continue,
+        };
+        if index.is_unchanged(&path, stamp) {
+            report.skipped += 1;
+            continue;
+        }
+
+        let session_id = path
+            .file_stem()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown-session");
+        let cwd = transcript_cwd(&path).unwrap_or_else(|| ".".to_string());
+        let project = config.project_name(&cwd);
+        let base_dir = tool_dir.join(&project);
+        match export_session(config, &base_dir, &project, session_id, &cwd, &path, None) {
+            Ok(_) => {
+                report.exported += 1;
+                index.record(&path, stamp);
+                index.save(&index_path)?;
+            }
+            Err(e) => {
+                report.failed += 1;
+                log::warn(|| format!("failed to import {}: {e:#}", path.display()));
+            }
+        }
+    }
+    Ok(report)
+}
 
-    let project = safe_name(&git_project_name(cwd));
+// Sorted so imports run in the same order every time.
+fn find_transcripts(dir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(e) => e,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                stack.push(path);
+            } else if path.extension().and_then(|e| e.to_str()) == Some("jsonl") {
+                found.push(path);
+            }
+        }
+    }
+    found.sort();
+    found
+}
 
-    let vault_path = PathBuf::from(&vault);
-    let base_dir = vault_path.join(&ai_root).join("Claude Code").join(&project);
+// Claude Code records the working directory on each transcript entry.
+pub fn transcript_cwd(path: &Path) -> Option<String> {
+    let file = fs::File::open(path).ok()?;
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .find_map(|line| {
+            let obj: Value = serde_json::from_str(&line).ok()?;
+            let cwd = obj.get("cwd")?.as_str()?;
+            (!cwd.trim().is_empty()).then(|| cwd.to_string())
+        })
+}
+
+// What an export needs, from the hook's JSON payload or from command-line
+// flags for manual runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionInput {
+    pub session_id: String,
+    pub transcript_path: PathBuf,
+    pub cwd: String,
+}
+
+impl SessionInput {
+    pub fn from_payload(payload: &Value) -> Result<Self> {
+        let transcript_path = payload
+            .get("transcript_path")
+            .and_then(|v| v.as_str())
+            .context("missing transcript_path in hook payload")?;
+        let transcript_path = expand_tilde(transcript_path);
+
+        let cwd = payload.get("cwd").and_then(|v| v.as_str()).unwrap_or(".");
+
+        let session_id = match payload.get("session_id").and_then(|v| v.as_str()) {
+            Some(id) if !id.trim().is_empty() => id.to_string(),
+            _ => synthesized_session_id(&transcript_path, cwd),
+        };
+
+        Ok(Self {
+            session_id,
+            transcript_path,
+            cwd: cwd.to_string(),
+        })
+    }
+
+    // `--session-id <id> --transcript <path> [--cwd <dir>]`
+    pub fn from_flags(args: &[String]) -> Result<Self> {
+        let mut session_id = None;
+        let mut transcript = None;
+        let mut cwd = None;
+        for (flag, value) in parse_cli_flags(args)? {
+            match flag.as_str() {
+                "--session-id" => session_id = Some(value),
+                "--transcript" => transcript = Some(value),
+                "--cwd" => cwd = Some(value),
+                other => return Err(anyhow!("unknown flag: {other}")),
+            }
+        }
+
+        Ok(Self {
+            session_id: session_id.context("missing --session-id")?,
+            transcript_path: expand_tilde(&transcript.context("missing --transcript")?),
+            cwd: cwd.unwrap_or_else(|| ".".to_string()),
+        })
+    }
+}
+
+// Returns the note path, or `None` when the transcript had nothing renderable.
+// With `merge_into`, the transcript goes into that note instead of the
+// session's own one.
+pub fn export_session(
+    config: &Config,
+    base_dir: &Path,
+    project: &str,
+    session_id: &str,
+    cwd: &str,
+    transcript_path: &Path,
+    merge_into: Option<&Path>,
+) -> Result<Option<PathBuf>> {
+    let formats = &config.formats;
+    let session_id_safe = safe_id(session_id, "unknown-session");
+    let uid = note_identity("Claude Code", session_id);
     let md_dir = base_dir.join("Threads");
+    if config.is_ignored(session_id, cwd) {
+        if config.ignore_purge {
+            purge_session(config, base_dir, session_id)?;
+        }
+        return Ok(None);
+    }
     fs::create_dir_all(&md_dir).context("failed to create md_dir")?;
 
     let lock_path = md_dir.join(format!(".lock_{session_id_safe}"));
-    with_lock_file(&lock_path, || {
-        let msgs = parse_claude_jsonl(transcript_path).context("failed to parse transcript JSONL")?;
+    let mut index_rows = Vec::new();
+    let note = with_lock_file(&lock_path, || {
+        let opts = ParseOptions {
+            session_id: split_session_filter(config, session_id, cwd, transcript_path),
+            ..ParseOptions::from_config(config)
+        };
+        let transcript = parse_claude_jsonl(transcript_path, &opts)
+            .context("failed to parse transcript JSONL")?;
+        if transcript.session_ids.len() > 1 && opts.session_id.is_none() {
+            log::warn(|| {
+                format!(
+                    "{} interleaves sessions {}; LLM_LOGS_SPLIT_SESSIONS=1 keeps only {session_id}",
+                    transcript_path.display(),
+                    transcript.session_ids.join(", ")
+                )
+            });
+        }
+        let mut msgs = transcript.msgs;
+        let mut sidechain = transcript.sidechain;
+        let redactor = config.redactor()?;
+        if let Some(redactor) = &redactor {
+            for m in msgs.iter_mut().chain(sidechain.iter_mut()) {
+                m.text = redactor.redact(&m.text);
+            }
+        }
+
+        // A transcript with only tool events has nothing to render: it creates
+        // no note, and an existing note is refreshed with its transcript block
+        // left as it is. Only the default layout has such a refresh.
+        let refresh_only = msgs.is_empty();
+        if refresh_only
+            && (merge_into.is_some()
+                || config.layout == Layout::Exploded
+                || config.flavor == Flavor::Logseq
+                || find_session_note(&md_dir, &session_id_safe, &uid).is_none())
+        {
+            return Ok(None);
+        }
+
+        if msgs.len() < config.min_messages && !note_exists(&md_dir, &session_id_safe, &uid) {
+            return Ok(None);
+        }
+
+        if config.sqlite {
+            index_rows = msgs
+                .iter()
+                .map(|m| IndexedMessage {
+                    role: m.role.to_string(),
+                    ts: m.ts.map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                    text: m.text.clone(),
+                })
+                .collect();
+        }
+
         let started_at = msgs.iter().find_map(|m| m.ts);
-        let first_user_msg = msgs.iter().find(|m| m.role == "user").map(|m| m.text.as_str());
+        let created = resolve_created(msgs.iter().map(|m| m.ts));
+        let user_msgs = msgs
+            .iter()
+            .filter(|m| m.role == "user")
+            .map(|m| m.text.as_str());
+        let title_msg = config.title_source.pick(user_msgs).map(str::to_string);
 
-        let md_path =
-            find_or_create_md_path(&md_dir, &session_id_safe, first_user_msg, started_at)
-                .context("failed to find or create md path")?;
+        if config.copy_refs {
+            let attachments = base_dir.join("Attachments");
+            for m in msgs.iter_mut().filter(|m| m.role == "user") {
+                m.text = copy_path_refs(&m.text, Path::new(cwd), &attachments);
+            }
+        }
+
+        let template = read_frontmatter_template(base_dir);
+        let project_link = config.vault_link(base_dir);
+        let note_cwd = (!config.omit_cwd).then_some(cwd);
+        let with_project_path = |note: &str| {
+            upsert_project_path_field(note, config.project_path, note_cwd, project, &project_link)
+        };
+        if let Some(target) = merge_into {
+            let skeleton = build_claude_note_skeleton(
+                project,
+                session_id,
+                note_cwd,
+                created,
+                transcript.tool_call_count,
+            );
+            let skeleton = upsert_model_field(&skeleton, transcript.model.as_deref());
+            let skeleton = with_project_path(&skeleton);
+            let skeleton = merge_frontmatter_template(&skeleton, &template);
+            let render = RenderOptions::from_config(config);
+            let mut body = build_transcript_body(
+                &Local::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+                &config.source_label(transcript_path),
+                &msgs,
+                &render,
+            );
+            if !sidechain.is_empty() {
+                body.push_str(&build_sidechain_section(&sidechain, &render));
+            }
+            merge_into_note(config, target, &skeleton, session_id, &body)?;
+            return Ok(Some(target.to_path_buf()));
+        }
+
+        if config.layout == Layout::Exploded {
+            let skeleton = build_claude_note_skeleton(
+                project,
+                session_id,
+                note_cwd,
+                created,
+                transcript.tool_call_count,
+            );
+            let skeleton = upsert_model_field(&skeleton, transcript.model.as_deref());
+            let skeleton = with_project_path(&skeleton);
+            let skeleton = merge_frontmatter_template(&skeleton, &template);
+            let dir = md_dir.join(&session_id_safe);
+            // Message files keep the default spacing whatever the note uses.
+            let opts = RenderOptions {
+                time_fmt: formats.time.clone(),
+                missing_time: config.missing_time.clone(),
+                ..RenderOptions::default()
+            };
+            let index = write_exploded_session(config, &dir, &skeleton, &msgs, &sidechain, &opts)?;
+            return Ok(Some(index));
+        }
+
+        if config.flavor == Flavor::Logseq {
+            let md_path = find_or_create_md_path(
+                &md_dir,
+                &session_id_safe,
+                &uid,
+                title_msg.as_deref(),
+                started_at,
+                config,
+            )
+            .context("failed to find or create md path")?;
+            let renderer = LogseqRenderer {
+                time_fmt: formats.time.clone(),
+                properties: logseq_properties(
+                    project,
+                    session_id,
+                    note_cwd,
+                    created,
+                    transcript.model.as_deref(),
+                ),
+            };
+            let title = md_path
+                .file_stem()
+                .and_then(|n| n.to_str())
+                .unwrap_or(session_id);
+            let existing = if md_path.exists() {
+                read_to_string_lossy(&md_path).context("failed to read existing Logseq page")?
+            } else {
+                String::new()
+            };
+            let page = update_logseq_page(
+                &existing,
+                &renderer.header(title),
+                &renderer.blocks(&msgs, &sidechain),
+            );
+            config
+                .write_output(&md_path, &page)
+                .context("failed to write Logseq page")?;
+            return Ok(Some(md_path));
+        }
+
+        let md_path = find_or_create_md_path(
+            &md_dir,
+            &session_id_safe,
+            &uid,
+            title_msg.as_deref(),
+            started_at,
+            config,
+        )
+        .context("failed to find or create md path")?;
 
+        let skeleton = build_claude_note_skeleton(
+            project,
+            session_id,
+            note_cwd,
+            created,
+            transcript.tool_call_count,
+        );
         let existing = if md_path.exists() {
-            fs::read_to_string(&md_path).context("failed to read existing md note")?
+            let note = read_to_string_lossy(&md_path).context("failed to read existing md note")?;
+            if config.force_rebuild && !refresh_only {
+                rebuild_note(&skeleton, &note)
+            } else {
+                note
+            }
         } else {
-            build_claude_note_skeleton(&project, &session_id, cwd, started_at)
+            skeleton
+        };
+        let (existing, _) = repair_markers(&existing);
+        let existing = upsert_frontmatter_field(
+            &existing,
+            "tool_call_count",
+            &transcript.tool_call_count.to_string(),
+        );
+        let existing = match transcript.usage.total() {
+            0 => existing,
+            total => upsert_frontmatter_field(&existing, "total_tokens", &total.to_string()),
         };
+        let existing = upsert_frontmatter_field(&existing, "uid", &format!("\"{uid}\""));
+        let existing = match note_cwd {
+            Some(_) => existing,
+            None => remove_cwd_fields(&existing),
+        };
+        let existing = upsert_model_field(&existing, transcript.model.as_deref());
+        let existing = with_project_path(&existing);
+        let title = note_title(&md_path, &session_id_safe, formats);
+        let existing = upsert_aliases_field(&existing, title.as_deref());
+        let existing = merge_frontmatter_template(&existing, &template);
+
+        let mut updated = existing;
+        if !refresh_only {
+            let exported = Local::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+            let source_rel = config.source_label(transcript_path);
+
+            let render = RenderOptions::from_config(config);
+            let new_block = build_transcript_block(&exported, &source_rel, &msgs, &render);
+            updated = upsert_block(&updated, &new_block);
+            // The block goes once the session has no sub-agent messages left or
+            // they are merged into the thread or skipped.
+            updated = if sidechain.is_empty() {
+                remove_marked_block(&updated, SIDECHAIN_BEGIN, SIDECHAIN_END)
+            } else {
+                upsert_sidechain_block(&updated, &build_sidechain_section(&sidechain, &render))
+            };
+            // With `inline_raw` off, a block left from when it was on is dropped.
+            let raw = if config.inline_raw {
+                read_inline_raw(transcript_path, config.inline_raw_limit())?
+            } else {
+                None
+            };
+            let raw = raw.map(|raw| match &redactor {
+                Some(redactor) => redactor.redact(&raw),
+                None => raw,
+            });
+            updated = upsert_inline_raw_block(&updated, raw.as_deref());
 
-        let exported = Local::now().to_rfc3339_opts(SecondsFormat::Secs, true);
-        let source_rel = transcript_path.to_string();
+            // On LLM failure the previous summary, if any, is kept.
+            if config.summary {
+                let summary = generate_summary_with_llm(&config.llm, &summary_input(&msgs));
+                if let Some(summary) = summary {
+                    updated = upsert_summary_block(&updated, &summary, BEGIN);
+                }
+            }
+        }
 
-        let new_block = build_transcript_block(&exported, &source_rel, &msgs);
-        let updated = upsert_block(&existing, &new_block);
+        if config.footer_nav {
+            let footer = build_footer_block(project, &config.vault_link(base_dir));
+            updated = upsert_footer_block(&updated, &footer);
+        }
 
-        fs::write(&md_path, updated).context("failed to write md note")?;
-        Ok(())
+        config
+            .write_output(&md_path, &normalize_markdown(&updated))
+            .context("failed to write md note")?;
+        if refresh_only {
+            return Ok(Some(md_path));
+        }
+
+        if let Some(renderer) = renderer_for_format(&config.format, &formats.time) {
+            let title = md_path
+                .file_stem()
+                .and_then(|n| n.to_str())
+                .unwrap_or(session_id);
+            let out_path = md_path.with_extension(renderer.extension());
+            config.write_output(&out_path, &renderer.render(title, &msgs, &sidechain))?;
+        }
+        if config.format.trim().eq_ignore_ascii_case("jsonl") {
+            let jsonl_path = md_path.with_extension("messages.jsonl");
+            append_message_lines(config, &jsonl_path, &msgs)?;
+        }
+        if config.emit_csv {
+            let csv_path = md_path.with_extension("csv");
+            config.write_output(&csv_path, &message_csv(&msgs))?;
+        }
+
+        if config.result_note {
+            if let Some(last) = last_assistant_message(&msgs) {
+                let frontmatter = [
+                    ("tool", "Claude Code"),
+                    ("project", project),
+                    ("session_id", session_id),
+                ];
+                write_result_note(
+                    config,
+                    base_dir,
+                    &md_path,
+                    &frontmatter,
+                    &last.text,
+                    config.result_max_chars,
+                )?;
+            }
+        }
+
+        if config.daily_digest {
+            let today = Local::now().date_naive();
+            let block = build_digest_block(session_id, &md_path, &msgs, today, &formats.time);
+            if !block.is_empty() {
+                let label = today.format(formats.filename_date()).to_string();
+                let daily = base_dir.join("Daily");
+                let first = msgs
+                    .iter()
+                    .filter_map(|m| m.ts)
+                    .filter(|t| t.date_naive() == today)
+                    .min();
+                append_to_daily_digest(config, &daily, &label, &session_id_safe, first, &block)
+                    .context("failed to update daily digest")?;
+            }
+        }
+        Ok(Some(md_path))
     })?;
-    Ok(())
+
+    if let Some(note) = &note {
+        config.refresh_overview(base_dir, note);
+    }
+    // The index is updated once the note is written and is only a convenience,
+    // so a failure here is a warning rather than a failed export.
+    // An empty transcript leaves the session's rows as they are.
+    if config.sqlite && note.is_some() && !index_rows.is_empty() {
+        let indexed = config.ai_dir().and_then(|ai_dir| {
+            index_session(
+                &index_path(&ai_dir),
+                "Claude Code",
+                project,
+                session_id,
+                &index_rows,
+            )
+        });
+        if let Err(e) = indexed {
+            log::warn(|| format!("failed to update index: {e:#}"));
+        }
+    }
+    Ok(note)
 }
 
-pub fn build_claude_note_skeleton(
+fn note_exists(md_dir: &Path, session_id_safe: &str, uid: &str) -> bool {
+    find_session_note(md_dir, session_id_safe, uid).is_some()
+        || md_dir
+            .join(session_id_safe)
+            .join(EXPLODED_INDEX_FILE)
+            .exists()
+}
+
+// The Logseq counterpart of `build_claude_note_skeleton`'s frontmatter.
+pub fn logseq_properties(
     project: &str,
     session_id: &str,
-    cwd: &str,
-    created: Option<DateTime<Local>>,
+    cwd: Option<&str>,
+    created: DateTime<Local>,
+    model: Option<&str>,
+) -> Vec<(&'static str, String)> {
+    let mut properties = vec![
+        ("tool", "Claude Code".to_string()),
+        ("project", project.to_string()),
+        ("session_id", session_id.to_string()),
+    ];
+    if let Some(cwd) = cwd {
+        properties.push(("cwd", cwd.to_string()));
+    }
+    properties.push((
+        "created",
+        created.to_rfc3339_opts(SecondsFormat::Secs, true),
+    ));
+    if let Some(model) = model.filter(|m| !m.trim().is_empty()) {
+        properties.push(("model", model.to_string()));
+    }
+    // A tag that isn't a single word needs Logseq's page-reference brackets.
+    let project_tag = if project.contains([',', ' ']) {
+        format!("[[{project}]]")
+    } else {
+        project.to_string()
+    };
+    properties.push(("tags", format!("ai-log, claude, {project_tag}")));
+    properties
+}
+
+pub const SIDECHAIN_BEGIN: &str = "<!-- BEGIN AUTO SUBAGENT -->";
+pub const SIDECHAIN_END: &str = "<!-- END AUTO SUBAGENT -->";
+
+pub fn build_sidechain_section(msgs: &[Msg], opts: &RenderOptions) -> String {
+    let msgs = with_code_callouts(msgs, opts);
+    format!("## Sub-agent\n\n{}", render_messages(&msgs, opts))
+}
+
+// The sub-agent block is its own marked block so the transcript block keeps
+// its shape; a new one goes right after the transcript.
+pub fn upsert_sidechain_block(note: &str, section: &str) -> String {
+    let block = format!("{SIDECHAIN_BEGIN}\n{}\n{SIDECHAIN_END}", section.trim_end());
+    if find_marker(note, SIDECHAIN_BEGIN).is_none() {
+        if let Some(e) = find_marker(note, END) {
+            let (pre, post) = note.split_at(e + END.len());
+            return format!("{pre}\n\n{block}{post}");
+        }
+    }
+    upsert_marked_block(note, SIDECHAIN_BEGIN, SIDECHAIN_END, &block)
+}
+
+pub const RAW_BEGIN: &str = "<!-- BEGIN AUTO RAW -->";
+pub const RAW_END: &str = "<!-- END AUTO RAW -->";
+
+// The transcript as it is on disk, or `None` once it is larger than `limit`
+// bytes and the note only points at it.
+pub fn read_inline_raw(transcript_path: &Path, limit: u64) -> Result<Option<String>> {
+    let len = fs::metadata(transcript_path)
+        .context("failed to stat transcript")?
+        .len();
+    if len > limit {
+        return Ok(None);
+    }
+    let raw = read_to_string_lossy(transcript_path).context("failed to read transcript")?;
+    Ok(Some(raw))
+}
+
+// The raw JSONL in a collapsed callout at the bottom of the note. `None`
+// drops a block left from when the transcript was still small enough.
+pub fn upsert_inline_raw_block(note: &str, raw: Option<&str>) -> String {
+    let raw = match raw {
+        Some(raw) => raw,
+        None => return remove_marked_block(note, RAW_BEGIN, RAW_END),
+    };
+    let mut callout = String::from("[!abstract]- Raw transcript\n```jsonl\n");
+    for line in raw.lines().filter(|l| !l.trim().is_empty()) {
+        callout.push_str(line);
+        callout.push('\n');
+    }
+    callout.push_str("```");
+    let block = format!("{RAW_BEGIN}\n{}{RAW_END}", quote_lines(&callout));
+    upsert_marked_block(note, RAW_BEGIN, RAW_END, &block)
+}
+
+pub fn merge_begin(session_id: &str) -> String {
+    format!("<!-- BEGIN AUTO TRANSCRIPT session:{session_id} -->")
+}
+
+pub fn merge_end(session_id: &str) -> String {
+    format!("<!-- END AUTO TRANSCRIPT session:{session_id} -->")
+}
+
+// A merge target can collect several sessions, so each one owns a section
+// keyed by its id; the note's own text around the sections is left alone.
+// `LLM_LOGS_OMIT_CWD` strips the cwd fields from an existing target as well,
+// whichever session put them there.
+pub fn merge_into_note(
+    config: &Config,
+    target: &Path,
+    skeleton: &str,
+    session_id: &str,
+    body: &str,
+) -> Result<()> {
+    let dir = target.parent().context("merge target has no parent dir")?;
+    fs::create_dir_all(dir).context("failed to create merge target dir")?;
+    let stem = target
+        .file_stem()
+        .and_then(|n| n.to_str())
+        .unwrap_or("note");
+    let lock_path = dir.join(format!(".lock_{}", safe_name(stem)));
+
+    with_lock_file(&lock_path, || {
+        let existing = if target.exists() {
+            read_to_string_lossy(target).context("failed to read merge target")?
+        } else {
+            skeleton.to_string()
+        };
+        let merged = upsert_merged_section(&existing, session_id, body);
+        let merged = if config.omit_cwd {
+            remove_cwd_fields(&merged)
+        } else {
+            merged
+        };
+        let updated = normalize_markdown(&merged);
+        if updated != existing {
+            config
+                .write_output(target, &updated)
+                .context("failed to write merge target")?;
+        }
+        Ok(())
+    })
+}
+
+pub fn upsert_merged_section(existing: &str, session_id: &str, body: &str) -> String {
+    let begin = merge_begin(session_id);
+    let end = merge_end(session_id);
+    let section = format!("{begin}\n{}\n{end}\n", body.trim());
+
+    match (find_marker(existing, &begin), find_marker(existing, &end)) {
+        (Some(bi), Some(ei)) if ei >= bi => {
+            let pre = &existing[..bi];
+            let post = existing[ei + end.len()..].trim_start_matches('\n');
+            format!("{pre}{section}{post}")
+        }
+        _ => format!("{}\n\n{section}", existing.trim_end()),
+    }
+}
+
+pub fn summary_input(msgs: &[Msg]) -> String {
+    msgs.iter()
+        .map(|m| {
+            let who = if m.role == "user" {
+                "User"
+            } else {
+                "Assistant"
+            };
+            format!("{who}: {}", m.text.trim())
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+pub fn last_assistant_message(msgs: &[Msg]) -> Option<&Msg> {
+    msgs.iter()
+        .rev()
+        .find(|m| m.role == "assistant" && !m.text.trim().is_empty())
+}
+
+// Only messages stamped on `date` go into that day's digest; an empty string
+// means the session has nothing to contribute.
+pub fn build_digest_block(
+    session_id: &str,
+    md_path: &Path,
+    msgs: &[Msg],
+    date: NaiveDate,
+    time_fmt: &str,
 ) -> String {
-    let created = created.unwrap_or_else(Local::now);
-    let created = created.to_rfc3339_opts(SecondsFormat::Secs, true);
-
-    let project_q = yaml_quote(project);
-    let session_q = yaml_quote(session_id);
-    let cwd_q = yaml_quote(cwd);
-
-    format!(
-        r#"---
-tool: "Claude Code"
-project: "{project_q}"
-session_id: "{session_q}"
-cwd: "{cwd_q}"
-created: "{created}"
-tags:
-  - ai-log
-  - claude
-  - {project_q}
----
-
-"#
-    )
-}
-
-pub fn build_transcript_block(exported: &str, source: &str, msgs: &[Msg]) -> String {
-    let mut out = String::new();
-    out.push_str(BEGIN);
-    out.push('\n');
-    out.push_str("## Transcript (auto)\n");
-    out.push_str(&format!("- Exported: {exported}\n"));
-    out.push_str(&format!("- Source transcript: {source}\n\n"));
-
-    for m in msgs {
-        let ts = m
-            .ts
-            .map(|t| t.format("%Y-%m-%d %H:%M:%S %z").to_string())
-            .unwrap_or_default();
-        let who = if m.role == "user" { "User" } else { "Assistant" };
-        out.push_str(&format!("### {ts} {who}\n"));
-        out.push_str(m.text.trim_end());
-        out.push_str("\n\n");
-    }
-
-    out.push_str(END);
-    out.push('\n');
+    let todays: Vec<Msg> = msgs
+        .iter()
+        .filter(|m| m.ts.is_some_and(|t| t.date_naive() == date))
+        .cloned()
+        .collect();
+    if todays.is_empty() {
+        return String::new();
+    }
+
+    let note = md_path
+        .file_stem()
+        .and_then(|n| n.to_str())
+        .unwrap_or(session_id);
+
+    let mut out = format!("## Session {session_id} ([[{note}]])\n\n");
+    let opts = RenderOptions {
+        time_fmt: time_fmt.to_string(),
+        ..RenderOptions::default()
+    };
+    out.push_str(&render_messages(&todays, &opts));
     out
 }
 
+// `--force-rebuild`: a fresh skeleton's frontmatter replaces the note's, and the
+// transcript block is emptied so nothing of the old rendering survives. What
+// the user wrote around the block is kept where it was.
+pub fn rebuild_note(skeleton: &str, existing: &str) -> String {
+    let body = note_body(existing);
+    let body = match (find_marker(body, BEGIN), find_marker(body, END)) {
+        (Some(b), Some(e)) if e >= b => format!("{}{BEGIN}\n{}", &body[..b], &body[e..]),
+        _ => body.to_string(),
+    };
+    format!("{skeleton}{}", body.trim_start_matches('\n'))
+}
+
+pub fn repair_markers(md: &str) -> (String, bool) {
+    repair_markers_with(md, BEGIN, END)
+}
+
+// The block always starts on its own line and ends with exactly one newline
+// before whatever follows END, whatever whitespace the caller's block carries.
 pub fn upsert_block(existing: &str, new_block: &str) -> String {
-    let b = existing.find(BEGIN);
-    let e = existing.find(END);
+    let block = new_block.trim();
+    let b = find_marker(existing, BEGIN);
+    let e = find_marker(existing, END);
 
     match (b, e) {
         (Some(bi), Some(ei)) if ei >= bi => {
             let pre = &existing[..bi];
             let post = &existing[ei + END.len()..];
-            format!("{pre}{new_block}{post}")
+            let pre_sep = if pre.is_empty() || pre.ends_with('\n') {
+                ""
+            } else {
+                "\n"
+            };
+            let post_sep = if post.starts_with(['\n', '\r']) {
+                ""
+            } else {
+                "\n"
+            };
+            format!("{pre}{pre_sep}{block}{post_sep}{post}")
         }
         _ => {
             let mut s = existing.trim_end().to_string();
             s.push_str("\n\n");
-            s.push_str(new_block);
+            s.push_str(block);
+            s.push('\n');
             s
         }
     }
 }
 
-fn parse_claude_jsonl(path: &str) -> Result<Vec<Msg>> {
-    let f = fs::File::open(path).with_context(|| format!("failed to open transcript: {path}"))?;
+fn parse_claude_jsonl(path: &Path, opts: &ParseOptions) -> Result<Transcript> {
+    let f = fs::File::open(path)
+        .with_context(|| format!("failed to open transcript: {}", path.display()))?;
     let reader = BufReader::new(f);
 
-    let mut msgs = Vec::new();
+    let mut msgs: Vec<Msg> = Vec::new();
+    let mut sidechain: Vec<Msg> = Vec::new();
+    let mut tool_call_count = 0;
+    let mut model = None;
+    let mut resumed = false;
+    let mut session_ids: Vec<String> = Vec::new();
+    let mut usage = Usage::default();
+    let mut usage_ids: HashSet<String> = HashSet::new();
+    // Usage not yet attached to a message, per target (main, sidechain).
+    let mut pending = [None::<Usage>; 2];
 
-    for line in reader.lines() {
+    // Split on raw bytes so a line with invalid UTF-8 is recovered lossily
+    // instead of being dropped.
+    for line in reader.split(b'\n') {
         let line = match line {
-            Ok(l) => l,
+            Ok(l) => String::from_utf8_lossy(&l).into_owned(),
             Err(_) => continue,
         };
         let line = line.trim();
@@ -181,89 +1289,249 @@ fn parse_claude_jsonl(path: &str) -> Result<Vec<Msg>> {
             Err(_) => continue,
         };
 
+        if let Some(id) = obj.get("sessionId").and_then(|v| v.as_str()) {
+            if !session_ids.iter().any(|s| s == id) {
+                session_ids.push(id.to_string());
+            }
+            if opts
+                .session_id
+                .as_deref()
+                .is_some_and(|target| target != id)
+            {
+                continue;
+            }
+        }
+
         let typ = obj.get("type").and_then(|v| v.as_str()).unwrap_or("");
-        let ts = obj
-            .get("timestamp")
-            .and_then(|v| v.as_str())
-            .and_then(parse_rfc3339_local);
+        let ts = extract_timestamp(&obj, opts.zone);
 
-        if typ == "user" || typ == "assistant" {
-            let role = if typ == "user" { "user" } else { "assistant" };
+        if is_resume_boundary(&obj) {
+            resumed = true;
+            continue;
+        }
 
+        if let Some(role) = canonical_role(typ, &opts.role_aliases) {
             let content = obj
                 .get("message")
                 .and_then(|m| m.get("content"))
                 .unwrap_or(&Value::Null);
 
-            if let Some(text) = extract_text(content) {
+            if role == "user" && opts.skip_meta && is_meta_entry(&obj) {
+                continue;
+            }
+
+            let is_sidechain = obj.get("isSidechain").and_then(|v| v.as_bool()) == Some(true);
+            // 0 for the main conversation, 1 for the sub-agent section; skipped
+            // sub-agent entries still count towards the session's totals.
+            let slot = match (is_sidechain, opts.sidechain) {
+                (true, SidechainMode::Skip) => None,
+                (true, SidechainMode::Section) => Some(1),
+                _ => Some(0),
+            };
+            if role == "assistant" {
+                tool_call_count += count_tool_uses(content);
+                if let Some(m) = entry_model(&obj).filter(|_| !is_sidechain) {
+                    model = Some(m.to_string());
+                }
+                if let Some(entry) = entry_usage(&obj, &mut usage_ids) {
+                    usage = usage + entry;
+                    if let Some(slot) = slot {
+                        pending[slot] = Some(pending[slot].unwrap_or_default() + entry);
+                    }
+                }
+            }
+            let slot = match slot {
+                Some(slot) => slot,
+                None => continue,
+            };
+            let target = if slot == 1 { &mut sidechain } else { &mut msgs };
+            let pending = &mut pending[slot];
+
+            if let Some(text) = opts.text.extract(content) {
                 let text = text.trim().to_string();
-                if !text.is_empty() {
-                    msgs.push(Msg { role, text, ts });
+                let hidden = role == "user" && opts.hide_slash && is_slash_command(&text);
+                if text.is_empty() || hidden {
+                    continue;
+                }
+                let stitch = opts.coalesce_assistant && role == "assistant" && !resumed;
+                let usage = if role == "assistant" {
+                    pending.take()
+                } else {
+                    None
+                };
+                match target.last_mut() {
+                    Some(prev) if stitch && prev.role == role => {
+                        prev.text.push_str("\n\n");
+                        prev.text.push_str(&text);
+                        prev.usage = match (prev.usage, usage) {
+                            (Some(a), Some(b)) => Some(a + b),
+                            (a, b) => a.or(b),
+                        };
+                    }
+                    _ => target.push(Msg {
+                        role,
+                        text,
+                        ts,
+                        resumed,
+                        usage,
+                    }),
                 }
+                resumed = false;
             }
         }
     }
 
-    Ok(msgs)
+    Ok(Transcript {
+        msgs,
+        tool_call_count,
+        model,
+        sidechain,
+        usage,
+        session_ids,
+    })
 }
 
-pub fn extract_text(v: &Value) -> Option<String> {
-    match v {
-        Value::String(s) => Some(s.clone()),
-        Value::Array(arr) => {
-            let mut parts: Vec<String> = Vec::new();
-            for item in arr {
-                if item.get("type").and_then(|x| x.as_str()) == Some("text") {
-                    if let Some(t) = item.get("text").and_then(|x| x.as_str()) {
-                        let t = t.trim();
-                        if !t.is_empty() {
-                            parts.push(t.to_string());
-                        }
-                    }
-                }
-            }
-            if parts.is_empty() {
-                None
-            } else {
-                Some(parts.join("\n"))
-            }
+// The session `LLM_LOGS_SPLIT_SESSIONS` keeps. An id synthesized for a payload
+// without one never appears on a line, so filtering on it would drop them all.
+fn split_session_filter(
+    config: &Config,
+    session_id: &str,
+    cwd: &str,
+    transcript_path: &Path,
+) -> Option<String> {
+    let synthesized = || session_id == synthesized_session_id(transcript_path, cwd);
+    (config.split_sessions && !synthesized()).then(|| session_id.to_string())
+}
+
+// Claude Code writes `summary` entries when a session is resumed and a
+// `compact_boundary` system entry when the conversation is compacted.
+pub fn is_resume_boundary(obj: &Value) -> bool {
+    match obj.get("type").and_then(|v| v.as_str()) {
+        Some("summary") => true,
+        Some("system") => obj.get("subtype").and_then(|v| v.as_str()) == Some("compact_boundary"),
+        _ => false,
+    }
+}
+
+// Claude Code marks entries it fabricated (e.g. after an interrupt) with the
+// `<synthetic>` model, which says nothing about the session's model.
+pub fn entry_model(obj: &Value) -> Option<&str> {
+    obj.get("message")
+        .and_then(|m| m.get("model"))
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|m| !m.is_empty() && *m != "<synthetic>")
+}
+
+// The token counts of an assistant entry's `message.usage`, with cached prompt
+// tokens counted as input. Claude Code repeats a response's usage on every
+// entry split from it, so an id already in `seen` yields `None`.
+pub fn entry_usage(obj: &Value, seen: &mut HashSet<String>) -> Option<Usage> {
+    let message = obj.get("message")?;
+    let usage = message.get("usage")?;
+    let count = |key: &str| usage.get(key).and_then(Value::as_u64).unwrap_or(0);
+    if let Some(id) = message.get("id").and_then(|v| v.as_str()) {
+        if !seen.insert(id.to_string()) {
+            return None;
         }
-        _ => None,
     }
+    Some(Usage {
+        input: count("input_tokens")
+            + count("cache_creation_input_tokens")
+            + count("cache_read_input_tokens"),
+        output: count("output_tokens"),
+    })
 }
 
-fn parse_rfc3339_local(s: &str) -> Option<DateTime<Local>> {
-    DateTime::parse_from_rfc3339(s)
-        .ok()
-        .map(|dt| dt.with_timezone(&Local))
+// Top-level flags Claude Code sets on entries it injected itself, paired with
+// the value that marks the entry as synthetic.
+const META_FLAGS: &[(&str, bool)] = &[
+    ("isMeta", true),
+    ("isVisibleInTranscript", false),
+    ("isCompactSummary", true),
+];
+
+// Text prefixes of user messages produced by reminders, hooks and local
+// commands rather than typed by the user.
+const META_TEXT_PREFIXES: &[&str] = &[
+    "<system-reminder>",
+    "<user-prompt-submit-hook>",
+    "<local-command-stdout>",
+    "<local-command-stderr>",
+    "Caveat: The messages below were generated by the user while running local commands.",
+];
+
+pub fn is_meta_entry(obj: &Value) -> bool {
+    let flagged = META_FLAGS
+        .iter()
+        .any(|(key, meta)| obj.get(*key).and_then(|v| v.as_bool()) == Some(*meta));
+    if flagged {
+        return true;
+    }
+
+    let content = obj
+        .get("message")
+        .and_then(|m| m.get("content"))
+        .unwrap_or(&Value::Null);
+    extract_text(content).is_some_and(|text| {
+        let text = text.trim_start();
+        META_TEXT_PREFIXES.iter().any(|p| text.starts_with(p))
+    })
+}
+
+pub fn count_tool_uses(content: &Value) -> usize {
+    match content {
+        Value::Array(arr) => arr
+            .iter()
+            .filter(|item| item.get("type").and_then(|x| x.as_str()) == Some("tool_use"))
+            .count(),
+        Value::Object(_) => {
+            usize::from(content.get("type").and_then(|x| x.as_str()) == Some("tool_use"))
+        }
+        _ => 0,
+    }
 }
 
 fn find_or_create_md_path(
     md_dir: &Path,
     session_id: &str,
-    first_user_msg: Option<&str>,
+    uid: &str,
+    title_msg: Option<&str>,
     started_at: Option<DateTime<Local>>,
+    config: &Config,
 ) -> Result<PathBuf> {
     if let Some(existing) = find_md_file_containing_id(md_dir, session_id) {
-        if let Some(migrated) = maybe_migrate_legacy_md_path(md_dir, &existing) {
+        if let Some(migrated) = maybe_migrate_legacy_md_path(config, md_dir, &existing) {
             return Ok(migrated);
         }
         return Ok(existing);
     }
+    // A note renamed away from its session id is still found by its `uid:`.
+    if let Some(renamed) = find_md_file_by_uid(md_dir, uid) {
+        return Ok(renamed);
+    }
 
     let started_at = started_at.unwrap_or_else(Local::now);
-    let day_dir = md_dir
-        .join(started_at.format("%Y").to_string())
-        .join(started_at.format("%m").to_string())
-        .join(started_at.format("%d").to_string());
+    let day_dir = dated_thread_dir(md_dir, started_at.date_naive(), config.monthly_folders);
     fs::create_dir_all(&day_dir).context("failed to create dated Threads dir")?;
 
-    let title = generate_title(first_user_msg);
-    let filename = format!("{title}_{session_id}.md");
+    let title = generate_title(title_msg, config.title_llm());
+    let budget = filename_budget(&day_dir, MAX_PATH_LEN);
+    let filename = note_filename_within(
+        &config.formats,
+        started_at.date_naive(),
+        &title,
+        session_id,
+        budget,
+    );
     Ok(day_dir.join(filename))
 }
 
-fn maybe_migrate_legacy_md_path(md_dir: &Path, existing: &Path) -> Option<PathBuf> {
+fn maybe_migrate_legacy_md_path(
+    config: &Config,
+    md_dir: &Path,
+    existing: &Path,
+) -> Option<PathBuf> {
     if existing.parent()? != md_dir {
         return None;
     }
@@ -279,7 +1547,7 @@ fn maybe_migrate_legacy_md_path(md_dir: &Path, existing: &Path) -> Option<PathBu
     }
 
     fs::create_dir_all(&target_dir).ok()?;
-    fs::rename(existing, &target_path).ok()?;
+    config.move_output(existing, &target_path).ok()?;
     Some(target_path)
 }
 
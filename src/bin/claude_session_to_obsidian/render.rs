@@ -0,0 +1,445 @@
+use ai_log_exporter::transcript::{Msg, BEGIN, END};
+use ai_log_exporter::{
+    closes_fence, elide_data_uris, fence_delimiter, find_marker, marked_block,
+    read_to_string_lossy, upsert_marked_block, Config, TOOL_EVENTS_BEGIN, TOOL_EVENTS_END,
+};
+use anyhow::{Context, Result};
+use chrono::SecondsFormat;
+use serde_json::{json, Value};
+use std::{borrow::Cow, path::Path};
+
+// `sidechain` holds the sub-agent messages kept apart in
+// `LLM_LOGS_SIDECHAIN=section`; they follow the thread under their own heading.
+pub trait Renderer {
+    fn extension(&self) -> &'static str;
+    fn render(&self, title: &str, msgs: &[Msg], sidechain: &[Msg]) -> String;
+}
+
+// `LLM_LOGS_FORMAT` selects an extra rendering written next to the Markdown
+// note. Markdown (the default) has no extra renderer.
+pub fn renderer_for_format(format: &str, time_fmt: &str) -> Option<Box<dyn Renderer>> {
+    match format.trim().to_ascii_lowercase().as_str() {
+        "html" => Some(Box::new(HtmlRenderer {
+            time_fmt: time_fmt.to_string(),
+        })),
+        "quote" => Some(Box::new(QuoteRenderer)),
+        _ => None,
+    }
+}
+
+// `LLM_LOGS_EMIT_CSV=1` writes `<note>.csv` next to the note, one row per
+// message, for looking at answer lengths in a spreadsheet. It is rewritten in
+// full on every export.
+pub const CSV_HEADER: &str = "index,role,timestamp,char_count,word_count";
+
+pub fn message_csv(msgs: &[Msg]) -> String {
+    let mut out = format!("{CSV_HEADER}\n");
+    for (i, m) in msgs.iter().enumerate() {
+        let ts =
+            m.ts.map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))
+                .unwrap_or_default();
+        let row = [
+            (i + 1).to_string(),
+            m.role.to_string(),
+            ts,
+            m.text.chars().count().to_string(),
+            m.text.split_whitespace().count().to_string(),
+        ];
+        let row: Vec<Cow<str>> = row.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+// RFC 4180 quoting: fields with a comma, quote or line break are wrapped in
+// quotes, and quotes inside are doubled.
+pub fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+// `LLM_LOGS_FORMAT=jsonl` keeps a `<note>.messages.jsonl` sidecar with one
+// `{role, ts, text}` object per message. Unlike the renderers it only grows:
+// lines already written are counted and kept, so re-exporting a session adds
+// just its new messages. A torn last line from an interrupted write is not a
+// message and is written again. Returns how many lines were added.
+pub fn append_message_lines(config: &Config, path: &Path, msgs: &[Msg]) -> Result<usize> {
+    let existing = if path.exists() {
+        read_to_string_lossy(path).with_context(|| format!("failed to read {}", path.display()))?
+    } else {
+        String::new()
+    };
+    let mut out = String::new();
+    for line in existing.split_inclusive('\n') {
+        if line.ends_with('\n') && serde_json::from_str::<Value>(line).is_ok() {
+            out.push_str(line);
+        }
+    }
+    let written = out.lines().count();
+    let new = msgs.get(written..).unwrap_or_default();
+    if new.is_empty() && out == existing {
+        return Ok(0);
+    }
+
+    for m in new {
+        let line = json!({
+            "role": m.role,
+            "ts": m.ts.map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            "text": m.text,
+        });
+        out.push_str(&line.to_string());
+        out.push('\n');
+    }
+    config.write_output(path, &out)?;
+    Ok(new.len())
+}
+
+pub struct HtmlRenderer {
+    pub time_fmt: String,
+}
+
+const HTML_STYLE: &str = r#"body { font-family: -apple-system, "Segoe UI", sans-serif; max-width: 52rem; margin: 2rem auto; padding: 0 1rem; line-height: 1.6; color: #222; }
+section.message { border-radius: 8px; padding: 0.5rem 1rem; margin: 1rem 0; }
+section.user { background: #eef4ff; border-left: 4px solid #3b6fd8; }
+section.assistant { background: #f6f6f6; border-left: 4px solid #999; }
+section.message h2 { font-size: 0.9rem; margin: 0.25rem 0; color: #555; }
+pre { background: #1e1e1e; color: #eee; padding: 0.75rem; overflow-x: auto; border-radius: 4px; }"#;
+
+impl Renderer for HtmlRenderer {
+    fn extension(&self) -> &'static str {
+        "html"
+    }
+
+    fn render(&self, title: &str, msgs: &[Msg], sidechain: &[Msg]) -> String {
+        let title = escape_html(title);
+        let mut out = String::new();
+        out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        out.push_str(&format!("<title>{title}</title>\n"));
+        out.push_str(&format!("<style>\n{HTML_STYLE}\n</style>\n"));
+        out.push_str("</head>\n<body>\n");
+        out.push_str(&format!("<h1>{title}</h1>\n"));
+        self.push_messages(&mut out, msgs);
+        if !sidechain.is_empty() {
+            out.push_str("<h1>Sub-agent</h1>\n");
+            self.push_messages(&mut out, sidechain);
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+impl HtmlRenderer {
+    fn push_messages(&self, out: &mut String, msgs: &[Msg]) {
+        for m in msgs {
+            let (class, who) = if m.role == "user" {
+                ("user", "User")
+            } else {
+                ("assistant", "Assistant")
+            };
+            let ts =
+                m.ts.map(|t| format!(" <time>{}</time>", t.format(&self.time_fmt)))
+                    .unwrap_or_default();
+
+            out.push_str(&format!("<section class=\"message {class}\">\n"));
+            out.push_str(&format!("<h2>{who}{ts}</h2>\n"));
+            out.push_str(&render_html_body(&elide_data_uris(&m.text)));
+            out.push_str("</section>\n");
+        }
+    }
+}
+
+pub fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn render_html_body(text: &str) -> String {
+    let mut out = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut code: Option<Vec<&str>> = None;
+    let mut lang = String::new();
+
+    for line in text.lines() {
+        if let Some(lines) = code.as_mut() {
+            if line.trim_start().starts_with("```") {
+                out.push_str(&code_html(&lang, lines));
+                code = None;
+            } else {
+                lines.push(line);
+            }
+            continue;
+        }
+
+        if let Some(rest) = line.trim_start().strip_prefix("```") {
+            flush_paragraph(&mut out, &mut paragraph);
+            lang = rest.trim().to_string();
+            code = Some(Vec::new());
+        } else if line.trim().is_empty() {
+            flush_paragraph(&mut out, &mut paragraph);
+        } else {
+            paragraph.push(line);
+        }
+    }
+
+    // An unterminated fence still renders as code rather than being dropped.
+    if let Some(lines) = code {
+        out.push_str(&code_html(&lang, &lines));
+    }
+    flush_paragraph(&mut out, &mut paragraph);
+    out
+}
+
+fn flush_paragraph(out: &mut String, paragraph: &mut Vec<&str>) {
+    if paragraph.is_empty() {
+        return;
+    }
+    let body: Vec<String> = paragraph.iter().map(|l| escape_html(l)).collect();
+    out.push_str(&format!("<p>{}</p>\n", body.join("<br>\n")));
+    paragraph.clear();
+}
+
+fn code_html(lang: &str, lines: &[&str]) -> String {
+    let class = if lang.is_empty() {
+        String::new()
+    } else {
+        format!(" class=\"language-{}\"", escape_html(lang))
+    };
+    format!(
+        "<pre><code{class}>{}</code></pre>\n",
+        escape_html(&lines.join("\n"))
+    )
+}
+
+// Chat-friendly thread: user messages as plain text, assistant replies as
+// `> ` quotes. Written as `.txt` so it never shadows the note in id lookups.
+pub struct QuoteRenderer;
+
+pub const QUOTE_LINE_WIDTH: usize = 100;
+
+impl Renderer for QuoteRenderer {
+    fn extension(&self) -> &'static str {
+        "txt"
+    }
+
+    fn render(&self, title: &str, msgs: &[Msg], sidechain: &[Msg]) -> String {
+        let mut out = format!("**{}**\n", escape_mentions(title));
+        push_quoted_messages(&mut out, msgs);
+        if !sidechain.is_empty() {
+            out.push_str("\n**Sub-agent**\n");
+            push_quoted_messages(&mut out, sidechain);
+        }
+        out
+    }
+}
+
+fn push_quoted_messages(out: &mut String, msgs: &[Msg]) {
+    for m in msgs {
+        let prefix = if m.role == "user" { "" } else { "> " };
+        out.push('\n');
+        let text = escape_mentions(&elide_data_uris(m.text.trim_end()));
+        for line in wrap_text(&text, QUOTE_LINE_WIDTH - prefix.len()) {
+            if line.is_empty() {
+                out.push_str(prefix.trim_end());
+            } else {
+                out.push_str(prefix);
+                out.push_str(&line);
+            }
+            out.push('\n');
+        }
+    }
+}
+
+// Breaks a zero-width space after `@` at the start of a word, so `@here` or
+// `@someone` don't ping anybody; addresses like `a@b.com` are left alone.
+pub fn escape_mentions(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut prev: Option<char> = None;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        out.push(c);
+        let word_start = prev.is_none_or(|p| !p.is_alphanumeric());
+        if c == '@' && word_start && chars.peek().is_some_and(|n| n.is_alphanumeric()) {
+            out.push('\u{200B}');
+        }
+        prev = Some(c);
+    }
+    out
+}
+
+// Wraps prose at word boundaries; fenced code is kept verbatim.
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut in_code = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            lines.push(line.to_string());
+        } else if in_code || line.chars().count() <= width {
+            lines.push(line.to_string());
+        } else {
+            let mut current = String::new();
+            for word in line.split(' ') {
+                let needed = current.chars().count() + 1 + word.chars().count();
+                if !current.is_empty() && needed > width {
+                    lines.push(std::mem::take(&mut current));
+                } else if !current.is_empty() {
+                    current.push(' ');
+                }
+                current.push_str(word);
+            }
+            lines.push(current);
+        }
+    }
+    lines
+}
+
+// `LLM_LOGS_FLAVOR=logseq` writes the note itself as a Logseq page: `key::
+// value` properties instead of frontmatter, then one top-level block per
+// message with its paragraphs, list items and code blocks as child blocks.
+// Sub-agent messages follow under a `## Sub-agent` block. An existing page is
+// updated with `update_logseq_page`.
+pub struct LogseqRenderer {
+    pub time_fmt: String,
+    pub properties: Vec<(&'static str, String)>,
+}
+
+impl Renderer for LogseqRenderer {
+    fn extension(&self) -> &'static str {
+        "md"
+    }
+
+    fn render(&self, title: &str, msgs: &[Msg], sidechain: &[Msg]) -> String {
+        format!("{}\n{}", self.header(title), self.blocks(msgs, sidechain))
+    }
+}
+
+impl LogseqRenderer {
+    // The page properties, `title::` first.
+    pub fn header(&self, title: &str) -> String {
+        let mut out = format!("title:: {}\n", logseq_value(title));
+        for (key, value) in &self.properties {
+            out.push_str(&format!("{key}:: {}\n", logseq_value(value)));
+        }
+        out
+    }
+
+    pub fn blocks(&self, msgs: &[Msg], sidechain: &[Msg]) -> String {
+        let mut out = self.message_blocks(msgs);
+        if !sidechain.is_empty() {
+            out.push_str("- ## Sub-agent\n");
+            out.push_str(&self.message_blocks(sidechain));
+        }
+        out
+    }
+
+    fn message_blocks(&self, msgs: &[Msg]) -> String {
+        let mut out = String::new();
+        for m in msgs {
+            let who = if m.role == "user" {
+                "User"
+            } else {
+                "Assistant"
+            };
+            let ts =
+                m.ts.map(|t| format!(" {}", t.format(&self.time_fmt)))
+                    .unwrap_or_default();
+            out.push_str(&format!("- **{who}**{ts}\n"));
+            for block in logseq_child_blocks(&elide_data_uris(&m.text)) {
+                let mut lines = block.into_iter();
+                if let Some(first) = lines.next() {
+                    out.push_str(&format!("\t- {first}\n"));
+                }
+                // Continuation lines line up with the text after `- `.
+                for line in lines {
+                    out.push_str(&format!("\t  {line}\n"));
+                }
+            }
+        }
+        out
+    }
+}
+
+// Refreshes the generated parts of an existing page: the exporter's own
+// properties, with any others the user added kept after them, and the message
+// blocks between the transcript markers. Everything else on the page, such as
+// the user's own blocks or the tool activity block, is left alone. A page
+// without the markers was generated whole, so only its tool activity is kept.
+pub fn update_logseq_page(existing: &str, header: &str, blocks: &str) -> String {
+    let block = format!("{BEGIN}\n{blocks}{END}");
+    let mut properties_len = 0;
+    let mut kept = String::new();
+    for line in existing.split_inclusive('\n') {
+        let key = match line.split_once(":: ") {
+            Some((key, _)) if !key.contains(' ') => key,
+            _ => break,
+        };
+        let prefix = format!("{key}:: ");
+        if !header.lines().any(|l| l.starts_with(&prefix)) {
+            kept.push_str(line.trim_end());
+            kept.push('\n');
+        }
+        properties_len += line.len();
+    }
+
+    let body = &existing[properties_len..];
+    let body = if find_marker(body, BEGIN).is_some() {
+        upsert_marked_block(body, BEGIN, END, &block)
+    } else {
+        match marked_block(existing, TOOL_EVENTS_BEGIN, TOOL_EVENTS_END) {
+            Some(tools) => format!("\n{block}\n\n{tools}\n"),
+            None => format!("\n{block}\n"),
+        }
+    };
+    format!("{header}{kept}{body}")
+}
+
+// Property values are single-line.
+fn logseq_value(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Splits a message into blocks: a fenced code block stays whole, a list item
+// becomes its own block without its marker, and other lines are grouped into
+// paragraphs at blank lines.
+fn logseq_child_blocks(text: &str) -> Vec<Vec<&str>> {
+    let mut blocks: Vec<Vec<&str>> = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut fence = None;
+    for line in text.trim_end().lines() {
+        if let Some(open) = fence {
+            current.push(line);
+            if closes_fence(line, open) {
+                fence = None;
+                blocks.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        let item = line.strip_prefix("- ").or_else(|| line.strip_prefix("* "));
+        let breaks = line.trim().is_empty() || item.is_some() || fence_delimiter(line).is_some();
+        if breaks && !current.is_empty() {
+            blocks.push(std::mem::take(&mut current));
+        }
+        fence = fence_delimiter(line);
+        if !line.trim().is_empty() {
+            current.push(item.unwrap_or(line));
+        }
+    }
+    if !current.is_empty() {
+        blocks.push(current);
+    }
+    blocks
+}
@@ -0,0 +1,333 @@
+use ai_log_exporter::{
+    build_claude_note_skeleton, dated_thread_dir, expand_tilde, filename_budget, find_marker,
+    find_session_note, first_claude_prompt, generate_title, normalize_markdown,
+    note_filename_within, note_identity, read_to_string_lossy, safe_id, synthesized_session_id,
+    upsert_marked_block, with_lock_file, Config, Layout, EXPLODED_INDEX_FILE, MAX_PATH_LEN,
+    TOOL_EVENTS_BEGIN, TOOL_EVENTS_END,
+};
+use ai_log_exporter::log;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde_json::Value;
+use std::{
+    env, fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+pub const BEGIN: &str = TOOL_EVENTS_BEGIN;
+pub const END: &str = TOOL_EVENTS_END;
+const HEADING: &str = "## Tool activity (auto)";
+
+// Tool uses without an id share this sentinel, so they are never deduplicated;
+// their `PostToolUse` completes the latest open line of the same tool instead.
+pub const MISSING_TOOL_USE_ID: &str = "(missing)";
+
+// Most event lines a note keeps; older ones are dropped as new ones arrive.
+pub const MAX_TOOL_EVENTS: usize = 200;
+
+// Longest tool input summary kept on an event line.
+pub const SUMMARY_MAX_CHARS: usize = 120;
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (verbosity, args) = log::take_verbosity(&args);
+    log::set_verbosity(verbosity);
+    if args.first().map(String::as_str) == Some("--print-config") {
+        let cwd = args.get(1).map(String::as_str).unwrap_or(".");
+        print!("{}", Config::from_env()?.describe(cwd));
+        return Ok(());
+    }
+
+    // PreToolUse / PostToolUse hook payload arrives on stdin as JSON
+    let mut stdin = String::new();
+    io::stdin()
+        .read_to_string(&mut stdin)
+        .context("failed to read stdin")?;
+    let stdin = stdin.trim();
+    if stdin.is_empty() {
+        return Ok(());
+    }
+    let payload: Value =
+        serde_json::from_str(stdin).context("failed to parse hook JSON from stdin")?;
+    let event = match ToolEvent::from_payload(&payload, Local::now()) {
+        Some(event) => event,
+        None => return Ok(()),
+    };
+    let cwd = payload.get("cwd").and_then(|v| v.as_str()).unwrap_or(".");
+    let transcript_path = payload
+        .get("transcript_path")
+        .and_then(|v| v.as_str())
+        .map(expand_tilde)
+        .unwrap_or_default();
+    // The same stand-in claude_session_to_obsidian uses, so both land on one note.
+    let session_id = match payload.get("session_id").and_then(|v| v.as_str()) {
+        Some(id) if !id.trim().is_empty() => id.to_string(),
+        _ => synthesized_session_id(&transcript_path, cwd),
+    };
+
+    let config = Config::from_env()?;
+    record_tool_event(&config, &session_id, cwd, &transcript_path, &event)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Pre,
+    // `Some(false)` when the tool reported an error.
+    Post(Option<bool>),
+}
+
+// One `PreToolUse`/`PostToolUse` hook call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolEvent {
+    pub tool_use_id: Option<String>,
+    pub tool: String,
+    pub summary: String,
+    pub phase: Phase,
+    pub ts: DateTime<Local>,
+}
+
+impl ToolEvent {
+    // `None` for any other hook event, so the binary can be registered broadly.
+    pub fn from_payload(payload: &Value, ts: DateTime<Local>) -> Option<Self> {
+        let phase = match payload.get("hook_event_name").and_then(|v| v.as_str())? {
+            "PreToolUse" => Phase::Pre,
+            "PostToolUse" => Phase::Post(payload.get("tool_response").map(tool_succeeded)),
+            _ => return None,
+        };
+        let tool = payload
+            .get("tool_name")
+            .and_then(|v| v.as_str())
+            .unwrap_or("tool")
+            .to_string();
+        let input = payload.get("tool_input").unwrap_or(&Value::Null);
+        Some(Self {
+            tool_use_id: payload
+                .get("tool_use_id")
+                .and_then(|v| v.as_str())
+                .filter(|id| !id.trim().is_empty())
+                .map(str::to_string),
+            summary: summarize_input(&tool, input),
+            tool,
+            phase,
+            ts,
+        })
+    }
+
+    pub fn sentinel(&self) -> String {
+        let id = self.tool_use_id.as_deref().unwrap_or(MISSING_TOOL_USE_ID);
+        format!("<!-- tool-use:{id} -->")
+    }
+}
+
+fn tool_succeeded(response: &Value) -> bool {
+    let failed = response.get("is_error").and_then(Value::as_bool) == Some(true)
+        || response.get("success").and_then(Value::as_bool) == Some(false)
+        || response.get("error").is_some_and(|e| !e.is_null());
+    !failed
+}
+
+// The one input field that says what the call does (the command, the file,
+// the pattern), or the whole input as compact JSON for other tools.
+pub fn summarize_input(tool: &str, input: &Value) -> String {
+    let keys: &[&str] = match tool {
+        "Bash" => &["command"],
+        "Read" | "Write" | "Edit" | "MultiEdit" | "NotebookEdit" => &["file_path", "notebook_path"],
+        "Grep" | "Glob" => &["pattern"],
+        "WebFetch" => &["url"],
+        "WebSearch" => &["query"],
+        "Task" => &["description"],
+        _ => &[],
+    };
+    let text = keys
+        .iter()
+        .find_map(|k| input.get(*k).and_then(|v| v.as_str()))
+        .map(str::to_string)
+        .unwrap_or_else(|| match input {
+            Value::Null => String::new(),
+            other => other.to_string(),
+        });
+    let first_line = text
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or("")
+        .trim();
+    let more = text.trim().lines().count() > 1;
+    if first_line.chars().count() > SUMMARY_MAX_CHARS || more {
+        let cut: String = first_line.chars().take(SUMMARY_MAX_CHARS).collect();
+        format!("{}…", cut.trim_end())
+    } else {
+        first_line.to_string()
+    }
+}
+
+// An inline code span that survives backticks in `text`.
+fn inline_code(text: &str) -> String {
+    if text.is_empty() {
+        return String::new();
+    }
+    let mut fence = String::from("`");
+    while text.contains(fence.as_str()) {
+        fence.push('`');
+    }
+    if fence.len() > 1 || text.starts_with('`') || text.ends_with('`') {
+        format!("{fence} {text} {fence}")
+    } else {
+        format!("{fence}{text}{fence}")
+    }
+}
+
+// The event as a single list line, ending in its tool-use sentinel.
+pub fn render_tool_event(event: &ToolEvent, time_fmt: &str) -> String {
+    let status = match event.phase {
+        Phase::Pre => "",
+        Phase::Post(Some(false)) => " — failed",
+        Phase::Post(_) => " — done",
+    };
+    let summary = inline_code(&event.summary);
+    let sep = if summary.is_empty() { "" } else { " " };
+    format!(
+        "- {} **{}**{sep}{summary}{status} {}",
+        event.ts.format(time_fmt),
+        event.tool.replace(['\n', '\r'], " "),
+        event.sentinel()
+    )
+}
+
+// Whether `line` is a call of `tool` without an id still waiting for its outcome.
+fn is_open_anonymous_line(line: &str, tool: &str) -> bool {
+    let sentinel = format!("<!-- tool-use:{MISSING_TOOL_USE_ID} -->");
+    match line.strip_suffix(&sentinel).map(str::trim_end) {
+        Some(rest) => {
+            rest.contains(&format!(" **{}**", tool.replace(['\n', '\r'], " ")))
+                && !rest.ends_with(" — done")
+                && !rest.ends_with(" — failed")
+        }
+        None => false,
+    }
+}
+
+// Adds `event` to the note's tool activity block, creating the block at the
+// end of the note if needed. Each tool use keeps one line: a repeated
+// `PreToolUse` is ignored, and `PostToolUse` replaces the line with its outcome.
+// Only the newest `MAX_TOOL_EVENTS` lines are kept.
+pub fn apply_tool_event(note: &str, event: &ToolEvent, time_fmt: &str) -> String {
+    let mut lines: Vec<String> = match (find_marker(note, BEGIN), find_marker(note, END)) {
+        (Some(b), Some(e)) if e >= b => note[b + BEGIN.len()..e]
+            .lines()
+            .filter(|l| l.starts_with("- "))
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    };
+    let line = render_tool_event(event, time_fmt);
+    let sentinel = event.sentinel();
+    let existing = match (&event.tool_use_id, event.phase) {
+        (Some(_), _) => lines.iter().position(|l| l.ends_with(&sentinel)),
+        (None, Phase::Post(_)) => lines
+            .iter()
+            .rposition(|l| is_open_anonymous_line(l, &event.tool)),
+        (None, Phase::Pre) => None,
+    };
+    match (existing, event.phase) {
+        (Some(_), Phase::Pre) => return note.to_string(),
+        (Some(i), Phase::Post(_)) => lines[i] = line,
+        (None, _) => lines.push(line),
+    }
+    if lines.len() > MAX_TOOL_EVENTS {
+        lines.drain(..lines.len() - MAX_TOOL_EVENTS);
+    }
+
+    let block = format!("{BEGIN}\n{HEADING}\n{}\n{END}", lines.join("\n"));
+    upsert_marked_block(note, BEGIN, END, &block)
+}
+
+// Writes `event` into the session's note, creating the note from a skeleton
+// when the tool call comes before the first export. In the exploded layout the
+// events go into the session folder's index.
+pub fn record_tool_event(
+    config: &Config,
+    session_id: &str,
+    cwd: &str,
+    transcript_path: &Path,
+    event: &ToolEvent,
+) -> Result<()> {
+    if config.is_ignored(session_id, cwd) {
+        return Ok(());
+    }
+    let project = config.project_name(cwd);
+    let md_dir = config
+        .ai_dir()?
+        .join(config.claude_dir_name())
+        .join(&project)
+        .join("Threads");
+    let session_id_safe = safe_id(session_id, "unknown-session");
+    fs::create_dir_all(&md_dir).context("failed to create md_dir")?;
+
+    let mut event = event.clone();
+    if let Some(redactor) = config.redactor()? {
+        event.summary = redactor.redact(&event.summary);
+    }
+    let lock_path = md_dir.join(format!(".lock_{session_id_safe}"));
+    with_lock_file(&lock_path, || {
+        let uid = note_identity("Claude Code", session_id);
+        let existing = match config.layout {
+            Layout::Exploded => Some(md_dir.join(&session_id_safe).join(EXPLODED_INDEX_FILE))
+                .filter(|path| path.exists()),
+            Layout::Note => find_session_note(&md_dir, &session_id_safe, &uid),
+        };
+        let (md_path, note) = match existing {
+            Some(path) => {
+                let note = read_to_string_lossy(&path).context("failed to read md note")?;
+                (path, note)
+            }
+            None => {
+                log::info(|| format!("creating note for session: {session_id}"));
+                let first = first_claude_prompt(transcript_path);
+                let created = first.as_ref().and_then(|(_, ts)| *ts).unwrap_or(event.ts);
+                let note_cwd = (!config.omit_cwd).then_some(cwd);
+                let skeleton =
+                    build_claude_note_skeleton(&project, session_id, note_cwd, created, 0);
+                let title_msg = first.as_ref().map(|(text, _)| text.as_str());
+                let path = new_note_path(config, &md_dir, &session_id_safe, title_msg, created)?;
+                (path, skeleton)
+            }
+        };
+        let updated = apply_tool_event(&note, &event, &config.formats.time);
+        if updated != note {
+            config
+                .write_output(&md_path, &normalize_markdown(&updated))
+                .context("failed to write md note")?;
+        }
+        Ok(())
+    })
+}
+
+// Where the first export would put the session's note, so it keeps writing
+// there: the session folder's index in the exploded layout, else a dated file
+// named after the first prompt.
+fn new_note_path(
+    config: &Config,
+    md_dir: &Path,
+    session_id_safe: &str,
+    title_msg: Option<&str>,
+    created: DateTime<Local>,
+) -> Result<PathBuf> {
+    if config.layout == Layout::Exploded {
+        let dir = md_dir.join(session_id_safe);
+        fs::create_dir_all(&dir).context("failed to create session folder")?;
+        return Ok(dir.join(EXPLODED_INDEX_FILE));
+    }
+    let day_dir = dated_thread_dir(md_dir, created.date_naive(), config.monthly_folders);
+    fs::create_dir_all(&day_dir).context("failed to create dated Threads dir")?;
+    let title = generate_title(title_msg, config.title_llm());
+    let budget = filename_budget(&day_dir, MAX_PATH_LEN);
+    let date = created.date_naive();
+    let filename = note_filename_within(&config.formats, date, &title, session_id_safe, budget);
+    Ok(day_dir.join(filename))
+}
+
+#[cfg(test)]
+#[path = "tests.rs"]
+mod tests;
@@ -0,0 +1,252 @@
+use super::*;
+use serde_json::json;
+use std::path::{Path, PathBuf};
+
+fn at(rfc3339: &str) -> DateTime<Local> {
+    ai_log_exporter::parse_rfc3339_local(rfc3339).unwrap()
+}
+
+fn event(id: Option<&str>, phase: Phase, ts: &str) -> ToolEvent {
+    ToolEvent {
+        tool_use_id: id.map(str::to_string),
+        tool: "Bash".to_string(),
+        summary: "cargo test".to_string(),
+        phase,
+        ts: at(ts),
+    }
+}
+
+const TIME_FMT: &str = "%H:%M:%S";
+
+// ========================================
+// payload tests
+// ========================================
+
+#[test]
+fn test_tool_event_from_payload_reads_pre_and_post() {
+    let ts = at("2024-01-01T10:00:00Z");
+    let pre = json!({
+        "hook_event_name": "PreToolUse",
+        "tool_name": "Bash",
+        "tool_use_id": "toolu_1",
+        "tool_input": {"command": "cargo test --workspace\necho done", "timeout": 5},
+    });
+    let event = ToolEvent::from_payload(&pre, ts).unwrap();
+    assert_eq!(event.phase, Phase::Pre);
+    assert_eq!(event.tool, "Bash");
+    assert_eq!(event.tool_use_id.as_deref(), Some("toolu_1"));
+    assert_eq!(event.summary, "cargo test --workspace…");
+
+    let post = json!({
+        "hook_event_name": "PostToolUse",
+        "tool_name": "Read",
+        "tool_input": {"file_path": "/src/main.rs"},
+        "tool_response": {"success": false},
+    });
+    let event = ToolEvent::from_payload(&post, ts).unwrap();
+    assert_eq!(event.phase, Phase::Post(Some(false)));
+    assert_eq!(event.summary, "/src/main.rs");
+    assert_eq!(event.tool_use_id, None);
+
+    let stop = json!({"hook_event_name": "Stop", "session_id": "s1"});
+    assert_eq!(ToolEvent::from_payload(&stop, ts), None);
+}
+
+#[test]
+fn test_summarize_input_falls_back_to_compact_json() {
+    let input = json!({"todos": [{"content": "write tests"}]});
+    assert_eq!(summarize_input("TodoWrite", &input), input.to_string());
+    assert_eq!(summarize_input("Bash", &Value::Null), "");
+    let long = "x".repeat(SUMMARY_MAX_CHARS + 10);
+    let summary = summarize_input("Bash", &json!({"command": long}));
+    assert_eq!(summary.chars().count(), SUMMARY_MAX_CHARS + 1);
+    assert!(summary.ends_with('…'));
+}
+
+// ========================================
+// rendering tests
+// ========================================
+
+#[test]
+fn test_render_tool_event_formats_status_and_sentinel() {
+    let pre = event(Some("toolu_1"), Phase::Pre, "2024-01-01T10:00:00Z");
+    let time = pre.ts.format(TIME_FMT);
+    assert_eq!(
+        render_tool_event(&pre, TIME_FMT),
+        format!("- {time} **Bash** `cargo test` <!-- tool-use:toolu_1 -->")
+    );
+
+    let failed = event(
+        Some("toolu_1"),
+        Phase::Post(Some(false)),
+        "2024-01-01T10:00:00Z",
+    );
+    let line = render_tool_event(&failed, TIME_FMT);
+    assert!(line.ends_with("`cargo test` — failed <!-- tool-use:toolu_1 -->"));
+    let done = event(None, Phase::Post(None), "2024-01-01T10:00:00Z");
+    assert!(render_tool_event(&done, TIME_FMT).ends_with(" — done <!-- tool-use:(missing) -->"));
+
+    let ticks = ToolEvent {
+        summary: "echo `date`".to_string(),
+        ..pre
+    };
+    assert!(render_tool_event(&ticks, TIME_FMT).contains("**Bash** `` echo `date` `` <!--"));
+}
+
+// ========================================
+// dedupe tests
+// ========================================
+
+#[test]
+fn test_apply_tool_event_appends_block_and_dedupes_by_tool_use_id() {
+    let note = "---\nsession_id: \"s1\"\n---\n\nbody\n";
+    let pre = event(Some("toolu_1"), Phase::Pre, "2024-01-01T10:00:00Z");
+
+    let once = apply_tool_event(note, &pre, TIME_FMT);
+    assert!(once.starts_with(note.trim_end()));
+    assert!(once.contains(&format!("{BEGIN}\n## Tool activity (auto)\n- ")));
+    assert!(once.trim_end().ends_with(END));
+    assert_eq!(apply_tool_event(&once, &pre, TIME_FMT), once);
+
+    let post = event(
+        Some("toolu_1"),
+        Phase::Post(Some(true)),
+        "2024-01-01T10:00:03Z",
+    );
+    let done = apply_tool_event(&once, &post, TIME_FMT);
+    assert_eq!(done.matches("<!-- tool-use:toolu_1 -->").count(), 1);
+    assert!(done.contains(" — done <!-- tool-use:toolu_1 -->"));
+    assert_eq!(apply_tool_event(&done, &post, TIME_FMT), done);
+    // A late PreToolUse doesn't undo the outcome.
+    assert_eq!(apply_tool_event(&done, &pre, TIME_FMT), done);
+
+    let second = event(Some("toolu_2"), Phase::Pre, "2024-01-01T10:00:04Z");
+    let both = apply_tool_event(&done, &second, TIME_FMT);
+    let toolu_1 = both.find("tool-use:toolu_1").unwrap();
+    assert!(toolu_1 < both.find("tool-use:toolu_2").unwrap());
+    assert_eq!(both.matches(BEGIN).count(), 1);
+
+    // Without an id there is nothing to match on, so every call is kept.
+    let anon = event(None, Phase::Pre, "2024-01-01T10:00:05Z");
+    let anon_twice = apply_tool_event(&apply_tool_event(&both, &anon, TIME_FMT), &anon, TIME_FMT);
+    assert_eq!(anon_twice.matches("<!-- tool-use:(missing) -->").count(), 2);
+}
+
+#[test]
+fn test_apply_tool_event_completes_anonymous_call_and_caps_block() {
+    let note = "body\n";
+    let pre = event(None, Phase::Pre, "2024-01-01T10:00:00Z");
+    let read = ToolEvent {
+        tool: "Read".to_string(),
+        ..event(None, Phase::Pre, "2024-01-01T10:00:01Z")
+    };
+    let open = apply_tool_event(&apply_tool_event(note, &pre, TIME_FMT), &read, TIME_FMT);
+
+    // The outcome replaces the open Bash line rather than adding a second one.
+    let post = event(None, Phase::Post(Some(true)), "2024-01-01T10:00:02Z");
+    let done = apply_tool_event(&open, &post, TIME_FMT);
+    assert_eq!(done.matches("**Bash**").count(), 1, "{done}");
+    assert!(done.contains("**Bash** `cargo test` — done <!-- tool-use:(missing) -->"));
+    assert!(!done.contains("**Read** `cargo test` — done"));
+    // With no open Bash line left, a stray outcome gets a line of its own.
+    let again = apply_tool_event(&done, &post, TIME_FMT);
+    assert_eq!(again.matches("**Bash**").count(), 2, "{again}");
+
+    let mut capped = note.to_string();
+    for i in 0..MAX_TOOL_EVENTS + 5 {
+        let id = format!("toolu_{i}");
+        let pre = event(Some(&id), Phase::Pre, "2024-01-01T10:00:00Z");
+        capped = apply_tool_event(&capped, &pre, TIME_FMT);
+    }
+    assert_eq!(capped.matches("<!-- tool-use:").count(), MAX_TOOL_EVENTS);
+    assert!(!capped.contains("<!-- tool-use:toolu_4 -->"));
+    assert!(capped.contains("<!-- tool-use:toolu_5 -->"));
+    assert!(capped.starts_with("body\n"));
+}
+
+fn record_config(dir: &Path) -> Config {
+    Config {
+        vault: Some(dir.to_path_buf()),
+        ai_root: Some(Path::new("llms").to_path_buf()),
+        plain_titles: true,
+        ..Config::default()
+    }
+}
+
+#[test]
+fn test_record_tool_event_creates_note_from_skeleton_once() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let config = record_config(dir);
+    let transcript = dir.join("session.jsonl");
+    let prompt = json!({
+        "type": "user",
+        "timestamp": "2024-01-01T09:00:00Z",
+        "message": {"role": "user", "content": "Fix the flaky test"},
+    });
+    fs::write(&transcript, format!("{prompt}\n")).unwrap();
+    let pre = event(Some("toolu_1"), Phase::Pre, "2024-01-01T10:00:00Z");
+
+    record_tool_event(&config, "s-tool", "/work/proj", &transcript, &pre).unwrap();
+    record_tool_event(&config, "s-tool", "/work/proj", &transcript, &pre).unwrap();
+    let threads = dir.join("llms/Claude Code/proj/Threads");
+    let day = dated_thread_dir(&threads, at("2024-01-01T09:00:00Z").date_naive(), false);
+    let notes: Vec<PathBuf> = fs::read_dir(&day)
+        .unwrap()
+        .flatten()
+        .map(|e| e.path())
+        .collect();
+    assert_eq!(notes.len(), 1, "{notes:?}");
+    let name = notes[0].file_name().unwrap().to_string_lossy().into_owned();
+    assert!(
+        name.contains("fix-the-flaky-test") && name.contains("s-tool"),
+        "{name}"
+    );
+    let note = fs::read_to_string(&notes[0]).unwrap();
+    assert!(note.starts_with("---\ntool: \"Claude Code\"\n"), "{note}");
+    assert!(note.contains("session_id: \"s-tool\""));
+    assert!(note.contains("cwd: \"/work/proj\""));
+    assert_eq!(
+        note.matches("<!-- tool-use:toolu_1 -->").count(),
+        1,
+        "{note}"
+    );
+}
+
+#[test]
+fn test_record_tool_event_writes_exploded_index() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let config = Config {
+        layout: Layout::Exploded,
+        omit_cwd: true,
+        ..record_config(dir)
+    };
+    let pre = event(Some("toolu_1"), Phase::Pre, "2024-01-01T10:00:00Z");
+    let missing = dir.join("missing.jsonl");
+    record_tool_event(&config, "s-tool", "/work/proj", &missing, &pre).unwrap();
+
+    let index = dir
+        .join("llms/Claude Code/proj/Threads/s-tool")
+        .join(EXPLODED_INDEX_FILE);
+    let note = fs::read_to_string(&index).unwrap();
+    assert!(note.contains("<!-- tool-use:toolu_1 -->"), "{note}");
+    assert!(!note.contains("cwd:"), "{note}");
+}
+
+#[test]
+fn test_record_tool_event_finds_renamed_note_by_uid() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let config = record_config(dir);
+    let threads = dir.join("llms/Claude Code/proj/Threads");
+    fs::create_dir_all(&threads).unwrap();
+    let note_path = threads.join("My notes.md");
+    let uid = note_identity("Claude Code", "s-tool");
+    fs::write(&note_path, format!("---\nuid: \"{uid}\"\n---\n\nbody\n")).unwrap();
+
+    let pre = event(Some("toolu_1"), Phase::Pre, "2024-01-01T10:00:00Z");
+    record_tool_event(&config, "s-tool", "/work/proj", Path::new(""), &pre).unwrap();
+    let note = fs::read_to_string(&note_path).unwrap();
+    assert!(note.contains("<!-- tool-use:toolu_1 -->"), "{note}");
+}
@@ -1,111 +1,674 @@
 use ai_log_exporter::{
-    find_md_file_containing_id, generate_title, git_project_name, safe_id, safe_name, with_lock_file,
-    yaml_quote,
+    append_jsonl_line_dedup, append_to_daily_digest, build_footer_block, dated_thread_dir,
+    extract_timestamp, filename_budget, find_marker, find_md_file_by_uid,
+    find_md_file_containing_id, find_session_note, find_session_notes, frontmatter_field,
+    generate_title, merge_frontmatter_template, normalize_markdown, note_filename_within,
+    note_identity, note_title, parse_cli_flags, purge_exported_session, read_frontmatter,
+    read_frontmatter_template, read_to_string_lossy, remove_cwd_fields, repair_markers_with,
+    resolve_created, safe_id, synthesized_id, take_flag, turn_archive_path, upsert_aliases_field,
+    upsert_footer_block, upsert_frontmatter_field, upsert_model_field, upsert_project_path_field,
+    with_lock_file, write_result_note, yaml_quote, yaml_scalar, AssumedZone, Config, MAX_PATH_LEN,
+    TextOptions, TitleSource,
 };
-use anyhow::{Context, Result};
-use chrono::{Local, SecondsFormat};
+use ai_log_exporter::codex::{
+    ensure_turns_block, insert_before_end, render_turn, Turn, BEGIN, END, MISSING_SENTINEL,
+};
+use ai_log_exporter::index::{index_path, index_turn, IndexedMessage};
+use ai_log_exporter::log;
+use ai_log_exporter::redact::SecretRedactor;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local, NaiveDate, SecondsFormat};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{
     env, fs,
+    fs::OpenOptions,
+    io::Write,
     path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-pub const BEGIN: &str = "<!-- BEGIN AUTO TURNS -->";
-pub const END: &str = "<!-- END AUTO TURNS -->";
-
 fn main() -> Result<()> {
-    let payload_arg = env::args().nth(1);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (verbosity, args) = log::take_verbosity(&args);
+    log::set_verbosity(verbosity);
+    let (validate, args) = take_flag(&args, "--validate-payload");
+    let payload_arg = args.first().cloned();
+    if payload_arg.as_deref() == Some("--print-config") {
+        let cwd = args.get(1).cloned().unwrap_or_else(|| ".".to_string());
+        print!("{}", Config::from_env()?.describe(&cwd));
+        return Ok(());
+    }
+    if payload_arg.as_deref() == Some("--flush") {
+        return flush_spools(&Config::from_env()?, Duration::ZERO, SystemTime::now());
+    }
+    if payload_arg.as_deref() == Some("--archive") {
+        let config = Config::from_env()?;
+        for path in config.archive(config.codex_dir_name())? {
+            println!("archived {}", path.display());
+        }
+        return Ok(());
+    }
+    // Nothing to record exits 0; a bad payload or config fails the run so
+    // Codex reports it.
     let payload_arg = match payload_arg {
         Some(p) if !p.trim().is_empty() => p,
         _ => return Ok(()),
     };
 
-    // Codex has historically passed the notification as a JSON string in argv[1],
-    // but support treating argv[1] as a file path (JSON) as well.
-    let notification: Value = match serde_json::from_str(&payload_arg) {
-        Ok(v) => v,
-        Err(e_json) => match fs::read_to_string(&payload_arg) {
-            Ok(file_text) => {
-                serde_json::from_str(&file_text)
-                    .context("failed to parse notify JSON from file path in argv[1]")?
-            }
-            Err(e_file) => {
-                return Err(e_json).with_context(|| {
-                    format!(
-                        "failed to parse notify JSON from argv[1] and failed to read it as a file path: {}",
-                        e_file
-                    )
-                })
-            }
-        },
+    let notification = if payload_arg.starts_with("--") {
+        notification_from_flags(&args)?
+    } else {
+        parse_payload_arg(&payload_arg)?
     };
 
-    if !should_process_notification(&notification) {
+    let config = Config::from_env()?;
+    if validate {
+        print!("{}", validate_notification(&config, &notification)?);
         return Ok(());
     }
+    handle_notification(&config, &notification)
+}
 
-    let thread_id = notification_str(&notification, &["thread-id", "thread_id", "threadId"])
-        .unwrap_or("unknown-thread");
-    let thread_id_safe = safe_id(thread_id, "unknown-thread");
+// Codex has historically passed the notification as a JSON string in argv[1],
+// but support treating argv[1] as a file path (JSON) as well.
+fn parse_payload_arg(payload_arg: &str) -> Result<Value> {
+    match serde_json::from_str(payload_arg) {
+        Ok(v) => Ok(v),
+        Err(e_json) => match fs::read_to_string(payload_arg) {
+            Ok(file_text) => serde_json::from_str(&file_text)
+                .context("failed to parse notify JSON from file path in argv[1]"),
+            Err(e_file) => Err(e_json).with_context(|| {
+                format!(
+                    "failed to parse notify JSON from argv[1] and failed to read it as a file path: {}",
+                    e_file
+                )
+            }),
+        },
+    }
+}
+
+pub fn handle_notification(config: &Config, notification: &Value) -> Result<()> {
+    write_notification(config, notification, false)?;
+    // Spooled turns must not wait for another turn of their own thread, which
+    // may never come; any later run writes out the spools that are due.
+    if let Some(interval) = config.codex_debounce {
+        if let Err(e) = flush_spools(config, interval, SystemTime::now()) {
+            log::warn(|| format!("failed to flush spooled turns: {e:#}"));
+        }
+    }
+    Ok(())
+}
+
+// Writes out every spool whose oldest turn has waited at least `interval`;
+// `--flush` passes zero to write out all of them.
+pub fn flush_spools(config: &Config, interval: Duration, now: SystemTime) -> Result<()> {
+    let codex_dir = config.ai_dir()?.join(config.codex_dir_name());
+    let projects = match fs::read_dir(&codex_dir) {
+        Ok(projects) => projects,
+        Err(_) => return Ok(()),
+    };
+    for project in projects.flatten() {
+        let threads = match fs::read_dir(project.path().join("Threads")) {
+            Ok(threads) => threads,
+            Err(_) => continue,
+        };
+        for entry in threads.flatten() {
+            if !entry.file_name().to_string_lossy().starts_with(".spool_") {
+                continue;
+            }
+            let spool_path = entry.path();
+            if !spool_due(&spool_path, interval, now)? {
+                continue;
+            }
+            // The newest spooled notification names the thread and stands in
+            // for the turn that would otherwise have triggered the flush.
+            match read_spool(&spool_path)?.pop() {
+                Some(last) => write_notification(config, &last.notification, true)?,
+                None => {
+                    let _ = fs::remove_file(&spool_path);
+                }
+            }
+        }
+    }
+    Ok(())
+}
 
-    let turn_id = notification_str(&notification, &["turn-id", "turn_id", "turnId"]).unwrap_or("");
+// `flushing` replays a spooled notification: it is already in the raw log and
+// the spool, and its thread's spool is written out regardless of age.
+fn write_notification(config: &Config, notification: &Value, flushing: bool) -> Result<()> {
+    let redactor = config.redactor()?;
+    let redactor = redactor.as_ref();
+    let received = notification;
+    let redacted = redactor.map(|r| r.redact_json(notification));
+    let notification = redacted.as_ref().unwrap_or(notification);
 
-    let cwd = notification_str(&notification, &["cwd"]).unwrap_or(".");
+    let session_end = is_session_end_notification(notification);
+    if !session_end && !should_process_notification(notification) {
+        return Ok(());
+    }
 
-    let input_messages = notification
-        .get("input-messages")
-        .or_else(|| notification.get("input_messages"))
-        .or_else(|| notification.get("inputMessages"))
-        .cloned()
-        .unwrap_or(Value::Null);
+    let codex_dir = config.ai_dir()?.join(config.codex_dir_name());
+    let thread_id = notification_thread_id(notification);
+    let thread_id = thread_id.as_str();
+    let thread_id_safe = safe_id(thread_id, "unknown-thread");
+    let cwd = notification_cwd(notification, &codex_dir, thread_id);
 
-    let last_assistant =
-        notification_str(&notification, &["last-assistant-message", "last_assistant_message", "lastAssistantMessage"])
-            .unwrap_or("");
+    let project = config.project_name(&cwd);
+    if config.is_ignored(thread_id, &cwd) {
+        if config.ignore_purge {
+            purge_thread(config, &codex_dir.join(&project), thread_id)?;
+        }
+        return Ok(());
+    }
 
-    let vault = env::var("OBSIDIAN_VAULT").context("Missing OBSIDIAN_VAULT env var")?;
-    let ai_root = env::var("OBSIDIAN_AI_ROOT").context("Missing OBSIDIAN_AI_ROOT env var")?;
+    let text_options = config.text_options();
+    let input_messages = input_messages_of(notification);
+    let last_assistant = last_assistant_of(notification, &text_options);
 
-    let project = safe_name(&git_project_name(cwd));
+    let formats = &config.formats;
+    let debounce = config.codex_debounce;
 
-    let vault_path = PathBuf::from(&vault);
-    let base_dir = vault_path.join(&ai_root).join("Codex").join(&project);
+    let base_dir = codex_dir.join(&project);
     let md_dir = base_dir.join("Threads");
     fs::create_dir_all(&md_dir).context("failed to create md_dir")?;
 
     let lock_path = md_dir.join(format!(".lock_{thread_id_safe}"));
+    let mut appended = false;
+    let mut written = None;
     with_lock_file(&lock_path, || {
-        let first_user_msg = extract_first_user_msg(&input_messages);
-        let md_path = find_or_create_md_path(&md_dir, &thread_id_safe, first_user_msg.as_deref())
-            .context("failed to find or create md path")?;
-        let mut text = if md_path.exists() {
-            fs::read_to_string(&md_path).context("failed to read existing md")?
+        let raw_path = base_dir
+            .join("_raw")
+            .join("notify")
+            .join(format!("{thread_id_safe}.jsonl"));
+        let raw = if config.redact_raw {
+            notification
         } else {
-            build_codex_note_skeleton(&project, thread_id, cwd)
+            received
         };
+        if !flushing {
+            let raw_line =
+                serde_json::to_string(raw).context("failed to serialize notification")?;
+            appended = append_jsonl_line_dedup(&raw_path, &raw_line)?;
+            config.mirror(&raw_path);
+        }
 
-        text = ensure_turns_block(&text);
+        let ts = extract_timestamp(notification, config.assume_tz).unwrap_or_else(Local::now);
+        let turn = turn_block_for(config, notification, Some(ts));
 
-        if !turn_id.is_empty() {
-            let sentinel = format!("<!-- turn-id:{turn_id} -->");
-            if text.contains(&sentinel) {
+        // A new thread gets no note until it has enough messages; the turns
+        // held back until then are rebuilt from the raw log.
+        let mut backfill = Vec::new();
+        let uid = note_identity("Codex CLI", thread_id);
+        let new_thread = find_session_note(&md_dir, &thread_id_safe, &uid).is_none();
+        if config.min_messages > 0 && new_thread {
+            if raw_message_count(&raw_path)? < config.min_messages {
                 return Ok(());
             }
-            let block = build_turn_block(turn_id, &input_messages, last_assistant, &sentinel);
-            text = insert_before_end(&text, &block);
+            backfill = raw_turn_blocks(config, &raw_path, redactor)?;
+            // The notification being handled is the raw log's newest line; its
+            // turn keeps the time it was received.
+            if let (true, Some(last), Some(turn)) = (appended, backfill.last_mut(), &turn) {
+                last.clone_from(turn);
+            }
+        }
+
+        // With a debounce interval, turns go to a spool file first and only
+        // reach the note once the oldest has waited the interval or the
+        // session ends.
+        let spool_path = md_dir.join(format!(".spool_{thread_id_safe}"));
+        let spooled = flushing || debounce.is_some();
+        let blocks = match debounce {
+            _ if flushing => read_spooled_turns(&spool_path)?,
+            Some(interval) => {
+                let now = SystemTime::now();
+                if let Some(block) = &turn {
+                    spool_turn(&spool_path, notification, block, now)?;
+                }
+                if !session_end && !spool_due(&spool_path, interval, now)? {
+                    return Ok(());
+                }
+                read_spooled_turns(&spool_path)?
+            }
+            None => turn.clone().into_iter().collect(),
+        };
+        let blocks = if backfill.is_empty() {
+            blocks
         } else {
-            let sentinel = "<!-- turn-id:(missing) -->".to_string();
-            let block =
-                build_turn_block("(no turn-id)", &input_messages, last_assistant, &sentinel);
-            text = insert_before_end(&text, &block);
+            backfill
+        };
+        if blocks.is_empty() {
+            return Ok(());
         }
 
-        fs::write(&md_path, text).context("failed to write md")?;
+        // Only a new note needs a title; `last`/`longest` look at every prompt
+        // of the thread so far, which the raw log already holds.
+        let title_msg = match config.title_source {
+            TitleSource::First => extract_first_user_msg(&input_messages),
+            source if new_thread => {
+                let prompts: Vec<String> = raw_notifications(&raw_path, redactor)?
+                    .iter()
+                    .flat_map(|n| extract_user_msgs(&input_messages_of(n)))
+                    .collect();
+                source
+                    .pick(prompts.iter().map(String::as_str))
+                    .map(str::to_string)
+            }
+            _ => None,
+        };
+        // A turn without any prompt is titled from the answer instead.
+        let title_msg = title_msg
+            .filter(|m| !m.trim().is_empty())
+            .or_else(|| Some(last_assistant.clone()).filter(|a| !a.trim().is_empty()));
+        // A new note is dated by the thread's first notification, in its
+        // `created:` as well as its folder and filename.
+        let times = raw_notifications(&raw_path, None)?;
+        let created = resolve_created(times.iter().map(|n| extract_timestamp(n, config.assume_tz)));
+        let md_path = find_or_create_md_path(
+            &md_dir,
+            &thread_id_safe,
+            &uid,
+            title_msg.as_deref(),
+            created,
+            config,
+        )
+        .context("failed to find or create md path")?;
+        let note_cwd = (!config.omit_cwd).then_some(cwd.as_str());
+        let mut text = if md_path.exists() {
+            read_to_string_lossy(&md_path).context("failed to read existing md")?
+        } else {
+            build_codex_note_skeleton(&project, thread_id, note_cwd, created)
+        };
+
+        text = repair_markers(&text).0;
+        text = ensure_turns_block(&text);
+        text = upsert_frontmatter_field(&text, "uid", &format!("\"{uid}\""));
+        if config.omit_cwd {
+            text = remove_cwd_fields(&text);
+        }
+        text = upsert_model_field(&text, notification_str(notification, &["model"]));
+        let project_link = config.vault_link(&base_dir);
+        let style = config.project_path;
+        text = upsert_project_path_field(&text, style, note_cwd, &project, &project_link);
+        let title = note_title(&md_path, &thread_id_safe, formats);
+        text = upsert_aliases_field(&text, title.as_deref());
+        text = merge_frontmatter_template(&text, &read_frontmatter_template(&base_dir));
+
+        let archives = turn_archives(&md_path);
+        let blocks = drop_archived_turns(blocks, &archives)?;
+        let (mut updated, inserted) = apply_turns(&text, &blocks, config.blank_lines());
+        if let Some(max_turns) = config.max_turns.filter(|_| inserted > 0) {
+            updated = roll_over_turns(config, &md_path, &updated, max_turns, &archives)?;
+        }
+        if config.footer_nav {
+            let footer = build_footer_block(&project, &config.vault_link(&base_dir));
+            updated = upsert_footer_block(&updated, &footer);
+        }
+        if inserted > 0 {
+            config
+                .write_output(&md_path, &normalize_markdown(&updated))
+                .context("failed to write md")?;
+            written = Some(md_path.clone());
+        }
+        if spooled {
+            let _ = fs::remove_file(&spool_path);
+        }
+        if inserted == 0 {
+            return Ok(());
+        }
+        if config.result_note && !session_end && !last_assistant.trim().is_empty() {
+            let frontmatter = [
+                ("tool", "Codex CLI"),
+                ("project", project.as_str()),
+                ("thread_id", thread_id),
+            ];
+            write_result_note(
+                config,
+                &base_dir,
+                &md_path,
+                &frontmatter,
+                &last_assistant,
+                config.result_max_chars,
+            )?;
+        }
+
+        if config.daily_digest {
+            let today = Local::now().date_naive();
+            // Turns are dated by their notifications; one without a timestamp
+            // counts only while it is the one being handled.
+            let mut turns = dated_turn_blocks(config, &raw_path, redactor)?;
+            if let (None, Some(turn)) = (extract_timestamp(notification, config.assume_tz), &turn)
+            {
+                turns.push((ts, turn.clone()));
+            }
+            let block = build_digest_block(thread_id, &md_path, &turns, today);
+            if !block.is_empty() {
+                let label = today.format(formats.filename_date()).to_string();
+                let daily = base_dir.join("Daily");
+                let first = turns
+                    .iter()
+                    .map(|(at, _)| *at)
+                    .filter(|at| at.date_naive() == today)
+                    .min();
+                append_to_daily_digest(config, &daily, &label, &thread_id_safe, first, &block)
+                    .context("failed to update daily digest")?;
+            }
+        }
         Ok(())
     })?;
+
+    if let Some(note) = &written {
+        config.refresh_overview(&base_dir, note);
+    }
+    // The index gets each new turn once, after the note, so a broken index
+    // never costs the note its turn.
+    if config.sqlite && appended {
+        let rows = turn_index_messages(notification, &text_options, config.assume_tz);
+        let turn_id = notification_str(notification, &["turn-id", "turn_id", "turnId"]);
+        let indexed = config.ai_dir().and_then(|ai_dir| {
+            index_turn(
+                &index_path(&ai_dir),
+                "Codex CLI",
+                &project,
+                thread_id,
+                turn_id,
+                &rows,
+            )
+        });
+        if let Err(e) = indexed {
+            log::warn(|| format!("failed to update index: {e:#}"));
+        }
+    }
+    Ok(())
+}
+
+pub fn notification_thread_id(notification: &Value) -> String {
+    notification_str(notification, &["thread-id", "thread_id", "threadId"])
+        .filter(|id| !id.trim().is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| synthesized_thread_id(notification, Local::now().date_naive()))
+}
+
+// Without a cwd the project can't be resolved, so reuse the one recorded in
+// this thread's existing note to keep the turn with its siblings.
+fn notification_cwd(notification: &Value, codex_dir: &Path, thread_id: &str) -> String {
+    match notification_str(notification, &["cwd"]).filter(|c| !c.trim().is_empty()) {
+        Some(cwd) => cwd.to_string(),
+        None => inherited_cwd(codex_dir, thread_id).unwrap_or_else(|| ".".to_string()),
+    }
+}
+
+// What `handle_notification` would do with `notification`, for
+// `--validate-payload`; nothing is read from the raw log and nothing is written.
+pub fn validate_notification(config: &Config, notification: &Value) -> Result<String> {
+    let codex_dir = config.ai_dir()?.join(config.codex_dir_name());
+    let thread_id = notification_thread_id(notification);
+    let thread_id_safe = safe_id(&thread_id, "unknown-thread");
+    let cwd = notification_cwd(notification, &codex_dir, &thread_id);
+    let project = config.project_name(&cwd);
+    let md_dir = codex_dir.join(&project).join("Threads");
+    let uid = note_identity("Codex CLI", &thread_id);
+
+    let typ = notification
+        .get("type")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    let status = if config.is_ignored(&thread_id, &cwd) {
+        "ignored (.llmlogsignore)".to_string()
+    } else if is_session_end_notification(notification) || should_process_notification(notification)
+    {
+        "export".to_string()
+    } else {
+        format!("skip (type {typ:?})")
+    };
+    let note = match find_session_note(&md_dir, &thread_id_safe, &uid) {
+        Some(path) => path.display().to_string(),
+        None => {
+            let dir = dated_thread_dir(&md_dir, Local::now().date_naive(), config.monthly_folders);
+            format!("{}/ (new note)", dir.display())
+        }
+    };
+
+    Ok(format!(
+        "thread_id: {thread_id}\ncwd: {cwd}\nproject: {project}\nnote: {note}\nstatus: {status}\n"
+    ))
+}
+
+// Removes what earlier notifications wrote for a thread that is now ignored:
+// the raw log and spool, the note with its turn archives, and everything
+// exported alongside it.
+pub fn purge_thread(config: &Config, base_dir: &Path, thread_id: &str) -> Result<()> {
+    let thread_id_safe = safe_id(thread_id, "unknown-thread");
+    let md_dir = base_dir.join("Threads");
+    let uid = note_identity("Codex CLI", thread_id);
+    let notes = find_session_notes(&md_dir, &thread_id_safe, &uid);
+    let mut paths = vec![
+        base_dir
+            .join("_raw")
+            .join("notify")
+            .join(format!("{thread_id_safe}.jsonl")),
+        md_dir.join(format!(".spool_{thread_id_safe}")),
+    ];
+    for note in &notes {
+        paths.extend(turn_archives(note));
+    }
+    for path in &paths {
+        config.remove_output(path)?;
+    }
+    purge_exported_session(config, base_dir, &notes, thread_id, &thread_id_safe)
+}
+
+// A session-end notification carries no turn; it only forces spooled turns out.
+pub fn is_session_end_notification(notification: &Value) -> bool {
+    let typ = notification
+        .get("type")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    typ.ends_with("session-end") || typ.ends_with("session_end")
+}
+
+fn turn_sentinel(block: &str) -> &str {
+    block.lines().next().unwrap_or("").trim()
+}
+
+// Inserts each turn block whose sentinel is not in the note yet. Returns the
+// updated note and how many blocks were inserted.
+pub fn apply_turns(text: &str, blocks: &[String], blank_lines: usize) -> (String, usize) {
+    let mut text = text.to_string();
+    let mut inserted = 0;
+    for block in blocks {
+        let sentinel = turn_sentinel(block);
+        if sentinel != MISSING_SENTINEL && text.contains(sentinel) {
+            continue;
+        }
+        text = insert_before_end(&text, block, blank_lines);
+        inserted += 1;
+    }
+    (text, inserted)
+}
+
+// Byte offsets of the turn sentinels inside the AUTO TURNS block (the whole
+// text for an archive, which has no block). Only sentinels opening a line
+// count, so one quoted in a reply isn't a turn.
+pub fn turn_starts(text: &str) -> Vec<usize> {
+    let begin = find_marker(text, BEGIN).unwrap_or(0);
+    let end = find_marker(&text[begin..], END).map_or(text.len(), |i| begin + i);
+    text[begin..end]
+        .match_indices("<!-- turn-id:")
+        .map(|(i, _)| begin + i)
+        .filter(|&i| text[..i].ends_with('\n'))
+        .collect()
+}
+
+// When the note holds more than `max_turns` turns, returns the note with only
+// the newest `max_turns` left and the older turn blocks, oldest first.
+pub fn split_oldest_turns(text: &str, max_turns: usize) -> Option<(String, Vec<String>)> {
+    let starts = turn_starts(text);
+    if max_turns == 0 || starts.len() <= max_turns {
+        return None;
+    }
+    let keep_from = starts[starts.len() - max_turns];
+    let old = starts
+        .iter()
+        .zip(starts.iter().skip(1))
+        .take(starts.len() - max_turns)
+        .map(|(&a, &b)| text[a..b].trim_end().to_string())
+        .collect();
+    Some((format!("{}{}", &text[..starts[0]], &text[keep_from..]), old))
+}
+
+pub fn turn_archives(md_path: &Path) -> Vec<PathBuf> {
+    (1..)
+        .map(|n| turn_archive_path(md_path, n))
+        .take_while(|p| p.exists())
+        .collect()
+}
+
+// A turn already rolled into an archive is still "seen": re-sent
+// notifications for it must not reappear in the main note.
+pub fn drop_archived_turns(blocks: Vec<String>, archives: &[PathBuf]) -> Result<Vec<String>> {
+    if archives.is_empty() {
+        return Ok(blocks);
+    }
+    let mut archived = String::new();
+    for path in archives {
+        archived.push_str(&read_to_string_lossy(path).context("failed to read turn archive")?);
+    }
+    Ok(blocks
+        .into_iter()
+        .filter(|b| {
+            let sentinel = turn_sentinel(b);
+            sentinel == MISSING_SENTINEL || !archived.contains(sentinel)
+        })
+        .collect())
+}
+
+// Moves the turns beyond `max_turns` out of the note: the latest archive is
+// topped up to `max_turns` turns first, then new archives are started, each
+// linked from the note just above its remaining turns.
+pub fn roll_over_turns(
+    config: &Config,
+    md_path: &Path,
+    text: &str,
+    max_turns: usize,
+    archives: &[PathBuf],
+) -> Result<String> {
+    let (mut note, old) = match split_oldest_turns(text, max_turns) {
+        Some(split) => split,
+        None => return Ok(text.to_string()),
+    };
+    let mut old = old.into_iter().peekable();
+    let stem = md_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("note");
+
+    if let Some(last) = archives.last() {
+        let archive = read_to_string_lossy(last).context("failed to read turn archive")?;
+        let room = max_turns.saturating_sub(turn_starts(&archive).len());
+        let more: Vec<String> = old.by_ref().take(room).collect();
+        if !more.is_empty() {
+            let archive = format!("{}\n\n{}\n", archive.trim_end(), more.join("\n\n"));
+            config
+                .write_output(last, &normalize_markdown(&archive))
+                .context("failed to write turn archive")?;
+        }
+    }
+
+    let mut n = archives.len();
+    while old.peek().is_some() {
+        n += 1;
+        let path = turn_archive_path(md_path, n);
+        let chunk: Vec<String> = old.by_ref().take(max_turns).collect();
+        let archive = format!("Older turns of [[{stem}]].\n\n{}\n", chunk.join("\n\n"));
+        config
+            .write_output(&path, &normalize_markdown(&archive))
+            .context("failed to write turn archive")?;
+
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default();
+        let first = turn_starts(&note)[0];
+        note = format!(
+            "{}Older turns: [[{name}]]\n\n{}",
+            &note[..first],
+            &note[first..]
+        );
+    }
+    Ok(note)
+}
+
+// One turn waiting in `.spool_<thread>`, stored as a JSONL record. The
+// notification lets a later run flush the spool without a new turn arriving.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SpooledTurn {
+    pub spooled_at: u64,
+    pub notification: Value,
+    pub block: String,
+}
+
+pub fn spool_turn(
+    spool_path: &Path,
+    notification: &Value,
+    block: &str,
+    now: SystemTime,
+) -> Result<()> {
+    let sentinel = turn_sentinel(block);
+    if sentinel != MISSING_SENTINEL
+        && read_spool(spool_path)?
+            .iter()
+            .any(|t| turn_sentinel(&t.block) == sentinel)
+    {
+        return Ok(());
+    }
+
+    let record = SpooledTurn {
+        spooled_at: now
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_secs(),
+        notification: notification.clone(),
+        block: block.trim_end().to_string(),
+    };
+    let line = serde_json::to_string(&record).context("failed to serialize spooled turn")?;
+    let mut f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(spool_path)
+        .with_context(|| format!("failed to open spool: {}", spool_path.display()))?;
+    writeln!(f, "{line}").context("failed to append to spool")?;
     Ok(())
 }
 
+// Lines that are not spool records (e.g. a torn write) are skipped.
+pub fn read_spool(spool_path: &Path) -> Result<Vec<SpooledTurn>> {
+    if !spool_path.exists() {
+        return Ok(Vec::new());
+    }
+    let spool = read_to_string_lossy(spool_path).context("failed to read spool")?;
+    Ok(spool
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+pub fn read_spooled_turns(spool_path: &Path) -> Result<Vec<String>> {
+    Ok(read_spool(spool_path)?
+        .into_iter()
+        .map(|t| format!("{}\n", t.block))
+        .collect())
+}
+
+// Due once the oldest spooled turn has waited `interval`.
+pub fn spool_due(spool_path: &Path, interval: Duration, now: SystemTime) -> Result<bool> {
+    let oldest = match read_spool(spool_path)?.first() {
+        Some(turn) => UNIX_EPOCH + Duration::from_secs(turn.spooled_at),
+        None => return Ok(false),
+    };
+    Ok(now.duration_since(oldest).unwrap_or(Duration::ZERO) >= interval)
+}
+
 pub fn should_process_notification(notification: &Value) -> bool {
     let typ = notification.get("type").and_then(|v| v.as_str()).unwrap_or("");
     if typ == "agent-turn-complete" {
@@ -130,121 +693,328 @@ fn notification_str<'a>(notification: &'a Value, keys: &[&str]) -> Option<&'a st
     None
 }
 
-pub fn build_codex_note_skeleton(project: &str, thread_id: &str, cwd: &str) -> String {
-    let created = Local::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+// Builds the notification Codex would send from flags, for manual runs:
+// `--thread-id <id> [--turn-id <id>] [--cwd <dir>] [--input <msg>]... [--assistant <msg>]
+// [--type <type>]`.
+pub fn notification_from_flags(args: &[String]) -> Result<Value> {
+    let mut notification = serde_json::Map::new();
+    notification.insert("type".into(), "agent-turn-complete".into());
+    let mut inputs = Vec::new();
+    for (flag, value) in parse_cli_flags(args)? {
+        let key = match flag.as_str() {
+            "--input" => {
+                inputs.push(Value::String(value));
+                continue;
+            }
+            "--thread-id" => "thread-id",
+            "--turn-id" => "turn-id",
+            "--cwd" => "cwd",
+            "--assistant" => "last-assistant-message",
+            "--type" => "type",
+            other => return Err(anyhow!("unknown flag: {other}")),
+        };
+        notification.insert(key.into(), Value::String(value));
+    }
+    if !notification.contains_key("thread-id") {
+        return Err(anyhow!("missing --thread-id"));
+    }
+    notification.insert("input-messages".into(), Value::Array(inputs));
+    Ok(Value::Object(notification))
+}
+
+// Without a thread id nothing ties one turn to the next, so this keys on the
+// turn's first input; id-less notifications only share a note when they repeat
+// the same request from the same cwd on the same day.
+pub fn synthesized_thread_id(notification: &Value, date: NaiveDate) -> String {
+    let cwd = notification_str(notification, &["cwd"]).unwrap_or("");
+    let first = match input_messages_of(notification) {
+        Value::Array(arr) => arr
+            .iter()
+            .find_map(|v| v.as_str())
+            .unwrap_or("")
+            .to_string(),
+        Value::String(s) => s,
+        _ => String::new(),
+    };
+    synthesized_id("thread", cwd, &first, date)
+}
+
+fn input_messages_of(notification: &Value) -> Value {
+    notification
+        .get("input-messages")
+        .or_else(|| notification.get("input_messages"))
+        .or_else(|| notification.get("inputMessages"))
+        .cloned()
+        .unwrap_or(Value::Null)
+}
+
+fn last_assistant_of(notification: &Value, text: &TextOptions) -> String {
+    [
+        "last-assistant-message",
+        "last_assistant_message",
+        "lastAssistantMessage",
+    ]
+    .iter()
+    .find_map(|key| notification.get(*key))
+    .map(|v| assistant_text(v, text))
+    .unwrap_or_default()
+}
+
+// Newer Codex builds may send the reply as content parts (`[{"type":"text",
+// ...}]`) or a single object instead of a string. Text is pulled out where
+// there is some; anything else is kept as compact JSON rather than dropped.
+pub fn assistant_text(v: &Value, text: &TextOptions) -> String {
+    let text = match v {
+        Value::Null => return String::new(),
+        Value::Object(obj) => match (obj.get("text"), obj.get("content")) {
+            (Some(Value::String(t)), _) => Some(t.clone()),
+            (_, Some(content)) => text.extract(content),
+            _ => None,
+        },
+        other => text.extract(other),
+    };
+    text.unwrap_or_else(|| v.to_string())
+}
+
+// The turn block a notification contributes, headed with `ts`, or `None` for
+// a session end.
+pub fn turn_block_for(
+    config: &Config,
+    notification: &Value,
+    ts: Option<DateTime<Local>>,
+) -> Option<String> {
+    if is_session_end_notification(notification) {
+        return None;
+    }
+    let turn = Turn {
+        turn_id: notification_str(notification, &["turn-id", "turn_id", "turnId"])
+            .map(str::to_string),
+        input_messages: input_messages_of(notification),
+        last_assistant: last_assistant_of(notification, &config.text_options()),
+        ts,
+    };
+    Some(render_turn(
+        &turn,
+        &config.formats.time,
+        config.missing_time.as_deref(),
+        config.blank_lines(),
+    ))
+}
+
+// User inputs plus the assistant reply, if any.
+pub fn notification_message_count(notification: &Value) -> usize {
+    let inputs = match input_messages_of(notification) {
+        Value::Array(arr) => arr
+            .iter()
+            .filter(|v| v.as_str().is_some_and(|s| !s.trim().is_empty()))
+            .count(),
+        Value::String(s) if !s.trim().is_empty() => 1,
+        _ => 0,
+    };
+    let answer = last_assistant_of(notification, &TextOptions::default());
+    inputs + usize::from(!answer.trim().is_empty())
+}
+
+// The raw log keeps notifications as received unless `LLM_LOGS_REDACT_RAW` is
+// set, so anything rendered from it goes through `redactor` again.
+fn raw_notifications(raw_path: &Path, redactor: Option<&SecretRedactor>) -> Result<Vec<Value>> {
+    if !raw_path.exists() {
+        return Ok(Vec::new());
+    }
+    let raw = read_to_string_lossy(raw_path).context("failed to read raw notify log")?;
+    Ok(raw
+        .lines()
+        .filter_map(|l| serde_json::from_str::<Value>(l).ok())
+        .map(|v| match redactor {
+            Some(r) => r.redact_json(&v),
+            None => v,
+        })
+        .collect())
+}
+
+pub fn raw_message_count(raw_path: &Path) -> Result<usize> {
+    Ok(raw_notifications(raw_path, None)?
+        .iter()
+        .map(notification_message_count)
+        .sum())
+}
+
+// One notification's messages for the SQLite index: its prompts, then the
+// answer, all stamped with the turn's time.
+pub fn turn_index_messages(
+    notification: &Value,
+    text: &TextOptions,
+    zone: AssumedZone,
+) -> Vec<IndexedMessage> {
+    let ts =
+        extract_timestamp(notification, zone).map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true));
+    let inputs = match input_messages_of(notification) {
+        Value::Array(arr) => arr
+            .iter()
+            .filter_map(|v| v.as_str())
+            .map(str::to_string)
+            .collect(),
+        Value::String(s) => vec![s],
+        _ => Vec::new(),
+    };
+    let user = inputs.into_iter().map(|text| ("user", text));
+    let assistant = ("assistant", last_assistant_of(notification, text));
+    user.chain(std::iter::once(assistant))
+        .filter(|(_, text)| !text.trim().is_empty())
+        .map(|(role, text)| IndexedMessage {
+            role: role.to_string(),
+            ts: ts.clone(),
+            text: text.trim().to_string(),
+        })
+        .collect()
+}
+
+// Turns rebuilt from the raw log keep the time their notification carries;
+// without one they get the `LLM_LOGS_MISSING_TIME` heading, not today's time.
+// The raw log's turns that carry a timestamp, with it.
+pub fn dated_turn_blocks(
+    config: &Config,
+    raw_path: &Path,
+    redactor: Option<&SecretRedactor>,
+) -> Result<Vec<(DateTime<Local>, String)>> {
+    Ok(raw_notifications(raw_path, redactor)?
+        .iter()
+        .filter_map(|n| {
+            let at = extract_timestamp(n, config.assume_tz)?;
+            Some((at, turn_block_for(config, n, Some(at))?))
+        })
+        .collect())
+}
+
+pub fn raw_turn_blocks(
+    config: &Config,
+    raw_path: &Path,
+    redactor: Option<&SecretRedactor>,
+) -> Result<Vec<String>> {
+    Ok(raw_notifications(raw_path, redactor)?
+        .iter()
+        .filter_map(|n| turn_block_for(config, n, extract_timestamp(n, config.assume_tz)))
+        .collect())
+}
+
+// Looks for the thread's note in every project folder and returns its `cwd:`.
+pub fn inherited_cwd(codex_dir: &Path, thread_id: &str) -> Option<String> {
+    let thread_id_safe = safe_id(thread_id, "unknown-thread");
+    let uid = note_identity("Codex CLI", thread_id);
+    let projects = fs::read_dir(codex_dir).ok()?;
+    projects.flatten().find_map(|entry| {
+        let threads = entry.path().join("Threads");
+        let md_path = find_session_note(&threads, &thread_id_safe, &uid)?;
+        let frontmatter = read_frontmatter(&md_path)?;
+        frontmatter_field(&frontmatter, "cwd").filter(|c| !c.trim().is_empty())
+    })
+}
+
+pub fn build_codex_note_skeleton(
+    project: &str,
+    thread_id: &str,
+    cwd: Option<&str>,
+    created: DateTime<Local>,
+) -> String {
+    let created = created.to_rfc3339_opts(SecondsFormat::Secs, true);
 
     let project_q = yaml_quote(project);
+    let project_tag = yaml_scalar(project);
     let thread_q = yaml_quote(thread_id);
-    let cwd_q = yaml_quote(cwd);
+    let uid = note_identity("Codex CLI", thread_id);
+    // `None` (LLM_LOGS_OMIT_CWD) leaves the line out altogether.
+    let cwd_line = match cwd {
+        Some(cwd) => format!("cwd: \"{}\"\n", yaml_quote(cwd)),
+        None => String::new(),
+    };
 
     format!(
         r#"---
 tool: "Codex CLI"
 project: "{project_q}"
 thread_id: "{thread_q}"
-cwd: "{cwd_q}"
-created: "{created}"
+uid: "{uid}"
+{cwd_line}created: "{created}"
 tags:
   - ai-log
   - codex
-  - {project_q}
+  - {project_tag}
 ---
 
 "#
     )
 }
 
-pub fn ensure_turns_block(s: &str) -> String {
-    if s.contains(BEGIN) && s.contains(END) {
-        return s.to_string();
-    }
-    format!("{}\n\n{}\n## Turns (auto)\n{}\n", s.trim_end(), BEGIN, END)
+pub fn repair_markers(md: &str) -> (String, bool) {
+    repair_markers_with(md, BEGIN, END)
 }
 
-pub fn build_turn_block(
-    _turn_id: &str,
-    input_messages: &Value,
-    last_assistant: &str,
-    sentinel: &str,
+// Collects the turns of the note whose headings fall on `date`; an empty string
+// means the thread has nothing to contribute to that day's digest.
+// The turns dated `date`, each once in its latest rendering.
+pub fn build_digest_block(
+    thread_id: &str,
+    md_path: &Path,
+    turns: &[(DateTime<Local>, String)],
+    date: NaiveDate,
 ) -> String {
-    let now = Local::now().format("%Y-%m-%d %H:%M:%S %z").to_string();
-
-    let user_part = match input_messages {
-        Value::Array(arr) => {
-            let mut lines: Vec<String> = Vec::new();
-            for v in arr {
-                if let Some(s) = v.as_str() {
-                    let s = s.trim();
-                    if !s.is_empty() {
-                        lines.push(format!("- {s}"));
-                    }
-                }
-            }
-            if lines.is_empty() {
-                "- (empty)".to_string()
-            } else {
-                lines.join("\n")
-            }
+    let mut todays: Vec<&str> = Vec::new();
+    for (_, block) in turns.iter().filter(|(at, _)| at.date_naive() == date) {
+        let sentinel = turn_sentinel(block);
+        if sentinel != MISSING_SENTINEL {
+            todays.retain(|t| turn_sentinel(t) != sentinel);
         }
-        Value::String(s) => {
-            let s = s.trim();
-            if s.is_empty() {
-                "(empty)".to_string()
-            } else {
-                s.to_string()
-            }
-        }
-        _ => "- (empty)".to_string(),
-    };
-
-    format!(
-        r#"{sentinel}
-
-### {now} User
-{user_part}
+        todays.push(block.trim_end());
+    }
+    if todays.is_empty() {
+        return String::new();
+    }
 
-### {now} Assistant
-{assistant}
+    let stem = md_path
+        .file_stem()
+        .and_then(|n| n.to_str())
+        .unwrap_or(thread_id);
 
-"#,
-        assistant = last_assistant.trim_end()
+    format!(
+        "## Thread {thread_id} ([[{stem}]])\n\n{}\n",
+        todays.join("\n\n")
     )
 }
 
-pub fn insert_before_end(s: &str, block: &str) -> String {
-    if let Some(pos) = s.find(END) {
-        let (pre, post) = s.split_at(pos);
-        format!(
-            "{pre}\n\n{block}\n{post}",
-            pre = pre.trim_end(),
-            block = block.trim_end()
-        )
-    } else {
-        format!("{}\n\n{}", s.trim_end(), block.trim_end())
-    }
-}
-
-fn find_or_create_md_path(md_dir: &Path, thread_id: &str, first_user_msg: Option<&str>) -> Result<PathBuf> {
+fn find_or_create_md_path(
+    md_dir: &Path,
+    thread_id: &str,
+    uid: &str,
+    title_msg: Option<&str>,
+    created: DateTime<Local>,
+    config: &Config,
+) -> Result<PathBuf> {
     if let Some(existing) = find_md_file_containing_id(md_dir, thread_id) {
-        if let Some(migrated) = maybe_migrate_legacy_md_path(md_dir, &existing) {
+        if let Some(migrated) = maybe_migrate_legacy_md_path(config, md_dir, &existing) {
             return Ok(migrated);
         }
         return Ok(existing);
     }
+    // A note renamed away from its thread id is still found by its `uid:`.
+    if let Some(renamed) = find_md_file_by_uid(md_dir, uid) {
+        return Ok(renamed);
+    }
 
-    let now = Local::now();
-    let day_dir = md_dir
-        .join(now.format("%Y").to_string())
-        .join(now.format("%m").to_string())
-        .join(now.format("%d").to_string());
+    let date = created.date_naive();
+    let day_dir = dated_thread_dir(md_dir, date, config.monthly_folders);
     fs::create_dir_all(&day_dir).context("failed to create dated Threads dir")?;
 
-    let title = generate_title(first_user_msg);
-    let filename = format!("{title}_{thread_id}.md");
+    let title = generate_title(title_msg, config.title_llm());
+    let budget = filename_budget(&day_dir, MAX_PATH_LEN);
+    let filename = note_filename_within(&config.formats, date, &title, thread_id, budget);
     Ok(day_dir.join(filename))
 }
 
-fn maybe_migrate_legacy_md_path(md_dir: &Path, existing: &Path) -> Option<PathBuf> {
+fn maybe_migrate_legacy_md_path(
+    config: &Config,
+    md_dir: &Path,
+    existing: &Path,
+) -> Option<PathBuf> {
     if existing.parent()? != md_dir {
         return None;
     }
@@ -260,7 +1030,7 @@ fn maybe_migrate_legacy_md_path(md_dir: &Path, existing: &Path) -> Option<PathBu
     }
 
     fs::create_dir_all(&target_dir).ok()?;
-    fs::rename(existing, &target_path).ok()?;
+    config.move_output(existing, &target_path).ok()?;
     Some(target_path)
 }
 
@@ -292,6 +1062,18 @@ fn split_legacy_dated_filename(name: &str) -> Option<(&str, &str, &str, &str)> {
     Some((yyyy, mm, dd, rest))
 }
 
+pub fn extract_user_msgs(input_messages: &Value) -> Vec<String> {
+    match input_messages {
+        Value::Array(arr) => arr
+            .iter()
+            .filter_map(|v| v.as_str())
+            .map(str::to_string)
+            .collect(),
+        Value::String(s) => vec![s.clone()],
+        _ => Vec::new(),
+    }
+}
+
 pub fn extract_first_user_msg(input_messages: &Value) -> Option<String> {
     match input_messages {
         Value::Array(arr) => arr.first().and_then(|v| v.as_str()).map(|s| s.to_string()),
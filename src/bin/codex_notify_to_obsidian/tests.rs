@@ -1,4 +1,7 @@
-use ai_log_exporter::{sanitize_title, safe_name, yaml_quote};
+use ai_log_exporter::codex::{render_turn, Turn};
+use ai_log_exporter::{
+    clean_llm_title, result_note_path, safe_name, sanitize_title, yaml_quote, DEFAULT_TIME_FMT,
+};
 use super::*;
 
 // ========================================
@@ -62,6 +65,74 @@ fn test_yaml_quote_escapes_special_chars() {
     assert_eq!(yaml_quote(r#"foo"bar"#), r#"foo\"bar"#);
 }
 
+// ========================================
+// clean_llm_title tests
+// ========================================
+
+#[test]
+fn test_clean_llm_title_strips_quotes() {
+    assert_eq!(clean_llm_title("\"fix-login\""), "fix-login");
+    assert_eq!(clean_llm_title("'fix-login'\n"), "fix-login");
+    assert_eq!(clean_llm_title("“fix-login”"), "fix-login");
+}
+
+#[test]
+fn test_clean_llm_title_strips_code_fences() {
+    assert_eq!(clean_llm_title("```fix-login```"), "fix-login");
+    assert_eq!(clean_llm_title("`fix-login`"), "fix-login");
+    assert_eq!(clean_llm_title("```\nfix-login\n```\n"), "fix-login");
+    assert_eq!(clean_llm_title("```text\nfix-login\n```"), "fix-login");
+}
+
+#[test]
+fn test_clean_llm_title_takes_first_non_empty_line() {
+    let raw = "\n\nfix-login-bug\n\nThis title summarizes the login fix.\n";
+    assert_eq!(clean_llm_title(raw), "fix-login-bug");
+    assert_eq!(sanitize_title(&clean_llm_title(raw)), "fix-login-bug");
+}
+
+#[test]
+fn test_clean_llm_title_empty_output() {
+    assert_eq!(clean_llm_title(""), "");
+    assert_eq!(clean_llm_title("```\n```"), "");
+    assert_eq!(clean_llm_title("\"\""), "");
+}
+
+#[test]
+fn test_title_from_llm_output_rejects_paths() {
+    use ai_log_exporter::{is_safe_title, title_from_llm_output};
+
+    for raw in [
+        "../../evil",
+        "```\n/etc/passwd\n```",
+        "src/main",
+        "..\\..\\x",
+        "\"a/../b\"",
+    ] {
+        let title = title_from_llm_output(raw);
+        assert!(
+            title.as_deref().is_none_or(is_safe_title),
+            "{raw:?} -> {title:?}"
+        );
+    }
+    assert_eq!(title_from_llm_output("../../evil").as_deref(), Some("evil"));
+    assert_eq!(
+        title_from_llm_output("src/main").as_deref(),
+        Some("src-main")
+    );
+    assert_eq!(title_from_llm_output("../.."), None);
+    assert_eq!(
+        title_from_llm_output("fix-login").as_deref(),
+        Some("fix-login")
+    );
+
+    assert!(!is_safe_title("../evil"));
+    assert!(!is_safe_title("src/main"));
+    assert!(!is_safe_title("a\\b"));
+    assert!(!is_safe_title("a..b"));
+    assert!(!is_safe_title(""));
+}
+
 // ========================================
 // sanitize_title tests
 // ========================================
@@ -120,104 +191,1417 @@ fn test_extract_first_user_msg_from_null() {
     assert_eq!(extract_first_user_msg(&v), None);
 }
 
+#[test]
+fn test_extract_user_msgs_keeps_every_prompt() {
+    assert_eq!(
+        extract_user_msgs(&serde_json::json!(["a", 1, "b"])),
+        ["a", "b"]
+    );
+    assert_eq!(extract_user_msgs(&serde_json::json!("single")), ["single"]);
+    assert!(extract_user_msgs(&Value::Null).is_empty());
+}
+
+#[test]
+fn test_handle_notification_without_input_messages() {
+    let dir = temp_test_dir("handle-no-input");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let config = Config {
+        vault: Some(dir.clone()),
+        ai_root: Some(PathBuf::from("llms")),
+        daily_digest: true,
+        ..test_config()
+    };
+    let notification = serde_json::json!({
+        "type": "agent-turn-complete",
+        "thread-id": "no-input-thread",
+        "turn-id": "t1",
+        "cwd": cwd.to_str().unwrap(),
+        "last-assistant-message": "Refactored the parser module",
+    });
+
+    handle_notification(&config, &notification).unwrap();
+
+    let base_dir = dir.join("llms").join("Codex").join("proj");
+    let note = find_md_file_containing_id(&base_dir.join("Threads"), "no-input-thread").unwrap();
+    let name = note.file_name().unwrap().to_str().unwrap();
+    assert!(!name.contains("untitled"), "{name}");
+    let text = fs::read_to_string(&note).unwrap();
+    assert!(!text.contains(" User\n"));
+    assert!(text.contains("Refactored the parser module"));
+    let daily = fs::read_dir(base_dir.join("Daily"))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    let digest = fs::read_to_string(daily.path()).unwrap();
+    assert!(digest.contains("Refactored the parser module"));
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
 // ========================================
-// ensure_turns_block tests
+// build_codex_note_skeleton tests
 // ========================================
 
 #[test]
-fn test_ensure_turns_block_adds_markers_when_missing() {
-    let input = "# Title\n\nSome content";
-    let result = ensure_turns_block(input);
+fn test_build_codex_note_skeleton_contains_required_fields() {
+    let result = build_codex_note_skeleton(
+        "my-project",
+        "thread-123",
+        Some("/path/to/cwd"),
+        Local::now(),
+    );
+
+    assert!(result.contains("tool: \"Codex CLI\""));
+    assert!(result.contains("project: \"my-project\""));
+    assert!(result.contains("thread_id: \"thread-123\""));
+    let uid = ai_log_exporter::note_identity("Codex CLI", "thread-123");
+    assert!(result.contains(&format!("uid: \"{uid}\"")));
+    assert!(result.contains("cwd: \"/path/to/cwd\""));
+    assert!(result.contains("tags:"));
+    assert!(result.contains("- ai-log"));
+    assert!(result.contains("- codex"));
+}
+
+#[test]
+fn test_build_codex_note_skeleton_omits_cwd() {
+    use ai_log_exporter::remove_frontmatter_field;
 
-    assert!(result.contains(BEGIN));
-    assert!(result.contains(END));
-    assert!(result.contains("## Turns (auto)"));
+    let result = build_codex_note_skeleton("my-project", "thread-123", None, Local::now());
+    assert!(!result.contains("cwd:"));
+    assert!(result.contains("thread_id: \"thread-123\""));
+
+    let with_cwd = build_codex_note_skeleton("p", "thread-123", Some("/tmp"), Local::now());
+    let stripped = remove_frontmatter_field(&with_cwd, "cwd");
+    assert!(!stripped.contains("cwd:"));
+    assert_eq!(stripped.lines().count(), with_cwd.lines().count() - 1);
+    let plain = "no frontmatter\ncwd: x\n";
+    assert_eq!(remove_frontmatter_field(plain, "cwd"), plain);
 }
 
 #[test]
-fn test_ensure_turns_block_preserves_existing() {
-    let input = format!("# Title\n\n{}\nexisting\n{}", BEGIN, END);
-    let result = ensure_turns_block(&input);
+fn test_build_codex_note_skeleton_has_no_mojibake_heading() {
+    let result = build_codex_note_skeleton("my-project", "thread-123", Some("/tmp"), Local::now());
 
-    assert_eq!(result, input);
+    // An em-dash decoded as Latin-1 once shipped in a `# Codex thread — …` heading;
+    // the skeleton is frontmatter only, and the title comes from the file name.
+    assert!(!result.contains("\u{e2}\u{20ac}\u{201d}"));
+    assert!(!result.contains("# Codex thread"));
+    assert!(result.ends_with("---\n\n"));
 }
 
 // ========================================
-// insert_before_end tests
+// build_digest_block tests
 // ========================================
 
+fn dated_turn(id: &str, text: &str, at: &str) -> (DateTime<Local>, String) {
+    let at = ai_log_exporter::parse_rfc3339_local(at).unwrap();
+    let turn = Turn {
+        ts: Some(at),
+        ..codex_turn(Some(id), text, "reply")
+    };
+    (at, render_turn(&turn, DEFAULT_TIME_FMT, None, 1))
+}
+
 #[test]
-fn test_insert_before_end_inserts_correctly() {
-    let input = format!("# Title\n\n{}\n{}", BEGIN, END);
-    let block = "new content";
-    let result = insert_before_end(&input, block);
+fn test_build_digest_block_keeps_only_that_days_turns() {
+    let turns = [
+        dated_turn("t1", "old", "2024-01-01T10:00:00+00:00"),
+        dated_turn("t2", "new", "2024-01-02T10:00:00+00:00"),
+    ];
+    let date = turns[1].0.date_naive();
+    let result = build_digest_block("th", Path::new("title_th.md"), &turns, date);
+
+    assert!(result.contains("## Thread th ([[title_th]])"));
+    assert!(result.contains("<!-- turn-id:t2 -->"));
+    assert!(!result.contains("<!-- turn-id:t1 -->"));
+}
 
-    assert!(result.contains("new content"));
-    let end_pos = result.find(END).unwrap();
-    let content_pos = result.find("new content").unwrap();
-    assert!(content_pos < end_pos);
+#[test]
+fn test_build_digest_block_empty_without_turns() {
+    let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+    assert!(build_digest_block("th", Path::new("n.md"), &[], date).is_empty());
 }
 
+// The heading's time format plays no part, so a date-less one still works.
 #[test]
-fn test_insert_before_end_appends_when_no_end_marker() {
-    let input = "# Title\n\nSome content";
-    let block = "new content";
-    let result = insert_before_end(input, block);
+fn test_build_digest_block_with_time_only_format() {
+    let at = ai_log_exporter::parse_rfc3339_local("2024-01-02T15:00:00+00:00").unwrap();
+    let turn = Turn {
+        ts: Some(at),
+        ..codex_turn(Some("t1"), "new", "reply")
+    };
+    let turns = [(at, render_turn(&turn, "%H:%M", None, 1))];
+    let result = build_digest_block("th", Path::new("n.md"), &turns, at.date_naive());
 
-    assert!(result.contains("new content"));
-    assert!(result.contains("# Title"));
+    assert!(result.contains("<!-- turn-id:t1 -->"));
+}
+
+#[test]
+fn test_build_digest_block_keeps_one_copy_of_a_redelivered_turn() {
+    let turns = [
+        dated_turn("t1", "first", "2024-01-02T10:00:00+00:00"),
+        dated_turn("t1", "first", "2024-01-02T10:00:05+00:00"),
+    ];
+    let result = build_digest_block("th", Path::new("n.md"), &turns, turns[0].0.date_naive());
+
+    assert_eq!(result.matches("<!-- turn-id:t1 -->").count(), 1);
 }
 
 // ========================================
-// build_turn_block tests
+// raw notify log tests
 // ========================================
 
+fn temp_test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "ai-log-exporter-codex-test-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Tests name notes without running the real LLM.
+fn test_config() -> Config {
+    Config {
+        plain_titles: true,
+        ..Config::default()
+    }
+}
+
+#[test]
+fn test_append_jsonl_line_dedup_skips_identical_adjacent_lines() {
+    let dir = temp_test_dir("raw-dedup");
+    let raw = dir.join("_raw").join("notify").join("thread.jsonl");
+
+    let a = serde_json::json!({"type":"agent-turn-complete","turn-id":"1"}).to_string();
+    let b = serde_json::json!({"type":"agent-turn-complete","turn-id":"2"}).to_string();
+
+    assert!(append_jsonl_line_dedup(&raw, &a).unwrap());
+    assert!(!append_jsonl_line_dedup(&raw, &a).unwrap());
+    assert!(append_jsonl_line_dedup(&raw, &b).unwrap());
+    assert!(append_jsonl_line_dedup(&raw, &a).unwrap());
+
+    let content = fs::read_to_string(&raw).unwrap();
+    assert_eq!(content, format!("{a}\n{b}\n{a}\n"));
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
 #[test]
-fn test_build_turn_block_with_array_input() {
-    let input = serde_json::json!(["user message 1", "user message 2"]);
-    let sentinel = "<!-- turn-id:test123 -->";
-    let result = build_turn_block("test123", &input, "assistant response", sentinel);
+fn test_append_jsonl_line_dedup_ignores_suffix_matches() {
+    let dir = temp_test_dir("raw-suffix");
+    let raw = dir.join("thread.jsonl");
+
+    assert!(append_jsonl_line_dedup(&raw, "x{\"a\":1}").unwrap());
+    assert!(append_jsonl_line_dedup(&raw, "{\"a\":1}").unwrap());
 
-    assert!(result.contains(sentinel));
-    assert!(result.contains("- user message 1"));
-    assert!(result.contains("- user message 2"));
-    assert!(result.contains("assistant response"));
-    assert!(result.contains("User"));
-    assert!(result.contains("Assistant"));
+    assert_eq!(fs::read_to_string(&raw).unwrap(), "x{\"a\":1}\n{\"a\":1}\n");
+
+    let _ = fs::remove_dir_all(&dir);
 }
 
 #[test]
-fn test_build_turn_block_with_string_input() {
-    let input = serde_json::json!("single user message");
-    let sentinel = "<!-- turn-id:test456 -->";
-    let result = build_turn_block("test456", &input, "response", sentinel);
+fn test_append_jsonl_line_dedup_concurrent_appenders_keep_lines_whole() {
+    let dir = temp_test_dir("raw-concurrent");
+    let raw = dir.join("_raw").join("notify").join("thread.jsonl");
+
+    let handles: Vec<_> = (0..8)
+        .map(|writer| {
+            let raw = raw.clone();
+            std::thread::spawn(move || {
+                for turn in 0..10 {
+                    let line = serde_json::json!({
+                        "writer": writer,
+                        "turn": turn,
+                        "payload": "x".repeat(64 * 1024),
+                    });
+                    append_jsonl_line_dedup(&raw, &line.to_string()).unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    let content = fs::read_to_string(&raw).unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(lines.len(), 80);
+    for line in lines {
+        serde_json::from_str::<Value>(line).unwrap();
+    }
+    assert_eq!(fs::read_dir(raw.parent().unwrap()).unwrap().count(), 1);
 
-    assert!(result.contains("single user message"));
-    assert!(result.contains("response"));
+    let _ = fs::remove_dir_all(&dir);
 }
 
 #[test]
-fn test_build_turn_block_with_empty_array() {
-    let input = serde_json::json!([]);
-    let sentinel = "<!-- turn-id:test -->";
-    let result = build_turn_block("test", &input, "response", sentinel);
+fn test_index_turn_appends_each_turn_once() {
+    use ai_log_exporter::index::index_turn;
 
-    assert!(result.contains("- (empty)"));
+    let dir = temp_test_dir("index_turn");
+    let db = dir.join("index.sqlite");
+    let turn = |id: &str, input: &str| {
+        serde_json::json!({
+            "turn-id": id,
+            "input-messages": [input, " "],
+            "last-assistant-message": format!("re: {input}"),
+        })
+    };
+    for n in [turn("a", "one"), turn("b", "two"), turn("a", "one")] {
+        let rows = turn_index_messages(&n, &TextOptions::default(), AssumedZone::Local);
+        let turn_id = n["turn-id"].as_str();
+        index_turn(&db, "Codex CLI", "proj", "t1", turn_id, &rows).unwrap();
+    }
+
+    let conn = rusqlite::Connection::open(&db).unwrap();
+    let mut stmt = conn
+        .prepare("SELECT idx, role, text FROM messages WHERE session_id = 't1' ORDER BY idx")
+        .unwrap();
+    let rows: Vec<(i64, String, String)> = stmt
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let rows: Vec<(i64, &str, &str)> = rows
+        .iter()
+        .map(|(i, role, text)| (*i, role.as_str(), text.as_str()))
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            (0, "user", "one"),
+            (1, "assistant", "re: one"),
+            (2, "user", "two"),
+            (3, "assistant", "re: two")
+        ]
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_index_turn_without_turn_id_skips_redelivery() {
+    use ai_log_exporter::index::index_turn;
+
+    let dir = temp_test_dir("index_turn_no_id");
+    let db = dir.join("index.sqlite");
+    let turn = |input: &str| {
+        serde_json::json!({
+            "input-messages": [input],
+            "last-assistant-message": format!("re: {input}"),
+        })
+    };
+    let mut written = Vec::new();
+    for n in [turn("one"), turn("one"), turn("two"), turn("one")] {
+        let rows = turn_index_messages(&n, &TextOptions::default(), AssumedZone::Local);
+        written.push(index_turn(&db, "Codex CLI", "proj", "t1", None, &rows).unwrap());
+    }
+
+    assert_eq!(written, [true, false, true, true]);
+    let conn = rusqlite::Connection::open(&db).unwrap();
+    let count: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM messages WHERE session_id = 't1'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(count, 6);
+
+    let _ = fs::remove_dir_all(&dir);
 }
 
 // ========================================
-// build_codex_note_skeleton tests
+// repair_markers tests
 // ========================================
 
 #[test]
-fn test_build_codex_note_skeleton_contains_required_fields() {
-    let result = build_codex_note_skeleton("my-project", "thread-123", "/path/to/cwd");
+fn test_repair_markers_then_ensure_turns_block_keeps_single_pair() {
+    let md = format!("# Title\n\n{BEGIN}\n## Turns (auto)\n<!-- turn-id:a -->\n");
+    let (fixed, changed) = repair_markers(&md);
+    let result = ensure_turns_block(&fixed);
 
-    assert!(result.contains("tool: \"Codex CLI\""));
-    assert!(result.contains("project: \"my-project\""));
-    assert!(result.contains("thread_id: \"thread-123\""));
-    assert!(result.contains("cwd: \"/path/to/cwd\""));
-    assert!(result.contains("tags:"));
-    assert!(result.contains("- ai-log"));
-    assert!(result.contains("- codex"));
+    assert!(changed);
+    assert_eq!(result.matches(BEGIN).count(), 1);
+    assert_eq!(result.matches(END).count(), 1);
+    assert!(result.find("<!-- turn-id:a -->").unwrap() < result.find(END).unwrap());
+}
+
+fn blank_lines_before(text: &str, marker: &str) -> Vec<usize> {
+    text.match_indices(marker)
+        .map(|(i, _)| text[..i].chars().rev().take_while(|c| *c == '\n').count() - 1)
+        .collect()
+}
+
+#[test]
+fn test_turn_spacing_applies_within_and_between_turns() {
+    for blank_lines in [1, 2] {
+        let blocks: Vec<String> = ["t1", "t2"]
+            .iter()
+            .map(|id| render_turn(&codex_turn(Some(id), "q", "a"), "%H:%M", None, blank_lines))
+            .collect();
+        let (note, _) = apply_turns(&ensure_turns_block("# T"), &blocks, blank_lines);
+
+        assert_eq!(blank_lines_before(&note, "### "), vec![blank_lines; 4]);
+        assert_eq!(
+            blank_lines_before(&note, "<!-- turn-id:"),
+            vec![blank_lines; 2]
+        );
+    }
+}
+
+// ========================================
+// spool and flush tests
+// ========================================
+
+fn codex_turn(id: Option<&str>, text: &str, reply: &str) -> Turn {
+    Turn {
+        turn_id: id.map(str::to_string),
+        input_messages: serde_json::json!([text]),
+        last_assistant: reply.to_string(),
+        ts: Some(Local::now()),
+    }
+}
+
+fn turn(id: &str, text: &str) -> String {
+    render_turn(
+        &codex_turn(Some(id), text, "reply"),
+        DEFAULT_TIME_FMT,
+        None,
+        1,
+    )
+}
+
+#[test]
+fn test_spool_and_flush_coalesces_turns_into_one_write() {
+    let dir = temp_test_dir("spool");
+    let spool = dir.join(".spool_thread");
+
+    let (n, now) = (serde_json::json!({}), SystemTime::now());
+    spool_turn(&spool, &n, &turn("t1", "first"), now).unwrap();
+    spool_turn(&spool, &n, &turn("t2", "second"), now).unwrap();
+    spool_turn(&spool, &n, &turn("t1", "first"), now).unwrap();
+
+    let blocks = read_spooled_turns(&spool).unwrap();
+    assert_eq!(blocks.len(), 2);
+
+    let note = ensure_turns_block("# Title");
+    let (updated, inserted) = apply_turns(&note, &blocks, 1);
+
+    assert_eq!(inserted, 2);
+    assert!(updated.find("- first").unwrap() < updated.find("- second").unwrap());
+    assert!(updated.find("- second").unwrap() < updated.find(END).unwrap());
+
+    let (again, inserted) = apply_turns(&updated, &blocks, 1);
+    assert_eq!(inserted, 0);
+    assert_eq!(again, updated);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_spool_keeps_every_turn_without_id() {
+    let dir = temp_test_dir("spool-missing");
+    let spool = dir.join(".spool_thread");
+    let block = render_turn(&codex_turn(None, "q", "a"), DEFAULT_TIME_FMT, None, 1);
+
+    let (n, now) = (serde_json::json!({}), SystemTime::now());
+    spool_turn(&spool, &n, &block, now).unwrap();
+    spool_turn(&spool, &n, &block, now).unwrap();
+
+    let blocks = read_spooled_turns(&spool).unwrap();
+    assert_eq!(blocks.len(), 2);
+    let (_, inserted) = apply_turns(&ensure_turns_block("# T"), &blocks, 1);
+    assert_eq!(inserted, 2);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_spool_keeps_a_reply_quoting_the_turn_marker_in_one_turn() {
+    let dir = temp_test_dir("spool-quoted-marker");
+    let spool = dir.join(".spool_thread");
+    let reply = "notes look like `<!-- turn-id:x -->` inside";
+    let block = render_turn(
+        &codex_turn(Some("t1"), "q", reply),
+        DEFAULT_TIME_FMT,
+        None,
+        1,
+    );
+
+    spool_turn(&spool, &serde_json::json!({}), &block, SystemTime::now()).unwrap();
+
+    let blocks = read_spooled_turns(&spool).unwrap();
+    assert_eq!(blocks, vec![format!("{}\n", block.trim_end())]);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_spool_due_respects_interval() {
+    let dir = temp_test_dir("flush-due");
+    let spool = dir.join(".spool_thread");
+    let interval = Duration::from_secs(30);
+    let now = SystemTime::now();
+
+    assert!(!spool_due(&spool, interval, now).unwrap());
+
+    spool_turn(&spool, &serde_json::json!({}), &turn("t1", "first"), now).unwrap();
+    let later = now + Duration::from_secs(20);
+    spool_turn(&spool, &serde_json::json!({}), &turn("t2", "second"), later).unwrap();
+    assert!(!spool_due(&spool, interval, now + Duration::from_secs(5)).unwrap());
+    assert!(spool_due(&spool, interval, now + Duration::from_secs(31)).unwrap());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_stale_spool_is_flushed_without_a_new_turn() {
+    let dir = temp_test_dir("flush-stale");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let interval = Duration::from_secs(30);
+    let config = Config {
+        vault: Some(dir.clone()),
+        ai_root: Some(PathBuf::from("llms")),
+        codex_debounce: Some(interval),
+        ..test_config()
+    };
+    let notification = |thread: &str| {
+        serde_json::json!({
+            "type": "agent-turn-complete",
+            "thread-id": thread,
+            "turn-id": "t1",
+            "cwd": cwd.to_str().unwrap(),
+            "input-messages": ["question"],
+            "last-assistant-message": "answer",
+        })
+    };
+    let threads = dir.join("llms").join("Codex").join("proj").join("Threads");
+
+    handle_notification(&config, &notification("quiet")).unwrap();
+    assert!(find_md_file_containing_id(&threads, "quiet").is_none());
+    assert!(threads.join(".spool_quiet").exists());
+
+    // A later run for another thread flushes it once it has waited long enough.
+    let now = SystemTime::now();
+    flush_spools(&config, interval, now).unwrap();
+    assert!(threads.join(".spool_quiet").exists());
+    flush_spools(&config, interval, now + Duration::from_secs(31)).unwrap();
+    let note = find_md_file_containing_id(&threads, "quiet").unwrap();
+    assert!(fs::read_to_string(&note)
+        .unwrap()
+        .contains("<!-- turn-id:t1 -->"));
+    assert!(!threads.join(".spool_quiet").exists());
+    let raw = dir.join("llms/Codex/proj/_raw/notify/quiet.jsonl");
+    assert_eq!(fs::read_to_string(&raw).unwrap().lines().count(), 1);
+
+    // `--flush` writes out a spool however young it is.
+    handle_notification(&config, &notification("young")).unwrap();
+    flush_spools(&config, Duration::ZERO, SystemTime::now()).unwrap();
+    assert!(find_md_file_containing_id(&threads, "young").is_some());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_is_session_end_notification() {
+    assert!(is_session_end_notification(
+        &serde_json::json!({"type":"session-end"})
+    ));
+    assert!(is_session_end_notification(
+        &serde_json::json!({"type":"agent_session_end"})
+    ));
+    assert!(!is_session_end_notification(
+        &serde_json::json!({"type":"agent-turn-complete"})
+    ));
+}
+
+// ========================================
+// handle_notification tests
+// ========================================
+
+#[test]
+fn test_handle_notification_writes_under_configured_vault() {
+    let dir = temp_test_dir("handle-config");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let config = Config {
+        vault: Some(dir.clone()),
+        ai_root: Some(PathBuf::from("llms")),
+        result_note: true,
+        ..test_config()
+    };
+    let notification = serde_json::json!({
+        "type": "agent-turn-complete",
+        "thread-id": "thread-1",
+        "turn-id": "turn-1",
+        "cwd": cwd.to_str().unwrap(),
+        "input-messages": ["question"],
+        "last-assistant-message": "answer",
+    });
+
+    handle_notification(&config, &notification).unwrap();
+
+    let base_dir = dir.join("llms").join("Codex").join("proj");
+    let note = find_md_file_containing_id(&base_dir.join("Threads"), "thread-1").unwrap();
+    let text = fs::read_to_string(&note).unwrap();
+    assert!(text.contains("<!-- turn-id:turn-1 -->"));
+    assert!(base_dir.join("Results").is_dir());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_handle_notification_ignored_thread_writes_nothing_and_purges() {
+    let dir = temp_test_dir("handle-ignored");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let mut config = Config {
+        vault: Some(dir.clone()),
+        ai_root: Some(PathBuf::from("llms")),
+        ..test_config()
+    };
+    let notification = |turn: &str| {
+        serde_json::json!({
+            "type": "agent-turn-complete",
+            "thread-id": "private-thread",
+            "turn-id": turn,
+            "cwd": cwd.to_str().unwrap(),
+            "input-messages": ["question"],
+            "last-assistant-message": "answer",
+        })
+    };
+    let base_dir = dir.join("llms").join("Codex").join("proj");
+    let raw = base_dir
+        .join("_raw")
+        .join("notify")
+        .join("private-thread.jsonl");
+
+    handle_notification(&config, &notification("t1")).unwrap();
+    let note = find_md_file_containing_id(&base_dir.join("Threads"), "private-thread").unwrap();
+
+    fs::write(cwd.join(".llmlogsignore"), "private-*\n").unwrap();
+    handle_notification(&config, &notification("t2")).unwrap();
+    assert!(!fs::read_to_string(&note).unwrap().contains("turn-id:t2"));
+    assert_eq!(fs::read_to_string(&raw).unwrap().lines().count(), 1);
+
+    config.ignore_purge = true;
+    handle_notification(&config, &notification("t3")).unwrap();
+    assert!(!note.exists());
+    assert!(!raw.exists());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_purge_thread_removes_every_output() {
+    let dir = temp_test_dir("purge-thread");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let vault = dir.join("vault");
+    let config = Config {
+        vault: Some(vault.clone()),
+        ai_root: Some(PathBuf::from("llms")),
+        mirror_dir: Some(dir.join("backup")),
+        result_note: true,
+        daily_digest: true,
+        sqlite: true,
+        ..test_config()
+    };
+    for thread in ["t-purge", "t-keep"] {
+        let notification = serde_json::json!({
+            "type": "agent-turn-complete",
+            "thread-id": thread,
+            "turn-id": "turn-1",
+            "cwd": cwd.to_str().unwrap(),
+            "input-messages": ["question"],
+            "last-assistant-message": "answer",
+        });
+        handle_notification(&config, &notification).unwrap();
+    }
+    let base_dir = vault.join("llms/Codex/proj");
+    let note = find_md_file_containing_id(&base_dir.join("Threads"), "t-purge").unwrap();
+    let stem = note.file_stem().unwrap().to_str().unwrap();
+    let result = base_dir.join("Results").join(format!("{stem}_result.md"));
+    let mirrored = dir.join("backup").join(note.strip_prefix(&vault).unwrap());
+    let digest = fs::read_dir(base_dir.join("Daily"))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let db = vault.join("llms/_index.sqlite");
+    let rows = |id: &str| -> i64 {
+        let conn = rusqlite::Connection::open(&db).unwrap();
+        conn.query_row(
+            "SELECT COUNT(*) FROM messages WHERE session_id = ?1",
+            [id],
+            |r| r.get(0),
+        )
+        .unwrap()
+    };
+    assert!(result.exists() && mirrored.exists());
+    assert_eq!(rows("t-purge"), 2);
+
+    purge_thread(&config, &base_dir, "t-purge").unwrap();
+    assert!(!note.exists() && !result.exists() && !mirrored.exists());
+    let text = fs::read_to_string(&digest).unwrap();
+    assert!(
+        !text.contains("t-purge") && text.contains("t-keep"),
+        "{text}"
+    );
+    assert_eq!((rows("t-purge"), rows("t-keep")), (0, 2));
+    assert!(find_md_file_containing_id(&base_dir.join("Threads"), "t-keep").is_some());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_handle_notification_redacts_secrets() {
+    let dir = temp_test_dir("handle-redact");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let mut config = Config {
+        vault: Some(dir.clone()),
+        ai_root: Some(PathBuf::from("llms")),
+        redact_secrets: true,
+        ..test_config()
+    };
+    let notification = |turn: &str| {
+        serde_json::json!({
+            "type": "agent-turn-complete",
+            "thread-id": "secret-thread",
+            "turn-id": turn,
+            "cwd": cwd.to_str().unwrap(),
+            "input-messages": ["set OPENAI_API_KEY=sk-proj-4f9Kd82LmQpZx7Vb3NcR1tYw"],
+            "last-assistant-message": "done",
+        })
+    };
+    let base_dir = dir.join("llms").join("Codex").join("proj");
+    let raw = base_dir
+        .join("_raw")
+        .join("notify")
+        .join("secret-thread.jsonl");
+
+    handle_notification(&config, &notification("t1")).unwrap();
+    let note = find_md_file_containing_id(&base_dir.join("Threads"), "secret-thread").unwrap();
+    let text = fs::read_to_string(&note).unwrap();
+    assert!(text.contains("OPENAI_API_KEY=[REDACTED]"));
+    assert!(!text.contains("sk-proj-"));
+    // The raw log stays verbatim unless LLM_LOGS_REDACT_RAW is set too.
+    assert!(fs::read_to_string(&raw).unwrap().contains("sk-proj-"));
+
+    config.redact_raw = true;
+    handle_notification(&config, &notification("t2")).unwrap();
+    let raw_text = fs::read_to_string(&raw).unwrap();
+    let last = raw_text.lines().last().unwrap();
+    assert!(last.contains("OPENAI_API_KEY=[REDACTED]"));
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_handle_notification_merges_project_frontmatter_template() {
+    use ai_log_exporter::frontmatter_field;
+
+    let dir = temp_test_dir("handle-fm-template");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let config = Config {
+        vault: Some(dir.clone()),
+        ai_root: Some(PathBuf::from("llms")),
+        ..test_config()
+    };
+    let base_dir = dir.join("llms").join("Codex").join("proj");
+    fs::create_dir_all(&base_dir).unwrap();
+    fs::write(base_dir.join("_template.yaml"), "client: \"Acme\"\n").unwrap();
+    let notification = serde_json::json!({
+        "type": "agent-turn-complete",
+        "thread-id": "template-thread",
+        "turn-id": "t1",
+        "cwd": cwd.to_str().unwrap(),
+        "input-messages": ["question"],
+        "last-assistant-message": "answer",
+    });
+
+    handle_notification(&config, &notification).unwrap();
+
+    let note = find_md_file_containing_id(&base_dir.join("Threads"), "template-thread").unwrap();
+    let text = fs::read_to_string(&note).unwrap();
+    assert_eq!(frontmatter_field(&text, "client").as_deref(), Some("Acme"));
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_validate_notification_reports_existing_note_and_status() {
+    let dir = temp_test_dir("validate-notification");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let config = Config {
+        vault: Some(dir.clone()),
+        ai_root: Some(PathBuf::from("llms")),
+        ..test_config()
+    };
+    let notification = serde_json::json!({
+        "type": "agent-turn-complete",
+        "thread-id": "validate-1",
+        "turn-id": "t1",
+        "cwd": cwd.to_str().unwrap(),
+        "input-messages": ["question"],
+        "last-assistant-message": "answer",
+    });
+    handle_notification(&config, &notification).unwrap();
+    let md_dir = dir.join("llms").join("Codex").join("proj").join("Threads");
+    let note = find_md_file_containing_id(&md_dir, "validate-1").unwrap();
+    let before = fs::read_to_string(&note).unwrap();
+
+    let report = validate_notification(&config, &notification).unwrap();
+    assert!(report.starts_with("thread_id: validate-1\n"));
+    assert!(report.contains(&format!("note: {}\n", note.display())));
+    assert!(report.ends_with("status: export\n"));
+    assert_eq!(fs::read_to_string(&note).unwrap(), before);
+
+    let mut other = notification.clone();
+    other["type"] = serde_json::json!("approval-requested");
+    let report = validate_notification(&config, &other).unwrap();
+    assert!(report.ends_with("status: skip (type \"approval-requested\")\n"));
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_handle_notification_writes_mirror_copies() {
+    let dir = temp_test_dir("handle-mirror");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let vault = dir.join("vault");
+    let mirror = dir.join("backup");
+    let config = Config {
+        vault: Some(vault.clone()),
+        ai_root: Some(PathBuf::from("llms")),
+        mirror_dir: Some(mirror.clone()),
+        result_note: true,
+        max_turns: Some(1),
+        ..test_config()
+    };
+    for turn in ["t1", "t2"] {
+        let notification = serde_json::json!({
+            "type": "agent-turn-complete",
+            "thread-id": "mirror-thread",
+            "turn-id": turn,
+            "cwd": cwd.to_str().unwrap(),
+            "input-messages": [format!("question {turn}")],
+            "last-assistant-message": "answer",
+        });
+        handle_notification(&config, &notification).unwrap();
+    }
+
+    let base_dir = vault.join("llms").join("Codex").join("proj");
+    let note = find_md_file_containing_id(&base_dir.join("Threads"), "mirror-thread").unwrap();
+    let raw = base_dir
+        .join("_raw")
+        .join("notify")
+        .join("mirror-thread.jsonl");
+    let result = result_note_path(&base_dir, &note).unwrap();
+    let archive = turn_archive_path(&note, 1);
+    for path in [note, raw, result, archive] {
+        let copy = mirror.join(path.strip_prefix(&vault).unwrap());
+        assert_eq!(
+            fs::read_to_string(copy).unwrap(),
+            fs::read_to_string(&path).unwrap()
+        );
+    }
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_handle_notification_requires_vault() {
+    let notification = serde_json::json!({"type": "agent-turn-complete", "cwd": "."});
+    let err = handle_notification(&test_config(), &notification).unwrap_err();
+    assert!(err.to_string().contains("OBSIDIAN_VAULT"));
+}
+
+// ========================================
+// cwd inheritance tests
+// ========================================
+
+#[test]
+fn test_frontmatter_field_reads_quoted_values() {
+    use ai_log_exporter::frontmatter_field;
+
+    let note = build_codex_note_skeleton("proj", "t1", Some("/work/\"odd\" dir\\x"), Local::now());
+    assert_eq!(
+        frontmatter_field(&note, "cwd").as_deref(),
+        Some("/work/\"odd\" dir\\x")
+    );
+    assert_eq!(frontmatter_field(&note, "thread_id").as_deref(), Some("t1"));
+    assert_eq!(frontmatter_field(&note, "missing"), None);
+    assert_eq!(frontmatter_field("no frontmatter\ncwd: x\n", "cwd"), None);
+}
+
+#[test]
+fn test_handle_notification_without_cwd_inherits_from_note() {
+    let dir = temp_test_dir("inherit-cwd");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let config = Config {
+        vault: Some(dir.clone()),
+        ai_root: Some(PathBuf::from("llms")),
+        ..test_config()
+    };
+
+    let first = serde_json::json!({
+        "type": "agent-turn-complete",
+        "thread-id": "thread-cwd",
+        "turn-id": "turn-1",
+        "cwd": cwd.to_str().unwrap(),
+        "input-messages": ["q1"],
+        "last-assistant-message": "a1",
+    });
+    handle_notification(&config, &first).unwrap();
+
+    let second = serde_json::json!({
+        "type": "agent-turn-complete",
+        "thread-id": "thread-cwd",
+        "turn-id": "turn-2",
+        "input-messages": ["q2"],
+        "last-assistant-message": "a2",
+    });
+    handle_notification(&config, &second).unwrap();
+
+    let codex_dir = dir.join("llms").join("Codex");
+    assert_eq!(
+        inherited_cwd(&codex_dir, "thread-cwd").as_deref(),
+        cwd.to_str()
+    );
+    let note =
+        find_md_file_containing_id(&codex_dir.join("proj").join("Threads"), "thread-cwd").unwrap();
+    let text = fs::read_to_string(note).unwrap();
+    assert!(text.contains("<!-- turn-id:turn-1 -->"));
+    assert!(text.contains("<!-- turn-id:turn-2 -->"));
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_inherited_cwd_none_without_prior_note() {
+    let dir = temp_test_dir("inherit-none");
+    assert_eq!(inherited_cwd(&dir, "thread-x"), None);
+    assert_eq!(inherited_cwd(&dir.join("missing"), "thread-x"), None);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_inherited_cwd_reads_renamed_note() {
+    let dir = temp_test_dir("inherit-renamed");
+    let threads = dir.join("proj").join("Threads");
+    fs::create_dir_all(&threads).unwrap();
+    let uid = note_identity("Codex CLI", "thread-x");
+    let note = format!("---\nuid: \"{uid}\"\ncwd: \"/work/proj\"\n---\n\nbody\n");
+    fs::write(threads.join("My notes.md"), note).unwrap();
+
+    assert_eq!(
+        inherited_cwd(&dir, "thread-x").as_deref(),
+        Some("/work/proj")
+    );
+    assert_eq!(inherited_cwd(&dir, "thread-y"), None);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+// ========================================
+// min messages tests
+// ========================================
+
+fn min_messages_config(dir: &Path, min_messages: usize) -> Config {
+    Config {
+        vault: Some(dir.to_path_buf()),
+        ai_root: Some(PathBuf::from("llms")),
+        min_messages,
+        ..test_config()
+    }
+}
+
+fn turn_notification(cwd: &Path, turn_id: &str, input: &str, answer: &str) -> Value {
+    serde_json::json!({
+        "type": "agent-turn-complete",
+        "thread-id": "thread-min",
+        "turn-id": turn_id,
+        "cwd": cwd.to_str().unwrap(),
+        "input-messages": [input],
+        "last-assistant-message": answer,
+    })
+}
+
+#[test]
+fn test_new_note_created_from_earliest_turn_timestamp() {
+    let dir = temp_test_dir("created-earliest");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let config = min_messages_config(&dir, 3);
+    let threads = dir.join("llms").join("Codex").join("proj").join("Threads");
+
+    let mut first = turn_notification(&cwd, "turn-1", "hi", "hello");
+    first["timestamp"] = "2024-03-01T09:00:00Z".into();
+    let mut second = turn_notification(&cwd, "turn-2", "more", "sure");
+    second["timestamp"] = "2024-03-01T09:05:00Z".into();
+    handle_notification(&config, &first).unwrap();
+    handle_notification(&config, &second).unwrap();
+
+    let note = fs::read_to_string(find_md_file_containing_id(&threads, "thread-min").unwrap());
+    let created = frontmatter_field(&note.unwrap(), "created").unwrap();
+    let created = DateTime::parse_from_rfc3339(&created).unwrap();
+    assert_eq!(created.timestamp(), 1_709_283_600);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_new_note_filed_under_the_month_the_thread_started() {
+    let dir = temp_test_dir("created-month");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let mut config = Config {
+        monthly_folders: true,
+        ..min_messages_config(&dir, 3)
+    };
+    config.formats.date = Some("%Y-%m-%d".to_string());
+    let threads = dir.join("llms").join("Codex").join("proj").join("Threads");
+
+    // The note only comes into being with the second turn, in November.
+    let mut first = turn_notification(&cwd, "turn-1", "hi", "hello");
+    first["timestamp"] = "2024-10-31T23:50:00".into();
+    let mut second = turn_notification(&cwd, "turn-2", "more", "sure");
+    second["timestamp"] = "2024-11-01T00:10:00".into();
+    handle_notification(&config, &first).unwrap();
+    handle_notification(&config, &second).unwrap();
+
+    let note = find_md_file_containing_id(&threads, "thread-min").unwrap();
+    assert_eq!(note.parent().unwrap(), threads.join("2024-10"));
+    let name = note.file_name().unwrap().to_str().unwrap();
+    assert!(name.starts_with("2024-10-31"), "{name}");
+    let created = frontmatter_field(&fs::read_to_string(&note).unwrap(), "created").unwrap();
+    assert!(created.starts_with("2024-10-31T23:50:00"), "{created}");
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_notification_model_goes_into_frontmatter() {
+    let dir = temp_test_dir("codex-model");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let config = min_messages_config(&dir, 0);
+    let threads = dir.join("llms").join("Codex").join("proj").join("Threads");
+
+    let mut first = turn_notification(&cwd, "turn-1", "hi", "hello");
+    first["model"] = "gpt-5-codex".into();
+    handle_notification(&config, &first).unwrap();
+    let note_path = find_md_file_containing_id(&threads, "thread-min").unwrap();
+    let note = fs::read_to_string(&note_path).unwrap();
+    assert_eq!(
+        frontmatter_field(&note, "model").as_deref(),
+        Some("gpt-5-codex")
+    );
+
+    // A turn without a model keeps the recorded one.
+    handle_notification(&config, &turn_notification(&cwd, "turn-2", "more", "sure")).unwrap();
+    let note = fs::read_to_string(&note_path).unwrap();
+    assert_eq!(
+        frontmatter_field(&note, "model").as_deref(),
+        Some("gpt-5-codex")
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_split_oldest_turns_boundary() {
+    let blocks: Vec<String> = (1..=3).map(|i| turn(&format!("t{i}"), "q")).collect();
+    let (note, _) = apply_turns(&ensure_turns_block("# T"), &blocks, 1);
+
+    assert_eq!(turn_starts(&note).len(), 3);
+    assert!(split_oldest_turns(&note, 3).is_none());
+
+    let (kept, old) = split_oldest_turns(&note, 2).unwrap();
+    assert_eq!(old.len(), 1);
+    assert!(old[0].starts_with("<!-- turn-id:t1 -->"));
+    assert!(!kept.contains("turn-id:t1 "));
+    assert_eq!(turn_starts(&kept).len(), 2);
+    assert!(kept.contains("## Turns (auto)\n\n<!-- turn-id:t2 -->"));
+    assert!(kept.trim_end().ends_with(END));
+}
+
+#[test]
+fn test_max_turns_rolls_oldest_turns_into_archives() {
+    let dir = temp_test_dir("max-turns");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let config = Config {
+        max_turns: Some(3),
+        ..min_messages_config(&dir, 0)
+    };
+    let threads = dir.join("llms").join("Codex").join("proj").join("Threads");
+    let send = |i: usize| {
+        let n = turn_notification(&cwd, &format!("turn-{i}"), &format!("q{i}"), "a");
+        handle_notification(&config, &n).unwrap();
+    };
+
+    (1..=3).for_each(send);
+    let md_path = find_md_file_containing_id(&threads, "thread-min").unwrap();
+    assert!(turn_archives(&md_path).is_empty());
+
+    send(4);
+    let note = fs::read_to_string(&md_path).unwrap();
+    let archive_1 = turn_archive_path(&md_path, 1);
+    let archived = fs::read_to_string(&archive_1).unwrap();
+    assert_eq!(turn_starts(&note).len(), 3);
+    assert!(!note.contains("turn-id:turn-1 "));
+    assert!(archived.contains("<!-- turn-id:turn-1 -->"));
+    let archive_name = archive_1.file_stem().unwrap().to_str().unwrap();
+    assert!(note.contains(&format!("Older turns: [[{archive_name}]]")));
+
+    // Archives fill up to the cap before a new one is started.
+    (5..=7).for_each(send);
+    let archived = fs::read_to_string(&archive_1).unwrap();
+    assert_eq!(turn_starts(&archived).len(), 3);
+    let archive_2 = fs::read_to_string(turn_archive_path(&md_path, 2)).unwrap();
+    assert_eq!(turn_starts(&archive_2).len(), 1);
+    assert!(archive_2.contains("<!-- turn-id:turn-4 -->"));
+    let note = fs::read_to_string(&md_path).unwrap();
+    assert_eq!(note.matches("Older turns: [[").count(), 2);
+
+    // A re-sent archived turn is not logged again, and the note is still found.
+    send(1);
+    let again = fs::read_to_string(&md_path).unwrap();
+    assert_eq!(again, note);
+    assert_eq!(
+        find_md_file_containing_id(&threads, "thread-min"),
+        Some(md_path)
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_notification_message_count() {
+    let n = serde_json::json!({"input-messages": ["a", " ", "b"], "last-assistant-message": "c"});
+    assert_eq!(notification_message_count(&n), 3);
+    let n = serde_json::json!({"input-messages": "only"});
+    assert_eq!(notification_message_count(&n), 1);
+    assert_eq!(notification_message_count(&serde_json::json!({})), 0);
+}
+
+#[test]
+fn test_min_messages_defers_note_and_backfills_turns() {
+    let dir = temp_test_dir("min-messages");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let config = min_messages_config(&dir, 3);
+    let base_dir = dir.join("llms").join("Codex").join("proj");
+
+    handle_notification(&config, &turn_notification(&cwd, "turn-1", "hi", "hello")).unwrap();
+    assert_eq!(
+        find_md_file_containing_id(&base_dir.join("Threads"), "thread-min"),
+        None
+    );
+    assert!(base_dir
+        .join("_raw")
+        .join("notify")
+        .join("thread-min.jsonl")
+        .exists());
+
+    handle_notification(&config, &turn_notification(&cwd, "turn-2", "more", "sure")).unwrap();
+    let note = find_md_file_containing_id(&base_dir.join("Threads"), "thread-min").unwrap();
+    let text = fs::read_to_string(note).unwrap();
+    assert!(text.contains("<!-- turn-id:turn-1 -->"));
+    assert!(text.contains("<!-- turn-id:turn-2 -->"));
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_backfilled_turns_keep_their_own_time() {
+    use ai_log_exporter::parse_rfc3339_local;
+
+    let dir = temp_test_dir("min-messages-time");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let config = Config {
+        missing_time: Some("--:--".to_string()),
+        ..min_messages_config(&dir, 5)
+    };
+    let threads = dir.join("llms").join("Codex").join("proj").join("Threads");
+
+    let mut first = turn_notification(&cwd, "turn-1", "hi", "hello");
+    first["timestamp"] = serde_json::json!("2024-03-04T05:06:07Z");
+    handle_notification(&config, &first).unwrap();
+    handle_notification(&config, &turn_notification(&cwd, "turn-2", "more", "sure")).unwrap();
+    handle_notification(&config, &turn_notification(&cwd, "turn-3", "last", "done")).unwrap();
+
+    let note = find_md_file_containing_id(&threads, "thread-min").unwrap();
+    let text = fs::read_to_string(note).unwrap();
+    let heading = |turn: &str| {
+        let block = &text[text.find(&format!("<!-- turn-id:{turn} -->")).unwrap()..];
+        block
+            .lines()
+            .find(|l| l.starts_with("### "))
+            .unwrap()
+            .to_string()
+    };
+    let first_time = parse_rfc3339_local("2024-03-04T05:06:07Z").unwrap();
+    assert_eq!(
+        heading("turn-1"),
+        format!("### {} User", first_time.format(DEFAULT_TIME_FMT))
+    );
+    assert_eq!(heading("turn-2"), "### --:-- User");
+    let today = Local::now().format("### %Y-%m-%d ").to_string();
+    assert!(
+        heading("turn-3").starts_with(&today),
+        "{}",
+        heading("turn-3")
+    );
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_min_messages_still_updates_existing_note() {
+    let dir = temp_test_dir("min-messages-existing");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let base_dir = dir.join("llms").join("Codex").join("proj");
+
+    let first = turn_notification(&cwd, "turn-1", "hi", "hello");
+    handle_notification(&min_messages_config(&dir, 0), &first).unwrap();
+
+    let strict = min_messages_config(&dir, 100);
+    handle_notification(&strict, &turn_notification(&cwd, "turn-2", "again", "ok")).unwrap();
+    let note = find_md_file_containing_id(&base_dir.join("Threads"), "thread-min").unwrap();
+    assert!(fs::read_to_string(note)
+        .unwrap()
+        .contains("<!-- turn-id:turn-2 -->"));
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+// ========================================
+// elide_data_uris tests
+// ========================================
+
+#[test]
+fn test_elide_data_uris_replaces_long_uri_in_sentence() {
+    use ai_log_exporter::elide_data_uris;
+
+    let payload = "iVBORw0KGgo".repeat(1200);
+    let text = format!("see data:image/png;base64,{payload} for the screenshot");
+    assert_eq!(
+        elide_data_uris(&text),
+        "see [data:image/png;base64 (10KB) elided] for the screenshot"
+    );
+}
+
+#[test]
+fn test_elide_data_uris_leaves_short_uri_untouched() {
+    use ai_log_exporter::elide_data_uris;
+
+    let text = "tiny data:image/gif;base64,R0lGODlhAQABAAAAACw= pixel";
+    assert_eq!(elide_data_uris(text), text);
+    assert_eq!(elide_data_uris("metadata: none"), "metadata: none");
+}
+
+// ========================================
+// notification_from_flags tests
+// ========================================
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn test_notification_from_flags_builds_turn() {
+    let n = notification_from_flags(&args(&[
+        "--thread-id",
+        "th1",
+        "--turn-id",
+        "tu1",
+        "--cwd=/work/proj",
+        "--input",
+        "first",
+        "--input",
+        "second",
+        "--assistant",
+        "done",
+    ]))
+    .unwrap();
+
+    assert!(should_process_notification(&n));
+    assert_eq!(n["thread-id"], "th1");
+    assert_eq!(n["cwd"], "/work/proj");
+    assert_eq!(n["input-messages"], serde_json::json!(["first", "second"]));
+    let block = turn_block_for(&Config::default(), &n, Some(Local::now())).unwrap();
+    assert!(block.starts_with("<!-- turn-id:tu1 -->"));
+    assert!(block.contains("- first\n- second"));
+    assert!(block.contains("done"));
+}
+
+#[test]
+fn test_notification_from_flags_requires_thread_id() {
+    assert!(notification_from_flags(&args(&["--turn-id", "tu1"])).is_err());
+    assert!(notification_from_flags(&args(&["--thread-id", "th1", "--nope", "x"])).is_err());
+
+    let end =
+        notification_from_flags(&args(&["--thread-id", "th1", "--type", "session-end"])).unwrap();
+    assert!(is_session_end_notification(&end));
+}
+
+// ========================================
+// structured last-assistant-message tests
+// ========================================
+
+fn assistant_section(last_assistant: Value) -> String {
+    let n = serde_json::json!({
+        "type": "agent-turn-complete",
+        "turn-id": "t1",
+        "input-messages": ["q"],
+        "last-assistant-message": last_assistant,
+    });
+    let block = turn_block_for(&Config::default(), &n, Some(Local::now())).unwrap();
+    let (_, assistant) = block.split_once(" Assistant\n").unwrap();
+    assistant.trim().to_string()
+}
+
+#[test]
+fn test_last_assistant_message_as_string() {
+    assert_eq!(
+        assistant_section(serde_json::json!("plain answer")),
+        "plain answer"
+    );
+}
+
+#[test]
+fn test_last_assistant_message_as_object_with_text() {
+    let v = serde_json::json!({"type": "output_text", "text": "from object"});
+    assert_eq!(assistant_section(v), "from object");
+
+    let v =
+        serde_json::json!({"role": "assistant", "content": [{"type": "text", "text": "nested"}]});
+    assert_eq!(assistant_section(v), "nested");
+}
+
+#[test]
+fn test_last_assistant_message_as_array_of_parts() {
+    let v = serde_json::json!([
+        {"type": "text", "text": "first"},
+        {"type": "tool_use", "name": "shell"},
+        {"type": "text", "text": "second"},
+    ]);
+    assert_eq!(assistant_section(v), "first\nsecond");
+}
+
+#[test]
+fn test_last_assistant_message_without_text_falls_back_to_json() {
+    let v = serde_json::json!({"status": "done", "code": 0});
+    assert_eq!(assistant_section(v), r#"{"code":0,"status":"done"}"#);
+    assert_eq!(
+        notification_message_count(&serde_json::json!({"last-assistant-message": null})),
+        0
+    );
+}
+
+#[test]
+fn test_last_assistant_message_with_configured_text_types() {
+    let v = serde_json::json!([{"type": "output_text", "text": "custom"}]);
+    let custom = TextOptions {
+        types: vec!["output_text".to_string()],
+        ..TextOptions::default()
+    };
+    assert_eq!(assistant_text(&v, &custom), "custom");
+    let default = TextOptions::default();
+    assert_eq!(
+        assistant_text(&v, &default),
+        r#"[{"text":"custom","type":"output_text"}]"#
+    );
+}
+
+// ========================================
+// footer nav tests
+// ========================================
+
+#[test]
+fn test_footer_nav_is_upserted_once_after_turns() {
+    use ai_log_exporter::FOOTER_BEGIN;
+
+    let dir = temp_test_dir("footer-nav");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let config = Config {
+        vault: Some(dir.clone()),
+        ai_root: Some(PathBuf::from("llms")),
+        footer_nav: true,
+        ..test_config()
+    };
+
+    handle_notification(&config, &turn_notification(&cwd, "t1", "q1", "a1")).unwrap();
+    let threads = dir.join("llms/Codex/proj/Threads");
+    let note = find_md_file_containing_id(&threads, "thread-min").unwrap();
+    let edited = fs::read_to_string(&note).unwrap().replace("# ", "# My ");
+    fs::write(&note, edited).unwrap();
+    handle_notification(&config, &turn_notification(&cwd, "t2", "q2", "a2")).unwrap();
+
+    let text = fs::read_to_string(&note).unwrap();
+    assert_eq!(text.matches(FOOTER_BEGIN).count(), 1);
+    assert!(text.contains("# My "));
+    assert!(text.contains("[[llms/Codex/proj/_overview|Overview]]"));
+    assert!(text.find("<!-- turn-id:t2 -->").unwrap() < text.find(END).unwrap());
+    assert!(text.find(END).unwrap() < text.find(FOOTER_BEGIN).unwrap());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_handle_notification_uses_custom_codex_folder() {
+    let dir = temp_test_dir("custom-codex-dir");
+    let cwd = dir.join("proj");
+    fs::create_dir_all(&cwd).unwrap();
+    let config = Config {
+        vault: Some(dir.clone()),
+        ai_root: Some(PathBuf::from("llms")),
+        codex_dir: Some("AI_Codex".to_string()),
+        ..test_config()
+    };
+
+    handle_notification(&config, &turn_notification(&cwd, "t1", "q", "a")).unwrap();
+
+    let threads = dir.join("llms/AI_Codex/proj/Threads");
+    assert!(find_md_file_containing_id(&threads, "thread-min").is_some());
+    assert!(!dir.join("llms/Codex").exists());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+// ========================================
+// synthesized thread id tests
+// ========================================
+
+#[test]
+fn test_synthesized_thread_id_differs_between_id_less_payloads() {
+    let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+    let first = serde_json::json!({"cwd": "/work/proj", "input-messages": ["fix the parser"]});
+    let second = serde_json::json!({"cwd": "/work/proj", "input-messages": ["write the docs"]});
+    let elsewhere = serde_json::json!({"cwd": "/work/other", "input-messages": ["fix the parser"]});
+
+    let a = synthesized_thread_id(&first, date);
+    assert!(a.starts_with("thread-"));
+    assert_ne!(a, synthesized_thread_id(&second, date));
+    assert_ne!(a, synthesized_thread_id(&elsewhere, date));
+    assert_ne!(a, synthesized_thread_id(&first, date.succ_opt().unwrap()));
+    assert_eq!(a, synthesized_thread_id(&first, date));
 }
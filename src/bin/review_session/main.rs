@@ -1,14 +1,28 @@
-use ai_log_exporter::{find_md_file_containing_id, git_project_name, safe_id, safe_name, with_lock_file};
+use ai_log_exporter::{
+    closes_fence, fence_delimiter, find_md_file_by_uid, find_md_file_containing_id,
+    normalize_markdown, note_identity, read_to_string_lossy, safe_id, upsert_marked_block,
+    with_lock_file, Config,
+};
+use ai_log_exporter::log;
 use anyhow::{Context, Result};
 use serde_json::Value;
 use std::{
     env, fs,
     io::{self, Read},
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::Command,
 };
 
 fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (verbosity, args) = log::take_verbosity(&args);
+    log::set_verbosity(verbosity);
+    if args.first().map(String::as_str) == Some("--print-config") {
+        let cwd = args.get(1).map(String::as_str).unwrap_or(".");
+        print!("{}", Config::from_env()?.describe(cwd));
+        return Ok(());
+    }
+
     // SessionEnd hook payload arrives on stdin as JSON
     let mut stdin = String::new();
     io::stdin()
@@ -23,43 +37,45 @@ fn main() -> Result<()> {
     let payload: Value =
         serde_json::from_str(stdin).context("failed to parse hook JSON from stdin")?;
 
-    let session_id = payload
-        .get("session_id")
-        .and_then(|v| v.as_str())
+    let session_id = ["session_id", "thread_id", "thread-id"]
+        .iter()
+        .find_map(|k| payload.get(*k).and_then(|v| v.as_str()))
         .context("missing session_id in hook payload")?;
     let session_id_safe = safe_id(session_id, "unknown-session");
 
     let cwd = payload.get("cwd").and_then(|v| v.as_str()).unwrap_or(".");
+    let tool = payload.get("tool").and_then(|v| v.as_str());
 
-    let vault = env::var("OBSIDIAN_VAULT").context("Missing OBSIDIAN_VAULT env var")?;
-    let ai_root = env::var("OBSIDIAN_AI_ROOT").context("Missing OBSIDIAN_AI_ROOT env var")?;
-
-    let project = safe_name(&git_project_name(cwd));
-
-    // Find the MD file for this session
-    let vault_path = PathBuf::from(&vault);
-    let md_dir = vault_path
-        .join(&ai_root)
-        .join("Claude Code")
-        .join(&project)
-        .join("Threads");
-
-    let lock_path = md_dir.join(format!(".lock_{session_id_safe}"));
-    let md_content = with_lock_file(&lock_path, || {
-        let md_path = find_md_by_session_id(&md_dir, &session_id_safe);
-        let md_path = match md_path {
-            Some(p) => p,
-            None => {
-                eprintln!("MD file not found for session: {}", session_id);
-                return Ok(None);
-            }
-        };
-        let md_content = fs::read_to_string(&md_path).context("failed to read MD file")?;
-        Ok(Some((md_path, md_content)))
-    })?;
-    let (md_path, md_content) = match md_content {
+    let config = Config::from_env()?;
+    let project = config.project_name(cwd);
+
+    // Find the MD file for this session, in the hinted tool's tree or in all of them
+    let ai_dir = config.ai_dir()?;
+    let mut found = None;
+    for md_dir in candidate_md_dirs(&config, &ai_dir, &project, tool) {
+        if !md_dir.is_dir() {
+            continue;
+        }
+        let lock_path = md_dir.join(format!(".lock_{session_id_safe}"));
+        found = with_lock_file(&lock_path, || {
+            let md_path = match find_md_by_session_id(&md_dir, session_id) {
+                Some(p) => p,
+                None => return Ok(None),
+            };
+            let md_content = read_to_string_lossy(&md_path).context("failed to read MD file")?;
+            Ok(Some((md_path, md_content, lock_path.clone())))
+        })?;
+        if found.is_some() {
+            break;
+        }
+    }
+    let (md_path, md_content, lock_path) = match found {
         Some(v) => v,
-        None => return Ok(()),
+        // Ignored and too-short sessions have no note, so this is not a failure.
+        None => {
+            log::warn(|| format!("MD file not found for session: {session_id}"));
+            return Ok(());
+        }
     };
 
     // Extract user messages from MD content
@@ -77,8 +93,14 @@ fn main() -> Result<()> {
         }
     };
 
+    if config.proposals_inline {
+        write_inline_proposals(&config, &md_path, &lock_path, &proposals)?;
+        log::info(|| format!("Skill proposals added to: {}", md_path.display()));
+        return Ok(());
+    }
+
     // Save proposals to file
-    let proposals_dir = vault_path.join(&ai_root).join("skill_proposals");
+    let proposals_dir = ai_dir.join("skill_proposals");
     fs::create_dir_all(&proposals_dir).context("failed to create proposals dir")?;
 
     let proposal_file = proposals_dir.join(format!("{session_id_safe}.md"));
@@ -99,30 +121,118 @@ reviewed_file: "{}"
         proposals
     );
 
-    fs::write(&proposal_file, proposal_content).context("failed to write proposal file")?;
+    config
+        .write_output(&proposal_file, &normalize_markdown(&proposal_content))
+        .context("failed to write proposal file")?;
 
-    eprintln!("Skill proposals saved to: {}", proposal_file.display());
+    log::info(|| format!("Skill proposals saved to: {}", proposal_file.display()));
     Ok(())
 }
 
-fn find_md_by_session_id(md_dir: &PathBuf, session_id: &str) -> Option<PathBuf> {
-    find_md_file_containing_id(md_dir, session_id)
+pub const PROPOSALS_BEGIN: &str = "<!-- BEGIN SKILL PROPOSALS -->";
+pub const PROPOSALS_END: &str = "<!-- END SKILL PROPOSALS -->";
+
+pub fn upsert_proposals_block(note: &str, proposals: &str) -> String {
+    let block = format!(
+        "{PROPOSALS_BEGIN}\n## Skill 提案\n\n{}\n{PROPOSALS_END}",
+        proposals.trim()
+    );
+    upsert_marked_block(note, PROPOSALS_BEGIN, PROPOSALS_END, &block)
+}
+
+// The exporters may be rewriting the note at the same time, so the note is
+// re-read under its session lock before the block is upserted.
+pub fn write_inline_proposals(
+    config: &Config,
+    md_path: &Path,
+    lock_path: &Path,
+    proposals: &str,
+) -> Result<()> {
+    with_lock_file(lock_path, || {
+        let note = read_to_string_lossy(md_path).context("failed to read MD file")?;
+        let updated = normalize_markdown(&upsert_proposals_block(&note, proposals));
+        config
+            .write_output(md_path, &updated)
+            .context("failed to write proposals into MD file")
+    })
+}
+
+// Maps the payload's `tool` hint to the tool folders to search; without a
+// recognizable hint every tool is searched, Claude Code first.
+pub fn tool_dirs_for<'a>(config: &'a Config, tool: Option<&str>) -> Vec<&'a str> {
+    let hint = tool.map(|t| t.trim().to_ascii_lowercase());
+    match hint.as_deref() {
+        Some(t) if t.starts_with("claude") => vec![config.claude_dir_name()],
+        Some(t) if t.starts_with("codex") => vec![config.codex_dir_name()],
+        _ => vec![config.claude_dir_name(), config.codex_dir_name()],
+    }
+}
+
+pub fn candidate_md_dirs(
+    config: &Config,
+    ai_dir: &Path,
+    project: &str,
+    tool: Option<&str>,
+) -> Vec<PathBuf> {
+    tool_dirs_for(config, tool)
+        .into_iter()
+        .map(|t| ai_dir.join(t).join(project).join("Threads"))
+        .collect()
+}
+
+// Either exporter may have written the note, so a renamed one is looked up by
+// the `uid:` each of them would give the session.
+fn find_md_by_session_id(md_dir: &Path, session_id: &str) -> Option<PathBuf> {
+    let session_id_safe = safe_id(session_id, "unknown-session");
+    find_md_file_containing_id(md_dir, &session_id_safe).or_else(|| {
+        ["Claude Code", "Codex CLI"]
+            .iter()
+            .find_map(|tool| find_md_file_by_uid(md_dir, &note_identity(tool, session_id)))
+    })
 }
 
 pub fn extract_user_messages(md_content: &str) -> Vec<String> {
     let mut messages = Vec::new();
     let mut current_message = String::new();
     let mut in_user_block = false;
+    // Heading-like lines inside fenced code are part of the message.
+    let mut fence = None;
+    // Folded turns are quoted into a `> [!example]-` callout.
+    let mut in_fold = false;
 
     for line in md_content.lines() {
-        if line.starts_with("### ") && line.contains(" User") {
+        if line.starts_with("> [!example]- Turn ") {
+            in_fold = true;
+            continue;
+        }
+        if in_fold && !line.starts_with('>') {
+            // The message ends with its turn.
+            in_fold = false;
+            if in_user_block && !current_message.trim().is_empty() {
+                messages.push(current_message.trim().to_string());
+            }
+            current_message = String::new();
+            in_user_block = false;
+        }
+        let line = match line.strip_prefix('>').filter(|_| in_fold) {
+            Some(rest) => rest.strip_prefix(' ').unwrap_or(rest),
+            None => line,
+        };
+        let in_code = fence.is_some();
+        match fence {
+            None => fence = fence_delimiter(line),
+            Some(open) if closes_fence(line, open) => fence = None,
+            Some(_) => {}
+        }
+
+        if !in_code && line.starts_with("### ") && line.contains(" User") {
             // Start of a user message block
             if !current_message.trim().is_empty() {
                 messages.push(current_message.trim().to_string());
             }
             current_message = String::new();
             in_user_block = true;
-        } else if line.starts_with("### ") && line.contains(" Assistant") {
+        } else if !in_code && line.starts_with("### ") && line.contains(" Assistant") {
             // End of user block, start of assistant block
             if in_user_block && !current_message.trim().is_empty() {
                 messages.push(current_message.trim().to_string());
@@ -195,15 +305,35 @@ fn review_with_llm(user_messages: &[String], project: &str) -> Result<Option<Str
     let result = fs::read_to_string(&tmp_file).unwrap_or_default();
     let _ = fs::remove_file(&tmp_file);
 
-    // Check if LLM returned "NONE" (no proposals)
-    let trimmed = result.trim();
-    if trimmed.is_empty() || trimmed.eq_ignore_ascii_case("none") {
+    if is_no_proposal(&result) {
         return Ok(None);
     }
 
     Ok(Some(result))
 }
 
+// The prompt asks for a bare "NONE", but models often add a reason ("NONE -
+// too simple.") or Markdown (`**NONE**`). The first non-empty line counts as
+// NONE when, after stripping Markdown, it is the word NONE followed by nothing
+// or by punctuation — so "None of these steps repeat, but..." is still kept.
+pub fn is_no_proposal(output: &str) -> bool {
+    const MARKUP: &[char] = &['*', '_', '`', '#', '>', '"', '\'', '「', '」'];
+
+    let first = match output.lines().map(str::trim).find(|l| !l.is_empty()) {
+        Some(line) => line,
+        None => return true,
+    };
+    let line = first.trim_matches(|c: char| MARKUP.contains(&c) || c.is_whitespace());
+    if !line
+        .get(..4)
+        .is_some_and(|head| head.eq_ignore_ascii_case("none"))
+    {
+        return false;
+    }
+    let rest = line[4..].trim_start_matches(MARKUP).trim_start();
+    rest.chars().next().is_none_or(|c| !c.is_alphanumeric())
+}
+
 #[cfg(test)]
 #[path = "tests.rs"]
 mod tests;
@@ -138,3 +138,282 @@ Response.
     assert_eq!(messages[0], "First user message.");
     assert_eq!(messages[1], "Second user message.");
 }
+
+#[test]
+fn test_extract_user_messages_ignores_headings_in_code_blocks() {
+    let md = r#"
+### 2024-01-01 10:00:00 User
+Why does this template render twice?
+
+```markdown
+### {{date}} User
+### {{date}} Assistant
+```
+
+~~~
+### 10:00 Assistant
+``` not a closing fence
+~~~
+Thanks.
+
+### 2024-01-01 10:01:00 Assistant
+```
+### inside the answer User
+```
+
+### 2024-01-01 10:02:00 User
+Follow-up.
+"#;
+    let messages = extract_user_messages(md);
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0].starts_with("Why does this template render twice?"));
+    assert!(messages[0].contains("### {{date}} Assistant\n```"));
+    assert!(messages[0].contains("### 10:00 Assistant\n``` not a closing fence\n~~~"));
+    assert!(messages[0].ends_with("Thanks."));
+    assert_eq!(messages[1], "Follow-up.");
+}
+
+#[test]
+fn test_extract_user_messages_nested_fence() {
+    let md = r#"
+### 2024-01-01 10:00:00 User
+How do I show a fence in Markdown?
+
+````markdown
+```sh
+### 10:00 Assistant
+```
+### 10:01 User
+````
+Like that.
+
+### 2024-01-01 10:01:00 Assistant
+Yes.
+"#;
+    let messages = extract_user_messages(md);
+    assert_eq!(messages.len(), 1);
+    assert!(messages[0].contains("```sh\n### 10:00 Assistant\n```\n### 10:01 User\n````"));
+    assert!(messages[0].ends_with("Like that."));
+}
+
+#[test]
+fn test_extract_user_messages_from_folded_turns() {
+    use ai_log_exporter::transcript::{build_transcript_block, Msg, RenderOptions};
+
+    let msg = |role, text: &str| Msg {
+        role,
+        text: text.to_string(),
+        ts: None,
+        resumed: false,
+        usage: None,
+    };
+    let msgs = vec![
+        msg("user", "first question\n\nwith two paragraphs"),
+        msg("assistant", "an answer"),
+        msg("user", "second question"),
+    ];
+    let opts = RenderOptions {
+        fold_turns: true,
+        ..RenderOptions::default()
+    };
+    let md = format!("# Note\n\n{}", build_transcript_block("now", "src", &msgs, &opts));
+
+    let messages = extract_user_messages(&md);
+    assert_eq!(messages, vec!["first question\n\nwith two paragraphs", "second question"]);
+}
+
+// ========================================
+// candidate_md_dirs tests
+// ========================================
+
+#[test]
+fn test_candidate_md_dirs_follows_tool_hint() {
+    let config = Config::default();
+    let ai_dir = Path::new("/vault/llms");
+    assert_eq!(
+        candidate_md_dirs(&config, ai_dir, "proj", Some("codex")),
+        vec![PathBuf::from("/vault/llms/Codex/proj/Threads")]
+    );
+    assert_eq!(
+        candidate_md_dirs(&config, ai_dir, "proj", Some("Claude Code")),
+        vec![PathBuf::from("/vault/llms/Claude Code/proj/Threads")]
+    );
+    assert_eq!(tool_dirs_for(&config, Some("Codex CLI")), vec!["Codex"]);
+}
+
+#[test]
+fn test_candidate_md_dirs_scans_all_tools_without_hint() {
+    let config = Config::default();
+    let ai_dir = Path::new("/vault/llms");
+    let all = vec![
+        PathBuf::from("/vault/llms/Claude Code/proj/Threads"),
+        PathBuf::from("/vault/llms/Codex/proj/Threads"),
+    ];
+    assert_eq!(candidate_md_dirs(&config, ai_dir, "proj", None), all);
+    assert_eq!(
+        candidate_md_dirs(&config, ai_dir, "proj", Some("cursor")),
+        all
+    );
+}
+
+// ========================================
+// is_no_proposal tests
+// ========================================
+
+#[test]
+fn test_is_no_proposal_accepts_bare_none() {
+    assert!(is_no_proposal("NONE"));
+    assert!(is_no_proposal("  none\n"));
+    assert!(is_no_proposal(""));
+}
+
+#[test]
+fn test_is_no_proposal_accepts_none_with_punctuation_or_reason() {
+    assert!(is_no_proposal("NONE."));
+    assert!(is_no_proposal("NONE - this session is too simple."));
+    assert!(is_no_proposal("**NONE**\n\nThe session was a one-off."));
+    assert!(is_no_proposal("`NONE`"));
+    assert!(is_no_proposal("NONE（単純なセッションのため）"));
+}
+
+#[test]
+fn test_is_no_proposal_keeps_real_proposals_mentioning_none() {
+    assert!(!is_no_proposal(
+        "None of these steps repeat, but one pattern stands out:\n..."
+    ));
+    assert!(!is_no_proposal(
+        "## Skill: cleanup\n\nReturns none when the cache is empty."
+    ));
+    assert!(!is_no_proposal("Nonetheless, a deploy skill would help."));
+}
+
+// ========================================
+// inline proposals tests
+// ========================================
+
+#[test]
+fn test_upsert_proposals_block_creates_then_updates() {
+    let note = "---\nsession_id: \"s1\"\n---\n\n\
+                <!-- BEGIN AUTO TRANSCRIPT -->\nbody\n<!-- END AUTO TRANSCRIPT -->\n";
+
+    let created = upsert_proposals_block(note, "first proposal\n");
+    assert!(created.starts_with(note.trim_end()));
+    assert!(created.ends_with(&format!("first proposal\n{PROPOSALS_END}\n")));
+
+    let updated = upsert_proposals_block(&created, "second proposal");
+    assert_eq!(updated.matches(PROPOSALS_BEGIN).count(), 1);
+    assert!(updated.contains("second proposal"));
+    assert!(!updated.contains("first proposal"));
+    assert!(updated.contains("<!-- END AUTO TRANSCRIPT -->\n\n<!-- BEGIN SKILL PROPOSALS -->"));
+}
+
+#[test]
+fn test_write_inline_proposals_updates_note_on_disk() {
+    let dir = std::env::temp_dir().join(format!("review-inline-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let md_path = dir.join("title_s1.md");
+    fs::write(&md_path, "# Note\n").unwrap();
+    let lock_path = dir.join(".lock_s1");
+
+    write_inline_proposals(&Config::default(), &md_path, &lock_path, "use a skill").unwrap();
+    write_inline_proposals(&Config::default(), &md_path, &lock_path, "use a better skill").unwrap();
+
+    let note = fs::read_to_string(&md_path).unwrap();
+    let expected =
+        format!("# Note\n\n{PROPOSALS_BEGIN}\n## Skill 提案\n\nuse a better skill\n{PROPOSALS_END}\n");
+    assert_eq!(note, expected);
+    assert!(!lock_path.exists());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_find_md_by_session_id_finds_renamed_note_by_uid() {
+    let dir = std::env::temp_dir().join(format!("review-renamed-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let uid = note_identity("Codex CLI", "thread-1");
+    let md_path = dir.join("My notes.md");
+    fs::write(&md_path, format!("---\nuid: \"{uid}\"\n---\n\nbody\n")).unwrap();
+
+    assert_eq!(find_md_by_session_id(&dir, "thread-1"), Some(md_path));
+    assert_eq!(find_md_by_session_id(&dir, "thread-2"), None);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_candidate_md_dirs_uses_custom_tool_folders() {
+    let config = Config {
+        claude_dir: Some("Claude".to_string()),
+        codex_dir: Some("AI_Codex".to_string()),
+        ..Config::default()
+    };
+    let ai_dir = Path::new("/vault/llms");
+    assert_eq!(
+        candidate_md_dirs(&config, ai_dir, "proj", None),
+        vec![
+            PathBuf::from("/vault/llms/Claude/proj/Threads"),
+            PathBuf::from("/vault/llms/AI_Codex/proj/Threads"),
+        ]
+    );
+}
+
+// ========================================
+// verbosity tests
+// ========================================
+
+#[test]
+fn test_write_log_gates_on_verbosity() {
+    use log::{write_log, Level, Verbosity};
+
+    let emitted = |verbosity, level| {
+        let mut out = Vec::new();
+        let written = write_log(&mut out, verbosity, level, || {
+            "MD file not found".to_string()
+        });
+        assert_eq!(written, !out.is_empty());
+        String::from_utf8(out).unwrap()
+    };
+
+    assert_eq!(
+        emitted(Verbosity::Normal, Level::Warn),
+        "warning: MD file not found\n"
+    );
+    assert_eq!(emitted(Verbosity::Normal, Level::Info), "");
+    assert_eq!(emitted(Verbosity::Normal, Level::Debug), "");
+    assert_eq!(
+        emitted(Verbosity::Verbose, Level::Info),
+        "MD file not found\n"
+    );
+    assert_eq!(
+        emitted(Verbosity::Verbose, Level::Debug),
+        "debug: MD file not found\n"
+    );
+    for level in [Level::Warn, Level::Info, Level::Debug] {
+        assert_eq!(emitted(Verbosity::Quiet, level), "");
+    }
+
+    // Suppressed messages aren't even built.
+    let mut out = Vec::new();
+    write_log(&mut out, Verbosity::Quiet, Level::Warn, || unreachable!());
+}
+
+#[test]
+fn test_take_verbosity() {
+    use log::{take_verbosity, Verbosity};
+
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        take_verbosity(&args(&["--print-config", "-v"])),
+        (Verbosity::Verbose, args(&["--print-config"]))
+    );
+    assert_eq!(
+        take_verbosity(&args(&["--quiet"])),
+        (Verbosity::Quiet, args(&[]))
+    );
+    assert_eq!(take_verbosity(&args(&["-v", "-q"])).0, Verbosity::Quiet);
+    assert_eq!(
+        take_verbosity(&args(&["x"])),
+        (Verbosity::Normal, args(&["x"]))
+    );
+}
@@ -0,0 +1,321 @@
+use ai_log_exporter::{
+    build_footer_block, canonical_role, dated_thread_dir, extract_timestamp, fallback_title,
+    filename_budget, find_session_note, generate_title, merge_frontmatter_template,
+    normalize_markdown, note_filename_within, note_identity, note_title, parse_timestamp_in,
+    read_frontmatter_template, read_to_string_lossy, remove_cwd_fields, resolve_created, safe_id,
+    synthesized_id, upsert_aliases_field, upsert_footer_block, upsert_frontmatter_field,
+    upsert_marked_block, upsert_project_path_field, with_lock_file, yaml_quote, yaml_scalar,
+    Config, MAX_PATH_LEN,
+};
+use ai_log_exporter::log;
+use ai_log_exporter::transcript::{build_transcript_block, Msg, RenderOptions, BEGIN, END};
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local, SecondsFormat};
+use serde_json::Value;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+pub const TOOL: &str = "Windsurf";
+
+fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (verbosity, args) = log::take_verbosity(&args);
+    log::set_verbosity(verbosity);
+    if args.first().map(String::as_str) == Some("--print-config") {
+        let cwd = args.get(1).map(String::as_str).unwrap_or(".");
+        print!("{}", Config::from_env()?.describe(cwd));
+        return Ok(());
+    }
+    let (cwd, paths) = take_cwd(&args)?;
+    if paths.is_empty() {
+        return Err(anyhow!(
+            "usage: windsurf_to_obsidian <export.json>... [--cwd <dir>]"
+        ));
+    }
+
+    let config = Config::from_env()?;
+    for path in &paths {
+        let path = Path::new(path);
+        let export = read_cascade_export(path, &config)?;
+        match export_conversation(&config, &export, cwd.as_deref(), path)? {
+            Some(note) => println!("{}", note.display()),
+            None => log::info(|| format!("nothing to export: {}", path.display())),
+        }
+    }
+    Ok(())
+}
+
+// Pulls `--cwd <dir>` out of the arguments; it overrides the export's
+// `workspace` when resolving the project.
+pub fn take_cwd(args: &[String]) -> Result<(Option<String>, Vec<String>)> {
+    let mut cwd = None;
+    let mut rest = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--cwd" {
+            cwd = Some(args.next().context("missing value for --cwd")?.clone());
+        } else if let Some(value) = arg.strip_prefix("--cwd=") {
+            cwd = Some(value.to_string());
+        } else {
+            rest.push(arg.clone());
+        }
+    }
+    Ok((cwd, rest))
+}
+
+// One Cascade conversation as exported from Windsurf; see the README for the
+// JSON it is read from.
+#[derive(Debug, Clone)]
+pub struct CascadeExport {
+    pub id: String,
+    pub title: Option<String>,
+    pub workspace: Option<String>,
+    // The export's own `created_at`; the earliest message time wins over it.
+    pub created: Option<DateTime<Local>>,
+    pub msgs: Vec<Msg>,
+}
+
+pub fn read_cascade_export(path: &Path, config: &Config) -> Result<CascadeExport> {
+    let text = read_to_string_lossy(path)
+        .with_context(|| format!("failed to read Cascade export: {}", path.display()))?;
+    let json: Value = serde_json::from_str(&text)
+        .with_context(|| format!("failed to parse Cascade export: {}", path.display()))?;
+    parse_cascade_export(&json, config)
+}
+
+// `cascade` is what Windsurf calls its own turns; every other role goes
+// through the shared aliases, so `tool` and `system` entries are dropped.
+pub fn message_role(role: &str, aliases: &[(String, String)]) -> Option<&'static str> {
+    if role.trim().eq_ignore_ascii_case("cascade") {
+        return Some("assistant");
+    }
+    canonical_role(role, aliases)
+}
+
+pub fn parse_cascade_export(json: &Value, config: &Config) -> Result<CascadeExport> {
+    let entries = json
+        .get("messages")
+        .and_then(Value::as_array)
+        .context("not a Cascade export: missing \"messages\" array")?;
+    let str_field = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|k| json.get(*k).and_then(Value::as_str))
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+
+    let text_options = config.text_options();
+    let mut msgs = Vec::new();
+    for entry in entries {
+        let role = match entry.get("role").and_then(Value::as_str) {
+            Some(role) => role,
+            None => continue,
+        };
+        let role = match message_role(role, &config.role_aliases) {
+            Some(role) => role,
+            None => continue,
+        };
+        let text = entry
+            .get("content")
+            .and_then(|c| text_options.extract(c))
+            .unwrap_or_default();
+        if text.trim().is_empty() {
+            continue;
+        }
+        msgs.push(Msg {
+            role,
+            text,
+            ts: extract_timestamp(entry, config.assume_tz),
+            resumed: false,
+            usage: None,
+        });
+    }
+
+    let workspace = str_field(&["workspace", "cwd"]);
+    let id = match str_field(&["id", "cascade_id", "conversation_id"]) {
+        Some(id) => id,
+        None => {
+            let first = msgs.iter().find(|m| m.role == "user");
+            let date = first
+                .and_then(|m| m.ts)
+                .map_or_else(|| Local::now().date_naive(), |t| t.date_naive());
+            let text = first.map_or("", |m| m.text.as_str());
+            synthesized_id("cascade", workspace.as_deref().unwrap_or("."), text, date)
+        }
+    };
+    Ok(CascadeExport {
+        id,
+        title: str_field(&["title"]),
+        workspace,
+        created: str_field(&["created_at"])
+            .and_then(|s| parse_timestamp_in(&s, config.assume_tz))
+            .or_else(|| extract_timestamp(json, config.assume_tz)),
+        msgs,
+    })
+}
+
+// Returns the note path, or `None` when the conversation had no messages or
+// is ignored.
+pub fn export_conversation(
+    config: &Config,
+    export: &CascadeExport,
+    cwd: Option<&str>,
+    source: &Path,
+) -> Result<Option<PathBuf>> {
+    let cwd = cwd.or(export.workspace.as_deref()).unwrap_or(".");
+    let id = export.id.as_str();
+    if config.is_ignored(id, cwd) || export.msgs.is_empty() {
+        return Ok(None);
+    }
+
+    let mut msgs = export.msgs.clone();
+    if let Some(redactor) = config.redactor()? {
+        for m in &mut msgs {
+            m.text = redactor.redact(&m.text);
+        }
+    }
+
+    let project = config.project_name(cwd);
+    let base_dir = config
+        .ai_dir()?
+        .join(config.windsurf_dir_name())
+        .join(&project);
+    let md_dir = base_dir.join("Threads");
+    fs::create_dir_all(&md_dir).context("failed to create md_dir")?;
+    let id_safe = safe_id(id, "unknown-conversation");
+    let formats = &config.formats;
+
+    let lock_path = md_dir.join(format!(".lock_{id_safe}"));
+    let note = with_lock_file(&lock_path, || {
+        let created = resolve_created(msgs.iter().map(|m| m.ts).chain([export.created]));
+        let uid = note_identity(TOOL, id);
+        let title_msg = export.title.clone().or_else(|| {
+            msgs.iter()
+                .find(|m| m.role == "user")
+                .map(|m| m.text.clone())
+        });
+        let md_path = find_or_create_md_path(
+            &md_dir,
+            &id_safe,
+            &uid,
+            title_msg.as_deref(),
+            export.title.is_some(),
+            created,
+            config,
+        )
+        .context("failed to find or create md path")?;
+
+        let note_cwd = (!config.omit_cwd).then_some(cwd);
+        let existing = if md_path.exists() {
+            read_to_string_lossy(&md_path).context("failed to read existing md note")?
+        } else {
+            build_windsurf_note_skeleton(&project, id, note_cwd, created)
+        };
+        let existing = upsert_frontmatter_field(&existing, "uid", &format!("\"{uid}\""));
+        let existing = match note_cwd {
+            Some(_) => existing,
+            None => remove_cwd_fields(&existing),
+        };
+        let project_link = config.vault_link(&base_dir);
+        let existing = upsert_project_path_field(
+            &existing,
+            config.project_path,
+            note_cwd,
+            &project,
+            &project_link,
+        );
+        let title = note_title(&md_path, &id_safe, formats);
+        let existing = upsert_aliases_field(&existing, title.as_deref());
+        let existing = merge_frontmatter_template(&existing, &read_frontmatter_template(&base_dir));
+
+        let exported = Local::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+        let block = build_transcript_block(
+            &exported,
+            &config.source_label(source),
+            &msgs,
+            &RenderOptions::from_config(config),
+        );
+        let mut updated = upsert_marked_block(&existing, BEGIN, END, block.trim_end());
+        if config.footer_nav {
+            updated = upsert_footer_block(&updated, &build_footer_block(&project, &project_link));
+        }
+        config
+            .write_output(&md_path, &normalize_markdown(&updated))
+            .context("failed to write md note")?;
+        Ok(Some(md_path))
+    })?;
+    if let Some(note) = &note {
+        config.refresh_overview(&base_dir, note);
+    }
+    Ok(note)
+}
+
+// An export's own title names the file as is; otherwise the first prompt is
+// summarised like the other tools' notes.
+fn find_or_create_md_path(
+    md_dir: &Path,
+    id: &str,
+    uid: &str,
+    title_msg: Option<&str>,
+    titled: bool,
+    created: DateTime<Local>,
+    config: &Config,
+) -> Result<PathBuf> {
+    // A note renamed away from its conversation id is still found by its `uid:`.
+    if let Some(existing) = find_session_note(md_dir, id, uid) {
+        return Ok(existing);
+    }
+
+    let day_dir = dated_thread_dir(md_dir, created.date_naive(), config.monthly_folders);
+    fs::create_dir_all(&day_dir).context("failed to create dated Threads dir")?;
+
+    let title = match title_msg.map(fallback_title) {
+        Some(title) if titled && !title.is_empty() => title,
+        _ => generate_title(title_msg, config.title_llm()),
+    };
+    let budget = filename_budget(&day_dir, MAX_PATH_LEN);
+    let filename = note_filename_within(&config.formats, created.date_naive(), &title, id, budget);
+    Ok(day_dir.join(filename))
+}
+
+pub fn build_windsurf_note_skeleton(
+    project: &str,
+    conversation_id: &str,
+    cwd: Option<&str>,
+    created: DateTime<Local>,
+) -> String {
+    let created = created.to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    let project_q = yaml_quote(project);
+    let project_tag = yaml_scalar(project);
+    let id_q = yaml_quote(conversation_id);
+    let uid = note_identity(TOOL, conversation_id);
+    // `None` (LLM_LOGS_OMIT_CWD) leaves the line out altogether.
+    let cwd_line = match cwd {
+        Some(cwd) => format!("cwd: \"{}\"\n", yaml_quote(cwd)),
+        None => String::new(),
+    };
+
+    format!(
+        r#"---
+tool: "{TOOL}"
+project: "{project_q}"
+conversation_id: "{id_q}"
+uid: "{uid}"
+{cwd_line}created: "{created}"
+tags:
+  - ai-log
+  - windsurf
+  - {project_tag}
+---
+
+"#
+    )
+}
+
+#[cfg(test)]
+#[path = "tests.rs"]
+mod tests;
@@ -0,0 +1,165 @@
+use ai_log_exporter::parse_rfc3339_local;
+use super::*;
+use serde_json::json;
+
+fn test_config(dir: &Path) -> Config {
+    Config {
+        vault: Some(dir.to_path_buf()),
+        ai_root: Some(PathBuf::from("llms")),
+        plain_titles: true,
+        ..Config::default()
+    }
+}
+
+const SAMPLE_EXPORT: &str = r#"{
+  "id": "cascade-abc123",
+  "title": "Fix login redirect",
+  "workspace": "/work/webapp",
+  "created_at": "2026-01-05T09:00:00Z",
+  "messages": [
+    {"role": "user", "content": "Why does login loop?", "timestamp": "2026-01-05T09:00:10Z"},
+    {"role": "tool", "content": "grep output", "timestamp": "2026-01-05T09:00:12Z"},
+    {"role": "cascade", "content": [
+      {"type": "text", "text": "The redirect drops the session cookie."},
+      {"type": "text", "text": "Set SameSite=Lax."}
+    ], "timestamp": "2026-01-05T09:00:20Z"},
+    {"role": "system", "content": "memory updated"},
+    {"role": "user", "content": "Thanks", "timestamp": "2026-01-05T09:01:00Z"},
+    {"role": "assistant", "content": "   ", "timestamp": "2026-01-05T09:01:02Z"}
+  ]
+}"#;
+
+fn sample() -> CascadeExport {
+    let json: Value = serde_json::from_str(SAMPLE_EXPORT).unwrap();
+    parse_cascade_export(&json, &Config::default()).unwrap()
+}
+
+#[test]
+fn test_parse_cascade_export_reads_sample() {
+    let export = sample();
+    assert_eq!(export.id, "cascade-abc123");
+    assert_eq!(export.title.as_deref(), Some("Fix login redirect"));
+    assert_eq!(export.workspace.as_deref(), Some("/work/webapp"));
+    assert_eq!(export.created, parse_rfc3339_local("2026-01-05T09:00:00Z"));
+
+    // `tool`/`system` entries and the blank answer are dropped.
+    let roles: Vec<&str> = export.msgs.iter().map(|m| m.role).collect();
+    assert_eq!(roles, ["user", "assistant", "user"]);
+    assert!(export.msgs[1].text.contains("session cookie"));
+    assert!(export.msgs[1].text.contains("SameSite=Lax."));
+    assert_eq!(
+        export.msgs[0].ts,
+        parse_rfc3339_local("2026-01-05T09:00:10Z")
+    );
+}
+
+#[test]
+fn test_parse_cascade_export_without_id_is_stable() {
+    let json = json!({
+        "workspace": "/work/webapp",
+        "messages": [{"role": "user", "content": "hi", "timestamp": "2026-01-05T09:00:00Z"}],
+    });
+    let first = parse_cascade_export(&json, &Config::default()).unwrap();
+    let again = parse_cascade_export(&json, &Config::default()).unwrap();
+    assert!(first.id.starts_with("cascade-"));
+    assert_eq!(first.id, again.id);
+    assert_eq!(first.title, None);
+}
+
+#[test]
+fn test_parse_cascade_export_rejects_other_json() {
+    let err = parse_cascade_export(&json!({"id": "x"}), &Config::default()).unwrap_err();
+    assert!(err.to_string().contains("\"messages\""));
+    assert!(parse_cascade_export(&json!([1, 2]), &Config::default()).is_err());
+}
+
+#[test]
+fn test_message_role_maps_cascade_and_aliases() {
+    assert_eq!(message_role("Cascade", &[]), Some("assistant"));
+    assert_eq!(message_role("user", &[]), Some("user"));
+    assert_eq!(message_role("model", &[]), Some("assistant"));
+    assert_eq!(message_role("tool", &[]), None);
+    let aliases = [("me".to_string(), "user".to_string())];
+    assert_eq!(message_role("me", &aliases), Some("user"));
+}
+
+#[test]
+fn test_take_cwd() {
+    let args: Vec<String> = ["a.json", "--cwd", "/work/x", "b.json"]
+        .map(String::from)
+        .to_vec();
+    let (cwd, rest) = take_cwd(&args).unwrap();
+    assert_eq!(cwd.as_deref(), Some("/work/x"));
+    assert_eq!(rest, ["a.json", "b.json"]);
+    assert!(take_cwd(&["--cwd".to_string()]).is_err());
+}
+
+#[test]
+fn test_build_windsurf_note_skeleton() {
+    let skeleton = build_windsurf_note_skeleton("webapp", "c1", Some("/work/webapp"), Local::now());
+    assert!(skeleton.contains("tool: \"Windsurf\""));
+    assert!(skeleton.contains("conversation_id: \"c1\""));
+    assert!(skeleton.contains("cwd: \"/work/webapp\""));
+    assert!(skeleton.contains("  - windsurf\n"));
+    assert!(skeleton.contains(&format!("uid: \"{}\"", note_identity(TOOL, "c1"))));
+
+    let skeleton = build_windsurf_note_skeleton("webapp", "c1", None, Local::now());
+    assert!(!skeleton.contains("cwd:"));
+}
+
+#[test]
+fn test_export_conversation_writes_and_updates_note() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let config = test_config(dir);
+    let export = sample();
+    let source = Path::new("/exports/cascade.json");
+
+    let note_path = export_conversation(&config, &export, None, source)
+        .unwrap()
+        .unwrap();
+    let threads = dir.join("llms/Windsurf/webapp/Threads");
+    assert!(note_path.starts_with(&threads), "{}", note_path.display());
+    assert!(note_path.to_string_lossy().contains("fix-login-redirect"));
+    let note = fs::read_to_string(&note_path).unwrap();
+    assert!(note.contains("- windsurf\n"));
+    assert!(note.contains("Source transcript: /exports/cascade.json"));
+    assert!(note.contains(" User\nWhy does login loop?"));
+    assert!(note.contains(" Assistant\nThe redirect drops the session cookie."));
+    assert!(!note.contains("grep output"));
+
+    // A longer export of the same conversation replaces the transcript block.
+    let mut longer = export.clone();
+    longer.msgs.push(Msg {
+        role: "assistant",
+        text: "You're welcome.".to_string(),
+        ts: None,
+        resumed: false,
+        usage: None,
+    });
+    let again = export_conversation(&config, &longer, None, source)
+        .unwrap()
+        .unwrap();
+    assert_eq!(again, note_path);
+    let note = fs::read_to_string(&note_path).unwrap();
+    assert_eq!(note.matches(BEGIN).count(), 1);
+    assert!(note.contains("You're welcome."));
+}
+
+#[test]
+fn test_export_conversation_cwd_flag_overrides_workspace() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    let config = test_config(dir);
+    let note_path = export_conversation(&config, &sample(), Some("/work/other"), Path::new("x"))
+        .unwrap()
+        .unwrap();
+    assert!(note_path.starts_with(dir.join("llms/Windsurf/other/Threads")));
+
+    let mut empty = sample();
+    empty.msgs.clear();
+    assert_eq!(
+        export_conversation(&config, &empty, None, Path::new("x")).unwrap(),
+        None
+    );
+}
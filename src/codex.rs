@@ -0,0 +1,140 @@
+//! Codex CLI turn formatting, shared by `codex_notify_to_obsidian` and any
+//! tool that wants to write turns into the same notes.
+
+use crate::{elide_data_uris, escape_marker_lines, find_marker, section_gap};
+use chrono::{DateTime, Local};
+use serde_json::Value;
+
+pub const BEGIN: &str = "<!-- BEGIN AUTO TURNS -->";
+pub const END: &str = "<!-- END AUTO TURNS -->";
+
+// Turns without a turn-id share this sentinel, so it never deduplicates.
+pub const MISSING_SENTINEL: &str = "<!-- turn-id:(missing) -->";
+
+// One user→assistant exchange as Codex reports it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turn {
+    // `None` (or blank) renders with `MISSING_SENTINEL`.
+    pub turn_id: Option<String>,
+    // A list of prompts, rendered as bullets, or a single prompt string.
+    // `Null` (no `input-messages` at all) leaves out the User section.
+    pub input_messages: Value,
+    pub last_assistant: String,
+    // `None` when the turn's time isn't known, such as a turn rebuilt from a
+    // raw notification without a timestamp.
+    pub ts: Option<DateTime<Local>>,
+}
+
+// The turn as a note block: its sentinel line, then the user and assistant
+// sections headed with `ts` in `time_fmt`, or with `missing_time` (the time
+// left out if unset) when it has none.
+pub fn render_turn(
+    turn: &Turn,
+    time_fmt: &str,
+    missing_time: Option<&str>,
+    blank_lines: usize,
+) -> String {
+    let sentinel = match turn.turn_id.as_deref().filter(|id| !id.trim().is_empty()) {
+        Some(id) => format!("<!-- turn-id:{id} -->"),
+        None => MISSING_SENTINEL.to_string(),
+    };
+    let heading_time = match turn.ts {
+        Some(ts) => ts.format(time_fmt).to_string(),
+        None => missing_time.unwrap_or_default().to_string(),
+    };
+    format_turn(
+        &sentinel,
+        &heading_time,
+        &turn.input_messages,
+        &turn.last_assistant,
+        blank_lines,
+    )
+}
+
+fn format_turn(
+    sentinel: &str,
+    now: &str,
+    input_messages: &Value,
+    last_assistant: &str,
+    blank_lines: usize,
+) -> String {
+    let user_part = match input_messages {
+        Value::Null => None,
+        Value::Array(arr) => {
+            let mut lines: Vec<String> = Vec::new();
+            for v in arr {
+                if let Some(s) = v.as_str() {
+                    let s = s.trim();
+                    if !s.is_empty() {
+                        lines.push(format!("- {s}"));
+                    }
+                }
+            }
+            if lines.is_empty() {
+                Some("- (empty)".to_string())
+            } else {
+                Some(lines.join("\n"))
+            }
+        }
+        Value::String(s) => {
+            let s = s.trim();
+            if s.is_empty() {
+                Some("(empty)".to_string())
+            } else {
+                Some(s.to_string())
+            }
+        }
+        _ => Some("- (empty)".to_string()),
+    };
+
+    let gap = section_gap(blank_lines);
+    let heading = |who: &str| match now.trim() {
+        "" => format!("### {who}\n"),
+        now => format!("### {now} {who}\n"),
+    };
+    let user_section = match user_part {
+        Some(part) => format!("{}{}{gap}", heading("User"), escape_marker_lines(&part)),
+        None => String::new(),
+    };
+    let block = format!(
+        "{sentinel}{gap}{user_section}{}{assistant}{gap}",
+        heading("Assistant"),
+        assistant = escape_marker_lines(last_assistant.trim_end())
+    );
+    elide_data_uris(&block)
+}
+
+pub fn ensure_turns_block(s: &str) -> String {
+    if find_marker(s, BEGIN).is_some() && find_marker(s, END).is_some() {
+        return s.to_string();
+    }
+    format!("{}\n\n{}\n## Turns (auto)\n{}\n", s.trim_end(), BEGIN, END)
+}
+
+pub fn insert_before_end(s: &str, block: &str, blank_lines: usize) -> String {
+    if let Some(pos) = find_marker(s, END) {
+        let (pre, post) = s.split_at(pos);
+        // END with nothing before it would put the block above the frontmatter:
+        // append the block after the rest of the note and close it with END.
+        if pre.trim().is_empty() {
+            let rest = post[END.len()..].trim();
+            let block = block.trim_end();
+            return if rest.is_empty() {
+                format!("{block}\n{END}\n")
+            } else {
+                format!("{rest}\n\n{block}\n{END}\n")
+            };
+        }
+        format!(
+            "{pre}{gap}{block}\n{post}",
+            pre = pre.trim_end(),
+            gap = section_gap(blank_lines),
+            block = block.trim_end()
+        )
+    } else {
+        format!("{}\n\n{}", s.trim_end(), block.trim_end())
+    }
+}
+
+#[cfg(test)]
+mod tests;
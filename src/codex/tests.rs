@@ -0,0 +1,184 @@
+use super::*;
+use crate::DEFAULT_TIME_FMT;
+use chrono::TimeZone;
+use serde_json::json;
+
+fn turn(id: &str, input_messages: Value, last_assistant: &str) -> Turn {
+    Turn {
+        turn_id: Some(id.to_string()),
+        input_messages,
+        last_assistant: last_assistant.to_string(),
+        ts: Some(Local::now()),
+    }
+}
+
+#[test]
+fn test_ensure_turns_block_adds_markers_when_missing() {
+    let input = "# Title\n\nSome content";
+    let result = ensure_turns_block(input);
+
+    assert!(result.contains(BEGIN));
+    assert!(result.contains(END));
+    assert!(result.contains("## Turns (auto)"));
+}
+
+#[test]
+fn test_ensure_turns_block_preserves_existing() {
+    let input = format!("# Title\n\n{}\nexisting\n{}", BEGIN, END);
+    let result = ensure_turns_block(&input);
+
+    assert_eq!(result, input);
+}
+
+#[test]
+fn test_insert_before_end_inserts_correctly() {
+    let input = format!("# Title\n\n{}\n{}", BEGIN, END);
+    let block = "new content";
+    let result = insert_before_end(&input, block, 1);
+
+    assert!(result.contains("new content"));
+    let end_pos = result.find(END).unwrap();
+    let content_pos = result.find("new content").unwrap();
+    assert!(content_pos < end_pos);
+}
+
+#[test]
+fn test_insert_before_end_appends_when_no_end_marker() {
+    let input = "# Title\n\nSome content";
+    let block = "new content";
+    let result = insert_before_end(input, block, 1);
+
+    assert!(result.contains("new content"));
+    assert!(result.contains("# Title"));
+}
+
+#[test]
+fn test_insert_before_end_with_end_at_start_keeps_frontmatter_first() {
+    let input = format!("{END}\n---\nthread_id: \"t\"\n---\n\n# Title\n");
+    let result = insert_before_end(&input, "new content\n", 1);
+
+    assert!(result.starts_with("---\nthread_id: \"t\"\n---\n"));
+    assert!(result.ends_with("# Title\n\nnew content\n<!-- END AUTO TURNS -->\n"));
+    assert_eq!(result.matches(END).count(), 1);
+}
+
+#[test]
+fn test_insert_before_end_with_only_end_marker() {
+    let result = insert_before_end(&format!("\n  {END}"), "new content", 1);
+    assert_eq!(result, format!("new content\n{END}\n"));
+}
+
+#[test]
+fn test_render_turn_with_array_input() {
+    let input = json!(["user message 1", "user message 2"]);
+    let sentinel = "<!-- turn-id:test123 -->";
+    let turn = turn("test123", input, "assistant response");
+    let result = render_turn(&turn, DEFAULT_TIME_FMT, None, 1);
+
+    assert!(result.contains(sentinel));
+    assert!(result.contains("- user message 1"));
+    assert!(result.contains("- user message 2"));
+    assert!(result.contains("assistant response"));
+    assert!(result.contains("User"));
+    assert!(result.contains("Assistant"));
+}
+
+#[test]
+fn test_render_turn_with_string_input() {
+    let input = json!("single user message");
+    let result = render_turn(
+        &turn("test456", input, "response"),
+        DEFAULT_TIME_FMT,
+        None,
+        1,
+    );
+
+    assert!(result.contains("single user message"));
+    assert!(result.contains("response"));
+}
+
+#[test]
+fn test_render_turn_with_empty_array() {
+    let input = json!([]);
+    let result = render_turn(&turn("test", input, "response"), DEFAULT_TIME_FMT, None, 1);
+
+    assert!(result.contains("- (empty)"));
+}
+
+#[test]
+fn test_render_turn_without_input_messages_has_only_assistant() {
+    let sentinel = "<!-- turn-id:no-input -->";
+    let no_input = turn("no-input", Value::Null, "Background task finished");
+    let result = render_turn(&no_input, DEFAULT_TIME_FMT, None, 1);
+
+    assert!(result.starts_with(sentinel));
+    assert!(!result.contains("User"));
+    assert!(!result.contains("(empty)"));
+    assert!(result.contains("Assistant\nBackground task finished"));
+}
+
+#[test]
+fn test_render_turn_uses_custom_time_format() {
+    let input = json!(["hi"]);
+    let result = render_turn(&turn("t", input, "hello"), "%I:%M %p", None, 1);
+
+    let heading = result.lines().find(|l| l.ends_with(" User")).unwrap();
+    assert!(heading.ends_with("M User"));
+    assert!(!heading.contains('-'));
+}
+
+#[test]
+fn test_render_turn_uses_turn_timestamp() {
+    let turn = Turn {
+        turn_id: Some("t9".to_string()),
+        input_messages: json!(["ship it"]),
+        last_assistant: "shipped\n".to_string(),
+        ts: Some(Local.with_ymd_and_hms(2024, 3, 4, 5, 6, 7).unwrap()),
+    };
+    let result = render_turn(&turn, "%Y-%m-%d %H:%M", None, 1);
+
+    assert_eq!(
+        result,
+        "<!-- turn-id:t9 -->\n\n### 2024-03-04 05:06 User\n- ship it\n\n\
+         ### 2024-03-04 05:06 Assistant\nshipped\n\n"
+    );
+}
+
+#[test]
+fn test_render_turn_without_timestamp_uses_placeholder() {
+    let turn = Turn {
+        ts: None,
+        ..turn("t1", json!(["q"]), "a")
+    };
+    let result = render_turn(&turn, DEFAULT_TIME_FMT, Some("--:--"), 1);
+    assert!(result.contains("\n### --:-- User\n- q\n\n### --:-- Assistant\na\n"));
+    let result = render_turn(&turn, DEFAULT_TIME_FMT, None, 1);
+    assert!(
+        result.contains("\n### User\n- q\n\n### Assistant\na\n"),
+        "{result}"
+    );
+}
+
+#[test]
+fn test_render_turn_without_turn_id_uses_missing_sentinel() {
+    for turn_id in [None, Some(String::new()), Some("  ".to_string())] {
+        let turn = Turn {
+            turn_id,
+            input_messages: json!("question"),
+            last_assistant: "answer".to_string(),
+            ts: Some(Local::now()),
+        };
+        let result = render_turn(&turn, DEFAULT_TIME_FMT, None, 2);
+        assert!(result.starts_with(&format!("{MISSING_SENTINEL}\n\n\n### ")));
+        assert!(result.contains(" User\nquestion\n\n\n### "));
+    }
+}
+
+#[test]
+fn test_render_turn_elides_data_uris() {
+    let payload = "A".repeat(4096);
+    let input = json!([format!("look: data:image/jpeg;base64,{payload}")]);
+    let block = render_turn(&turn("t1", input, "ok"), DEFAULT_TIME_FMT, None, 1);
+    assert!(block.contains("- look: [data:image/jpeg;base64 (3KB) elided]"));
+    assert!(!block.contains(&payload));
+}
@@ -0,0 +1,173 @@
+//! Optional SQLite index of every exported message (`LLM_LOGS_SQLITE=1`), kept
+//! next to the notes so sessions can be queried without grepping Markdown.
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, TransactionBehavior};
+use std::path::{Path, PathBuf};
+
+pub const INDEX_FILE: &str = "_index.sqlite";
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS messages (
+    tool TEXT NOT NULL,
+    project TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    idx INTEGER NOT NULL,
+    role TEXT NOT NULL,
+    ts TEXT,
+    text TEXT NOT NULL,
+    PRIMARY KEY (session_id, idx)
+)";
+
+// Turns already appended by `index_turn`, so a redelivered turn is not
+// indexed twice.
+const TURNS_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS turns (
+    session_id TEXT NOT NULL,
+    turn_id TEXT NOT NULL,
+    PRIMARY KEY (session_id, turn_id)
+)";
+
+// One row of the `messages` table; `ts` is RFC 3339 when known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedMessage {
+    pub role: String,
+    pub ts: Option<String>,
+    pub text: String,
+}
+
+pub fn index_path(ai_dir: &Path) -> PathBuf {
+    ai_dir.join(INDEX_FILE)
+}
+
+fn open_index(db_path: &Path) -> Result<Connection> {
+    let conn = Connection::open(db_path)
+        .with_context(|| format!("failed to open index: {}", db_path.display()))?;
+    conn.execute(SCHEMA, [])
+        .context("failed to create messages table")?;
+    Ok(conn)
+}
+
+// Rows are keyed by (session_id, position in the session), so re-exporting a
+// session updates its rows in place. Rows past the end of `messages` are left
+// over from a longer earlier export and get dropped.
+pub fn index_session(
+    db_path: &Path,
+    tool: &str,
+    project: &str,
+    session_id: &str,
+    messages: &[IndexedMessage],
+) -> Result<()> {
+    let mut conn = open_index(db_path)?;
+    let tx = conn.transaction()?;
+    {
+        let mut upsert = tx.prepare(
+            "INSERT INTO messages (tool, project, session_id, idx, role, ts, text)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT (session_id, idx) DO UPDATE SET
+                 tool = excluded.tool,
+                 project = excluded.project,
+                 role = excluded.role,
+                 ts = excluded.ts,
+                 text = excluded.text",
+        )?;
+        for (idx, m) in messages.iter().enumerate() {
+            upsert.execute(params![
+                tool, project, session_id, idx, m.role, m.ts, m.text
+            ])?;
+        }
+    }
+    tx.execute(
+        "DELETE FROM messages WHERE session_id = ?1 AND idx >= ?2",
+        params![session_id, messages.len()],
+    )?;
+    tx.commit().context("failed to update index")?;
+    Ok(())
+}
+
+// Appends one turn's rows after the session's last row, for tools (Codex)
+// that deliver a session a turn at a time. Returns `false` when the turn was
+// already indexed for the session and nothing was written: by `turn_id`, or
+// without one when the session's last rows are exactly `messages`.
+pub fn index_turn(
+    db_path: &Path,
+    tool: &str,
+    project: &str,
+    session_id: &str,
+    turn_id: Option<&str>,
+    messages: &[IndexedMessage],
+) -> Result<bool> {
+    let mut conn = open_index(db_path)?;
+    conn.execute(TURNS_SCHEMA, [])
+        .context("failed to create turns table")?;
+
+    // Immediate, so two appenders can't both read the same next `idx`.
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    if let Some(turn_id) = turn_id {
+        let new = tx.execute(
+            "INSERT OR IGNORE INTO turns (session_id, turn_id) VALUES (?1, ?2)",
+            params![session_id, turn_id],
+        )?;
+        if new == 0 {
+            return Ok(false);
+        }
+    } else if !messages.is_empty() && ends_with_rows(&tx, session_id, messages)? {
+        return Ok(false);
+    }
+    let next: i64 = tx.query_row(
+        "SELECT COALESCE(MAX(idx) + 1, 0) FROM messages WHERE session_id = ?1",
+        params![session_id],
+        |row| row.get(0),
+    )?;
+    {
+        let mut insert = tx.prepare(
+            "INSERT INTO messages (tool, project, session_id, idx, role, ts, text)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        for (idx, m) in (next..).zip(messages) {
+            insert.execute(params![
+                tool, project, session_id, idx, m.role, m.ts, m.text
+            ])?;
+        }
+    }
+    tx.commit().context("failed to update index")?;
+    Ok(true)
+}
+
+// Whether the session's last rows are `messages`, i.e. the same turn
+// redelivered.
+fn ends_with_rows(
+    conn: &Connection,
+    session_id: &str,
+    messages: &[IndexedMessage],
+) -> Result<bool> {
+    let mut stmt = conn.prepare(
+        "SELECT role, ts, text FROM messages WHERE session_id = ?1
+         ORDER BY idx DESC LIMIT ?2",
+    )?;
+    let mut tail = stmt
+        .query_map(params![session_id, messages.len()], |row| {
+            Ok(IndexedMessage {
+                role: row.get(0)?,
+                ts: row.get(1)?,
+                text: row.get(2)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    tail.reverse();
+    Ok(tail == messages)
+}
+
+// Drops every row of the session, e.g. when it is purged.
+pub fn remove_session(db_path: &Path, session_id: &str) -> Result<()> {
+    let conn = open_index(db_path)?;
+    conn.execute(TURNS_SCHEMA, [])
+        .context("failed to create turns table")?;
+    conn.execute(
+        "DELETE FROM messages WHERE session_id = ?1",
+        params![session_id],
+    )?;
+    conn.execute(
+        "DELETE FROM turns WHERE session_id = ?1",
+        params![session_id],
+    )?;
+    Ok(())
+}
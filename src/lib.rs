@@ -1,34 +1,82 @@
 use anyhow::{anyhow, Context, Result};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::{
+    collections::BTreeMap,
     fs,
     fs::OpenOptions,
-    io::{self, Write},
+    io::{self, BufRead, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
-    process::Command,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
     thread,
     time::{Duration, Instant, SystemTime},
 };
 
-pub fn git_project_name(cwd: &str) -> String {
-    let out = Command::new("git")
+pub mod codex;
+pub mod index;
+pub mod log;
+pub mod redact;
+pub mod transcript;
+
+pub const GIT_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Runs `git -C <cwd> <args>` and returns its trimmed stdout. A missing git,
+// a non-zero exit, non-UTF-8 output and a git that is still running after
+// `GIT_TIMEOUT` (e.g. waiting on a network remote) all come back as `None`.
+pub fn run_git(cwd: &str, args: &[&str]) -> Option<String> {
+    let mut child = Command::new("git")
         .arg("-C")
         .arg(cwd)
-        .arg("rev-parse")
-        .arg("--show-toplevel")
-        .output();
-
-    if let Ok(out) = out {
-        if out.status.success() {
-            if let Ok(s) = String::from_utf8(out.stdout) {
-                let p = Path::new(s.trim());
-                if let Some(name) = p.file_name().and_then(|n| n.to_str()) {
-                    if !name.trim().is_empty() {
-                        return name.to_string();
-                    }
-                }
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    // Drained on its own thread: output larger than the pipe buffer would
+    // otherwise block git until the timeout kills it.
+    let mut stdout = child.stdout.take()?;
+    let reader = thread::spawn(move || {
+        let mut out = String::new();
+        stdout.read_to_string(&mut out).ok().map(|_| out)
+    });
+    wait_with_timeout(&mut child, GIT_TIMEOUT).filter(|s| s.success())?;
+    let out = reader.join().ok()??;
+    Some(out.trim().to_string())
+}
+
+// Polls `child` until it exits; past `timeout` it is killed and `None` returned.
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Option<ExitStatus> {
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Some(status),
+            Ok(None) if started.elapsed() < timeout => thread::sleep(Duration::from_millis(20)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
             }
         }
     }
+}
+
+pub fn git_project_name(cwd: &str) -> String {
+    let toplevel = run_git(cwd, &["rev-parse", "--show-toplevel"]);
+    let name = toplevel
+        .as_deref()
+        .and_then(|s| Path::new(s).file_name())
+        .and_then(|n| n.to_str())
+        .filter(|n| !n.trim().is_empty());
+    if let Some(name) = name {
+        return name.to_string();
+    }
 
     Path::new(cwd)
         .file_name()
@@ -37,20 +85,86 @@ pub fn git_project_name(cwd: &str) -> String {
         .to_string()
 }
 
+// Pulls a valueless `flag` out of the arguments wherever it appears.
+pub fn take_flag(args: &[String], flag: &str) -> (bool, Vec<String>) {
+    let rest: Vec<String> = args.iter().filter(|a| *a != flag).cloned().collect();
+    (rest.len() != args.len(), rest)
+}
+
+// Parses `--flag value` / `--flag=value` pairs in order. Every flag takes a value.
+pub fn parse_cli_flags(args: &[String]) -> Result<Vec<(String, String)>> {
+    let mut flags = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            return Err(anyhow!("unexpected argument: {arg}"));
+        }
+        match arg.split_once('=') {
+            Some((flag, value)) => flags.push((flag.to_string(), value.to_string())),
+            None => {
+                let value = args
+                    .next()
+                    .with_context(|| format!("missing value for {arg}"))?;
+                flags.push((arg.clone(), value.clone()));
+            }
+        }
+    }
+    Ok(flags)
+}
+
+// Only a leading `~` or `~/` is expanded; `~user` forms are left untouched.
+pub fn expand_tilde(path: &str) -> PathBuf {
+    let home = std::env::var_os("HOME").filter(|h| !h.is_empty());
+    match (path, home) {
+        ("~", Some(home)) => PathBuf::from(home),
+        (p, Some(home)) if p.starts_with("~/") => PathBuf::from(home).join(&p[2..]),
+        (p, _) => PathBuf::from(p),
+    }
+}
+
+// `Path::join` with an absolute path discards the vault prefix, so the AI root
+// is trimmed of surrounding separators and must stay a plain relative path.
+pub fn normalize_ai_root(raw: &str) -> Result<PathBuf> {
+    let trimmed = raw.trim().trim_matches(|c| c == '/' || c == '\\');
+    if trimmed.is_empty() {
+        return Err(anyhow!(
+            "OBSIDIAN_AI_ROOT must name a folder inside the vault"
+        ));
+    }
+
+    let path = PathBuf::from(trimmed);
+    if !path
+        .components()
+        .all(|c| matches!(c, std::path::Component::Normal(_)))
+    {
+        return Err(anyhow!(
+            "OBSIDIAN_AI_ROOT must be a relative path inside the vault: {raw:?}"
+        ));
+    }
+    Ok(path)
+}
+
+pub const SLUG_FORBIDDEN: &[char] = &['/', '\\', ':', '\n', '\r', '\t'];
+
 pub fn safe_name(s: &str) -> String {
+    safe_name_with(s, '_')
+}
+
+// Like `safe_name`, but path-hostile characters become `replacement`.
+pub fn safe_name_with(s: &str, replacement: char) -> String {
     let mut tmp = String::with_capacity(s.len());
     for c in s.chars() {
         match c {
-            '/' | '\\' | ':' | '\n' | '\r' | '\t' => tmp.push('_'),
+            c if SLUG_FORBIDDEN.contains(&c) => tmp.push(replacement),
             _ => tmp.push(c),
         }
     }
     let collapsed = tmp.split_whitespace().collect::<Vec<_>>().join(" ");
-    if collapsed.chars().count() > 120 {
-        collapsed.chars().take(120).collect()
-    } else {
-        collapsed
-    }
+    let name: String = collapsed.chars().take(120).collect();
+    // A leading dot would make a hidden file (and `.`/`..` aren't names at
+    // all), so those dots are replaced too; inner dots as in `v1.2.3` stay.
+    let dots = name.len() - name.trim_start_matches('.').len();
+    format!("{}{}", replacement.to_string().repeat(dots), &name[dots..])
 }
 
 pub fn safe_id(raw: &str, fallback: &str) -> String {
@@ -80,6 +194,68 @@ pub fn safe_id(raw: &str, fallback: &str) -> String {
     format!("{base}{suffix}")
 }
 
+// Sessions listed here (by id or glob pattern, one per line) are never
+// exported. The file is read from the vault root and from the session's cwd.
+pub const IGNORE_FILE: &str = ".llmlogsignore";
+
+pub fn read_ignore_patterns(dirs: &[&Path]) -> Vec<String> {
+    read_pattern_files(dirs, IGNORE_FILE)
+}
+
+// Non-empty lines of `file_name` in each of `dirs` that has one; `#` starts a
+// comment line.
+pub fn read_pattern_files(dirs: &[&Path], file_name: &str) -> Vec<String> {
+    dirs.iter()
+        .filter_map(|dir| fs::read_to_string(dir.join(file_name)).ok())
+        .flat_map(|text| {
+            text.lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+pub fn is_ignored_id(patterns: &[String], id: &str) -> bool {
+    let id = id.trim();
+    patterns.iter().any(|p| glob_match(p, id))
+}
+
+// `*` matches any run of characters and `?` exactly one; everything else is
+// literal, so a plain id only matches itself.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    // Last `*` seen and the text position it currently stands in for.
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+// A stand-in for a missing session/thread id, derived from what the payload
+// does carry so that distinct id-less sessions get distinct notes while
+// repeated hooks for the same one keep landing on the same note.
+pub fn synthesized_id(prefix: &str, cwd: &str, first_message: &str, date: NaiveDate) -> String {
+    let key = format!("{}\n{}\n{date}", cwd.trim(), first_message.trim());
+    format!("{prefix}-{:016x}", fnv1a_64(&key))
+}
+
 fn fnv1a_64(input: &str) -> u64 {
     let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
     for b in input.as_bytes() {
@@ -89,198 +265,3442 @@ fn fnv1a_64(input: &str) -> u64 {
     hash
 }
 
-pub fn yaml_quote(s: &str) -> String {
-    s.replace('\\', "\\\\").replace('"', "\\\"")
+// The first prompt of a Claude Code transcript and when it was sent: the
+// first non-empty `user` entry outside sub-agents, read with the default text
+// options so every hook of a session agrees on it.
+pub fn first_claude_prompt(transcript_path: &Path) -> Option<(String, Option<DateTime<Local>>)> {
+    let reader = io::BufReader::new(fs::File::open(transcript_path).ok()?);
+    let text_options = TextOptions::default();
+    reader.split(b'\n').flatten().find_map(|line| {
+        let obj: Value = serde_json::from_str(String::from_utf8_lossy(&line).trim()).ok()?;
+        let typ = obj.get("type").and_then(|v| v.as_str()).unwrap_or("");
+        let sidechain = obj.get("isSidechain").and_then(|v| v.as_bool()) == Some(true);
+        if sidechain || canonical_role(typ, &[]) != Some("user") {
+            return None;
+        }
+        let content = obj.get("message").and_then(|m| m.get("content"))?;
+        let text = text_options.extract(content)?.trim().to_string();
+        (!text.is_empty()).then(|| (text, extract_timestamp(&obj, AssumedZone::default())))
+    })
 }
 
-pub fn generate_title(text: Option<&str>) -> String {
-    let text = match text {
-        Some(t) if !t.trim().is_empty() => t,
-        _ => return "untitled".to_string(),
+// Keyed on the first user message and the day it was sent, which stay the same
+// on every hook run for a session; an unreadable transcript falls back to today.
+pub fn synthesized_session_id(transcript_path: &Path, cwd: &str) -> String {
+    let first = first_claude_prompt(transcript_path);
+    let date = first
+        .as_ref()
+        .and_then(|(_, ts)| *ts)
+        .map_or_else(|| Local::now().date_naive(), |t| t.date_naive());
+    let text = first.as_ref().map_or("", |(text, _)| text.as_str());
+    synthesized_id("session", cwd, text, date)
+}
+
+pub fn build_claude_note_skeleton(
+    project: &str,
+    session_id: &str,
+    cwd: Option<&str>,
+    created: DateTime<Local>,
+    tool_call_count: usize,
+) -> String {
+    let created = created.to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    let project_q = yaml_quote(project);
+    let project_tag = yaml_scalar(project);
+    let session_q = yaml_quote(session_id);
+    let uid = note_identity("Claude Code", session_id);
+    // `None` (LLM_LOGS_OMIT_CWD) leaves the line out altogether.
+    let cwd_line = match cwd {
+        Some(cwd) => format!("cwd: \"{}\"\n", yaml_quote(cwd)),
+        None => String::new(),
     };
 
-    if let Some(title) = generate_title_with_llm(text) {
-        return title;
+    format!(
+        r#"---
+tool: "Claude Code"
+project: "{project_q}"
+session_id: "{session_q}"
+uid: "{uid}"
+{cwd_line}created: "{created}"
+tool_call_count: {tool_call_count}
+tags:
+  - ai-log
+  - claude
+  - {project_tag}
+---
+
+"#
+    )
+}
+
+// Where `marker` sits in `text`. Markers on a line of their own win, so a
+// message quoting one mid-sentence can't be mistaken for a block boundary; a
+// note with no such line (say, hand-edited text run into the marker) falls
+// back to every occurrence.
+pub fn marker_indices(text: &str, marker: &str) -> Vec<usize> {
+    let all: Vec<usize> = text.match_indices(marker).map(|(i, _)| i).collect();
+    let own_line: Vec<usize> = all
+        .iter()
+        .copied()
+        .filter(|&i| {
+            let rest = text[i + marker.len()..].trim_start_matches([' ', '\t']);
+            let line_start = i == 0 || text[..i].ends_with('\n');
+            line_start && (rest.is_empty() || rest.starts_with(['\n', '\r']))
+        })
+        .collect();
+    if own_line.is_empty() {
+        all
+    } else {
+        own_line
     }
+}
 
-    fallback_title(text)
+pub fn find_marker(text: &str, marker: &str) -> Option<usize> {
+    marker_indices(text, marker).first().copied()
 }
 
-pub fn generate_title_with_llm(text: &str) -> Option<String> {
-    let prompt = format!(
-        "Generate a short filename-safe title (English, max 20 chars, lowercase, hyphens only, no spaces) for this conversation. Output ONLY the title, nothing else:\n\n{}",
-        text.chars().take(500).collect::<String>()
-    );
+// Indents message lines that would otherwise read as a BEGIN/END marker. One
+// leading space leaves the Markdown rendering unchanged.
+pub fn escape_marker_lines(text: &str) -> String {
+    if !text.contains("<!-- BEGIN ") && !text.contains("<!-- END ") {
+        return text.to_string();
+    }
+    text.split('\n')
+        .map(|line| {
+            if line.starts_with("<!-- BEGIN ") || line.starts_with("<!-- END ") {
+                format!(" {line}")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
-    let tmp_dir = std::env::temp_dir();
-    let tmp_file = tmp_dir.join(format!("title_{}.txt", std::process::id()));
+// Base64 data URIs at least this long are replaced when rendering notes.
+pub const DATA_URI_ELIDE_MIN_LEN: usize = 512;
 
-    let status = Command::new("codex")
-        .args(["exec", "-c", "notify=[]", "-o", tmp_file.to_str()?, &prompt])
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .status()
-        .ok()?;
+// Replaces long `data:<mime>;base64,<payload>` URIs with a short placeholder
+// so pasted images don't turn into multi-kilobyte lines.
+pub fn elide_data_uris(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find("data:") {
+        out.push_str(&rest[..i]);
+        let uri = &rest[i..];
+        match base64_data_uri_len(uri) {
+            Some((len, mime, payload)) if len >= DATA_URI_ELIDE_MIN_LEN => {
+                let kb = (payload * 3 / 4).div_ceil(1024);
+                out.push_str(&format!("[data:{mime};base64 ({kb}KB) elided]"));
+                rest = &uri[len..];
+            }
+            _ => {
+                out.push_str("data:");
+                rest = &uri["data:".len()..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
 
-    if !status.success() {
-        let _ = fs::remove_file(&tmp_file);
+// For text starting with `data:`, returns the URI's byte length, media type and
+// payload length when it is a base64 data URI.
+fn base64_data_uri_len(uri: &str) -> Option<(usize, &str, usize)> {
+    let header_end = uri.find(";base64,")?;
+    let mime = &uri["data:".len()..header_end];
+    if mime.len() > 100 || mime.contains(|c: char| c.is_whitespace() || c == ',') {
         return None;
     }
+    let payload_start = header_end + ";base64,".len();
+    let payload = uri[payload_start..]
+        .bytes()
+        .take_while(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'=' | b'-' | b'_'))
+        .count();
+    (payload > 0).then_some((payload_start + payload, mime, payload))
+}
 
-    let title = fs::read_to_string(&tmp_file).ok()?;
-    let _ = fs::remove_file(&tmp_file);
-    let title = sanitize_title(&title);
+// Referenced files larger than this are left as plain paths.
+pub const COPY_REFS_MAX_BYTES: u64 = 20 * 1024 * 1024;
+
+// Only files Obsidian can embed are copied.
+pub const COPY_REFS_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "webp", "svg", "bmp", "pdf", "mp3", "mp4", "webm",
+];
 
-    if title.is_empty() || title.len() > 50 {
+const PATH_REF_WRAPPERS: &[char] = &['"', '\'', '`', '(', ')', '[', ']', '<', '>', ',', ';'];
+const PATH_REF_TRAILING: &[char] = &['.', ':', '?', '!'];
+
+// Byte range of the path inside a whitespace-delimited word, if the word looks
+// like a local file path (`./design.png`, `~/shots/a.jpg`, `/tmp/x.pdf`,
+// `design.png`) with an embeddable extension. URLs are ignored.
+fn path_ref_span(word: &str) -> Option<(usize, usize)> {
+    let core = word.trim_start_matches(PATH_REF_WRAPPERS);
+    let start = word.len() - core.len();
+    let core =
+        core.trim_end_matches(|c| PATH_REF_WRAPPERS.contains(&c) || PATH_REF_TRAILING.contains(&c));
+    if core.is_empty() || core.contains("://") || core.starts_with("data:") {
         return None;
     }
-
-    Some(title)
+    let ext = Path::new(core).extension()?.to_str()?.to_ascii_lowercase();
+    let stem_ok = Path::new(core).file_stem().is_some_and(|s| !s.is_empty());
+    (stem_ok && COPY_REFS_EXTENSIONS.contains(&ext.as_str())).then_some((start, start + core.len()))
 }
 
-pub fn sanitize_title(s: &str) -> String {
-    let title: String = s
-        .trim()
-        .chars()
-        .take(30)
-        .map(|c| match c {
-            'a'..='z' | '0'..='9' | '-' => c,
-            'A'..='Z' => c.to_ascii_lowercase(),
-            ' ' | '_' => '-',
-            _ => '-',
-        })
-        .collect();
-
-    let mut result = String::new();
-    let mut prev_hyphen = false;
-    for c in title.chars() {
-        if c == '-' {
-            if !prev_hyphen && !result.is_empty() {
-                result.push('-');
+// Local file paths mentioned in prose; fenced code blocks are skipped.
+pub fn find_path_refs(text: &str) -> Vec<&str> {
+    let mut refs = Vec::new();
+    let mut in_code = false;
+    for line in text.lines() {
+        if is_fence_line(line.trim()) {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        for word in line.split_whitespace() {
+            if let Some((start, end)) = path_ref_span(word) {
+                refs.push(&word[start..end]);
             }
-            prev_hyphen = true;
-        } else {
-            result.push(c);
-            prev_hyphen = false;
         }
     }
+    refs
+}
 
-    result.trim_matches('-').to_string()
+// Replaces each path found by `find_path_refs` with whatever `embed` returns
+// for it; paths mapped to `None` are left as written.
+pub fn rewrite_path_refs(text: &str, mut embed: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_code = false;
+    for line in text.split_inclusive('\n') {
+        if is_fence_line(line.trim()) {
+            in_code = !in_code;
+            out.push_str(line);
+            continue;
+        }
+        if in_code {
+            out.push_str(line);
+            continue;
+        }
+        for piece in line.split_inclusive(char::is_whitespace) {
+            let word = piece.trim_end_matches(char::is_whitespace);
+            let replaced = path_ref_span(word)
+                .and_then(|(start, end)| Some((start, end, embed(&word[start..end])?)));
+            match replaced {
+                Some((start, end, embed)) => {
+                    out.push_str(&word[..start]);
+                    out.push_str(&embed);
+                    out.push_str(&piece[end..]);
+                }
+                None => out.push_str(piece),
+            }
+        }
+    }
+    out
 }
 
-pub fn fallback_title(text: &str) -> String {
-    sanitize_title(&text.chars().take(40).collect::<String>())
+// `~/` and absolute paths are taken as-is; anything else is relative to `cwd`.
+pub fn resolve_path_ref(raw: &str, cwd: &Path) -> PathBuf {
+    if raw.starts_with("~/") {
+        return expand_tilde(raw);
+    }
+    let path = Path::new(raw);
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        cwd.join(path)
+    }
 }
 
-pub fn with_lock_file<T, F>(lock_path: &Path, action: F) -> Result<T>
-where
-    F: FnOnce() -> Result<T>,
-{
-    const TIMEOUT: Duration = Duration::from_secs(10);
-    const RETRY_DELAY: Duration = Duration::from_millis(50);
-    const STALE_AFTER: Duration = Duration::from_secs(120);
+// Copies referenced files that still exist (and are at most
+// `COPY_REFS_MAX_BYTES`) into `attachments_dir` and rewrites their paths as
+// `![[...]]` embeds. The copy is named after a hash of the source path so
+// different files called `design.png` don't collide, and re-exports reuse it.
+pub fn copy_path_refs(text: &str, cwd: &Path, attachments_dir: &Path) -> String {
+    rewrite_path_refs(text, |raw| {
+        let src = resolve_path_ref(raw, cwd);
+        let meta = fs::metadata(&src).ok()?;
+        if !meta.is_file() || meta.len() > COPY_REFS_MAX_BYTES {
+            return None;
+        }
+        let file_name = src.file_name()?.to_str()?;
+        let hash = fnv1a_64(&src.to_string_lossy());
+        let name = format!(
+            "{:08x}-{}",
+            (hash & 0xffff_ffff) as u32,
+            safe_name(file_name)
+        );
+        let dest = attachments_dir.join(&name);
+        // `fs::copy` stamps the copy with the time it was made, so a source
+        // edited since then is newer even when its size is unchanged.
+        let up_to_date = fs::metadata(&dest).is_ok_and(|d| {
+            d.len() == meta.len()
+                && matches!((d.modified(), meta.modified()), (Ok(d), Ok(s)) if d >= s)
+        });
+        if !up_to_date {
+            fs::create_dir_all(attachments_dir).ok()?;
+            fs::copy(&src, &dest).ok()?;
+        }
+        Some(format!("![[{name}]]"))
+    })
+}
 
-    let started = Instant::now();
+// Content part types read as text when `LLM_LOGS_TEXT_TYPES` is unset.
+pub const DEFAULT_TEXT_TYPES: &[&str] = &["text"];
 
-    loop {
-        match OpenOptions::new()
-            .write(true)
-            .create_new(true)
-            .open(lock_path)
-        {
-            Ok(mut f) => {
-                let _ = writeln!(f, "pid={}", std::process::id());
-                let _ = f.flush();
-                drop(f);
+// Entry types read as a message role besides `user` and `assistant`;
+// `LLM_LOGS_ROLE_ALIASES` adds to them and takes precedence.
+pub const DEFAULT_ROLE_ALIASES: &[(&str, &str)] = &[
+    ("human", "user"),
+    ("ai", "assistant"),
+    ("model", "assistant"),
+];
 
-                let result = action();
+// `user` or `assistant` for an entry type naming either role, matched
+// case-insensitively; `None` for everything else (tool results, summaries).
+pub fn canonical_role(name: &str, aliases: &[(String, String)]) -> Option<&'static str> {
+    let as_role = |role: &str| match role {
+        "user" => Some("user"),
+        "assistant" => Some("assistant"),
+        _ => None,
+    };
+    let name = name.trim().to_ascii_lowercase();
+    as_role(&name)
+        .or_else(|| {
+            aliases
+                .iter()
+                .find(|(alias, _)| *alias == name)
+                .and_then(|(_, role)| as_role(role))
+        })
+        .or_else(|| {
+            DEFAULT_ROLE_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == name)
+                .and_then(|(_, role)| as_role(role))
+        })
+}
 
-                let _ = fs::remove_file(lock_path);
-                return result;
+// `alias=role` pairs separated by commas, e.g. `bot=assistant,me=user`.
+fn parse_role_aliases(value: &str) -> Result<Vec<(String, String)>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((alias, role @ ("user" | "assistant"))) if !alias.trim().is_empty() => {
+                Ok((alias.trim().to_ascii_lowercase(), role.to_string()))
             }
-            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
-                if is_stale_lock(lock_path, STALE_AFTER) {
-                    let _ = fs::remove_file(lock_path);
-                    continue;
-                }
+            _ => Err(anyhow!(
+                "invalid LLM_LOGS_ROLE_ALIASES entry: {pair:?} (expected alias=user|assistant)"
+            )),
+        })
+        .collect()
+}
 
-                if started.elapsed() > TIMEOUT {
-                    return Err(anyhow!(
-                        "timeout waiting for lock file: {}",
-                        lock_path.display()
-                    ));
-                }
+// Non-text block types the exporters know about and skip on purpose; any
+// other type is what `LLM_LOGS_DEBUG_UNKNOWN_BLOCKS` dumps.
+pub const KNOWN_BLOCK_TYPES: &[&str] = &[
+    "text",
+    "input_text",
+    "output_text",
+    "tool_use",
+    "tool_result",
+    "server_tool_use",
+    "web_search_tool_result",
+    "thinking",
+    "redacted_thinking",
+    "reasoning",
+    "image",
+    "input_image",
+    "document",
+    "function_call",
+    "function_call_output",
+];
 
-                thread::sleep(RETRY_DELAY);
+pub const UNKNOWN_BLOCK_DUMP_MAX_CHARS: usize = 2000;
+
+pub fn extract_text(v: &Value) -> Option<String> {
+    TextOptions::default().extract(v)
+}
+
+// How content parts are read into message text; see `Config::text_options`.
+#[derive(Debug, Clone, Default)]
+pub struct TextOptions {
+    // Part types read as text; empty means `DEFAULT_TEXT_TYPES`.
+    pub types: Vec<String>,
+    // Unrecognized blocks are kept as JSON dumps (`LLM_LOGS_DEBUG_UNKNOWN_BLOCKS`).
+    pub dump_unknown: bool,
+    // Blocks kept per message (`LLM_LOGS_MAX_BLOCKS_PER_MSG`); unlimited if unset.
+    pub max_blocks: Option<usize>,
+}
+
+impl TextOptions {
+    // Text parts are joined in order, so text before and after a `tool_use` in
+    // the same entry reads as one answer.
+    pub fn extract(&self, v: &Value) -> Option<String> {
+        extract_text_with(v, &self.types, self.dump_unknown, self.max_blocks)
+    }
+}
+
+// With `dump_unknown`, blocks of a type that is neither text nor in
+// `KNOWN_BLOCK_TYPES` (or with no type at all) are kept in place as a fenced
+// JSON dump, so new shapes in transcripts show up in the note. With
+// `max_blocks`, only that many rendered blocks are kept and the rest are
+// counted in a trailing `… [N more blocks elided]` line.
+pub fn extract_text_with(
+    v: &Value,
+    types: &[String],
+    dump_unknown: bool,
+    max_blocks: Option<usize>,
+) -> Option<String> {
+    let is_text_type = |t: &str| {
+        if types.is_empty() {
+            DEFAULT_TEXT_TYPES.contains(&t)
+        } else {
+            types.iter().any(|x| x == t)
+        }
+    };
+    match v {
+        Value::String(s) => Some(s.clone()),
+        Value::Array(arr) => {
+            let mut parts: Vec<String> = Vec::new();
+            for item in arr {
+                match item.get("type").and_then(|x| x.as_str()) {
+                    Some(t) if is_text_type(t) => {
+                        let text = item.get("text").or_else(|| item.get("content"));
+                        match text.and_then(nested_text) {
+                            Some(t) if !t.trim().is_empty() => parts.push(t.trim().to_string()),
+                            Some(_) => {}
+                            None => debug_log(|| format!("dropped {t} block without text: {item}")),
+                        }
+                    }
+                    Some(t) if KNOWN_BLOCK_TYPES.contains(&t) => {}
+                    Some(t) => {
+                        if dump_unknown {
+                            parts.push(unknown_block_dump(t, item));
+                        }
+                    }
+                    None => {
+                        debug_log(|| format!("dropped content block without type: {item}"));
+                        if dump_unknown {
+                            parts.push(unknown_block_dump("(none)", item));
+                        }
+                    }
+                }
             }
-            Err(e) => {
-                return Err(e)
-                    .with_context(|| format!("failed to create lock file: {}", lock_path.display()))
+            if let Some(max) = max_blocks.filter(|&max| parts.len() > max) {
+                let elided = parts.len() - max;
+                parts.truncate(max);
+                let noun = if elided == 1 { "block" } else { "blocks" };
+                parts.push(format!("… [{elided} more {noun} elided]"));
+            }
+            if parts.is_empty() {
+                None
+            } else {
+                Some(parts.join("\n"))
             }
         }
+        // Some transcripts store a lone content block without the array.
+        Value::Object(_) => extract_text_with(
+            &Value::Array(vec![v.clone()]),
+            types,
+            dump_unknown,
+            max_blocks,
+        ),
+        _ => None,
     }
 }
 
-fn is_stale_lock(lock_path: &Path, stale_after: Duration) -> bool {
-    let meta = match fs::metadata(lock_path) {
-        Ok(m) => m,
-        Err(_) => return false,
-    };
-
-    let modified = match meta.modified() {
-        Ok(m) => m,
-        Err(_) => return false,
+fn unknown_block_dump(block_type: &str, item: &Value) -> String {
+    let json = serde_json::to_string_pretty(item).unwrap_or_else(|_| item.to_string());
+    let json = if json.chars().count() > UNKNOWN_BLOCK_DUMP_MAX_CHARS {
+        let cut: String = json.chars().take(UNKNOWN_BLOCK_DUMP_MAX_CHARS).collect();
+        format!("{cut}\n… (truncated)")
+    } else {
+        json
     };
-
-    SystemTime::now()
-        .duration_since(modified)
-        .unwrap_or(Duration::ZERO)
-        > stale_after
+    format!("Unrecognized content block `{block_type}`:\n```json\n{json}\n```")
 }
 
-pub fn find_md_file_containing_id(root: &Path, id: &str) -> Option<PathBuf> {
-    if id.trim().is_empty() {
-        return None;
-    }
-    if !root.exists() {
-        return None;
+// The text of a part's `text` (or `content`) field, which some producers nest
+// one level deeper: `{"text": {"value": "..."}}`, `{"text": [{"text": "..."}]}`.
+fn nested_text(v: &Value) -> Option<String> {
+    match v {
+        Value::String(s) => Some(s.clone()),
+        Value::Object(obj) => ["value", "text", "content"]
+            .iter()
+            .find_map(|key| obj.get(*key))
+            .and_then(nested_text),
+        Value::Array(arr) => {
+            let parts: Vec<String> = arr.iter().filter_map(nested_text).collect();
+            (!parts.is_empty()).then(|| parts.join("\n"))
+        }
+        _ => None,
     }
+}
 
-    let mut stack = vec![root.to_path_buf()];
+// Diagnostics for input the exporters skip, printed to stderr only with
+// `--verbose` or `LLM_LOGS_DEBUG` so hooks stay quiet by default.
+pub fn debug_log(message: impl FnOnce() -> String) {
+    log::debug(message);
+}
 
-    while let Some(dir) = stack.pop() {
-        let entries = match fs::read_dir(&dir) {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
+pub fn yaml_quote(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
 
-        for entry in entries.flatten() {
-            let path = entry.path();
-            let file_type = match entry.file_type() {
-                Ok(t) => t,
-                Err(_) => continue,
-            };
+const YAML_INDICATORS: &[char] = &[
+    '-', '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%', '@', '`',
+];
 
-            if file_type.is_dir() {
-                stack.push(path);
-                continue;
-            }
+// True when `s` written as a plain (unquoted) YAML scalar would not read back
+// as the same string: booleans, nulls, numbers, dates, or text starting with an
+// indicator character or containing `: ` / ` #`.
+pub fn yaml_scalar_needs_quote(s: &str) -> bool {
+    if s.is_empty() || s.trim() != s {
+        return true;
+    }
+    let lower = s.to_ascii_lowercase();
+    if matches!(
+        lower.as_str(),
+        "true" | "false" | "yes" | "no" | "on" | "off" | "y" | "n" | "null" | "~"
+    ) {
+        return true;
+    }
+    if looks_like_yaml_number(&lower) || looks_like_yaml_date(s) {
+        return true;
+    }
+    if s.starts_with(YAML_INDICATORS) {
+        return true;
+    }
+    s.contains(": ") || s.contains(" #") || s.ends_with(':') || s.contains(['\n', '\r', '\t'])
+}
 
-            if !file_type.is_file() {
-                continue;
-            }
+fn looks_like_yaml_number(lower: &str) -> bool {
+    let digits = lower.trim_start_matches(['+', '-']).replace('_', "");
+    if let Some(hex) = digits.strip_prefix("0x") {
+        return !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    if let Some(rest) = digits
+        .strip_prefix("0o")
+        .or_else(|| digits.strip_prefix("0b"))
+    {
+        return !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit());
+    }
+    matches!(digits.as_str(), ".inf" | ".nan")
+        || (digits.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+            && digits.parse::<f64>().is_ok())
+}
 
-            let file_name = entry.file_name();
-            let name = match file_name.to_str() {
-                Some(s) => s,
-                None => continue,
-            };
+fn looks_like_yaml_date(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() >= 10
+        && b[..4].iter().all(u8::is_ascii_digit)
+        && b[4] == b'-'
+        && b[5..7].iter().all(u8::is_ascii_digit)
+        && b[7] == b'-'
+        && b[8..10].iter().all(u8::is_ascii_digit)
+}
 
-            if name.ends_with(".md") && name.contains(id) {
-                return Some(path);
+// A YAML list item or value: plain when that reads back as the same string,
+// double-quoted otherwise.
+pub fn yaml_scalar(s: &str) -> String {
+    if yaml_scalar_needs_quote(s) {
+        format!("\"{}\"", yaml_quote(s))
+    } else {
+        s.to_string()
+    }
+}
+
+pub fn yaml_unquote(s: &str) -> String {
+    let s = s.trim();
+    match s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+        Some(inner) => {
+            let mut out = String::with_capacity(inner.len());
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => out.extend(chars.next()),
+                    c => out.push(c),
+                }
             }
+            out
         }
+        None => s.to_string(),
     }
+}
 
-    None
+// Everything after the leading `---` frontmatter; the whole note if it has none.
+pub fn note_body(note: &str) -> &str {
+    let rest = match note.strip_prefix("---\n") {
+        Some(rest) => rest,
+        None => return note,
+    };
+    if let Some(after) = rest.strip_prefix("---") {
+        return after.strip_prefix('\n').unwrap_or(after);
+    }
+    match rest.find("\n---") {
+        Some(i) => {
+            let after = &rest[i + "\n---".len()..];
+            after.strip_prefix('\n').unwrap_or(after)
+        }
+        None => note,
+    }
+}
+
+// Reads `key:` from the leading `---` frontmatter, undoing `yaml_quote`.
+pub fn frontmatter_field(note: &str, key: &str) -> Option<String> {
+    let rest = note.strip_prefix("---\n")?;
+    let prefix = format!("{key}:");
+    rest.lines()
+        .take_while(|l| *l != "---")
+        .find_map(|l| l.strip_prefix(&prefix))
+        .map(yaml_unquote)
+}
+
+// Sets `key: value` inside the leading `---` frontmatter, replacing an existing
+// entry or appending a new one. `value` is written verbatim, so callers quote it.
+// Notes without frontmatter are returned unchanged.
+pub fn upsert_frontmatter_field(note: &str, key: &str, value: &str) -> String {
+    let body_start = match note.strip_prefix("---\n") {
+        Some(rest) => note.len() - rest.len(),
+        None => return note.to_string(),
+    };
+    let fm_end = match note[body_start..].find("\n---") {
+        Some(i) => body_start + i + 1,
+        None if note[body_start..].starts_with("---") => body_start,
+        None => return note.to_string(),
+    };
+
+    let prefix = format!("{key}:");
+    let line = format!("{key}: {value}");
+    let mut lines: Vec<&str> = note[body_start..fm_end].lines().collect();
+    match lines.iter().position(|l| l.starts_with(&prefix)) {
+        Some(i) => lines[i] = &line,
+        None => lines.push(&line),
+    }
+
+    let mut out = String::with_capacity(note.len() + line.len() + 1);
+    out.push_str(&note[..body_start]);
+    for l in lines {
+        out.push_str(l);
+        out.push('\n');
+    }
+    out.push_str(&note[fm_end..]);
+    out
+}
+
+// `note` without its `key:` frontmatter line; unchanged when there is none.
+pub fn remove_frontmatter_field(note: &str, key: &str) -> String {
+    let body_start = match note.strip_prefix("---\n") {
+        Some(rest) => note.len() - rest.len(),
+        None => return note.to_string(),
+    };
+    let fm_end = match note[body_start..].find("\n---") {
+        Some(i) => body_start + i + 1,
+        None => return note.to_string(),
+    };
+
+    let prefix = format!("{key}:");
+    let mut out = String::with_capacity(note.len());
+    out.push_str(&note[..body_start]);
+    for l in note[body_start..fm_end]
+        .lines()
+        .filter(|l| !l.starts_with(&prefix))
+    {
+        out.push_str(l);
+        out.push('\n');
+    }
+    out.push_str(&note[fm_end..]);
+    out
+}
+
+// Extra frontmatter for every note of a project, read from the project folder.
+pub const TEMPLATE_FILE: &str = "_template.yaml";
+
+// `key: value` lines of `<project_dir>/_template.yaml`, in file order. Blank
+// lines, `#` comments and anything that isn't a one-line top-level `key: value`
+// (lists, nested maps) are skipped; values are kept verbatim.
+pub fn read_frontmatter_template(project_dir: &Path) -> Vec<(String, String)> {
+    let text = match fs::read_to_string(project_dir.join(TEMPLATE_FILE)) {
+        Ok(text) => text,
+        Err(_) => return Vec::new(),
+    };
+    text.lines()
+        .filter(|l| !l.starts_with([' ', '\t', '-', '#']))
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim(), v.trim()))
+        .filter(|(k, v)| !k.is_empty() && !k.contains(char::is_whitespace) && !v.is_empty())
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+// Appends the template's keys after the note's own frontmatter. Keys the note
+// already has, standard or edited by hand, keep their value.
+pub fn merge_frontmatter_template(note: &str, template: &[(String, String)]) -> String {
+    let mut out = note.to_string();
+    for (key, value) in template {
+        if frontmatter_field(&out, key).is_none() {
+            out = upsert_frontmatter_field(&out, key, value);
+        }
+    }
+    out
+}
+
+// Records the model that produced the session; a note whose transcript names
+// no model is left as it is.
+pub fn upsert_model_field(note: &str, model: Option<&str>) -> String {
+    match model.map(str::trim).filter(|m| !m.is_empty()) {
+        Some(model) => {
+            upsert_frontmatter_field(note, "model", &format!("\"{}\"", yaml_quote(model)))
+        }
+        None => note.to_string(),
+    }
+}
+
+// `cwd` as a `file://` URI, with everything but unreserved characters and `/`
+// percent-encoded. A relative path has none.
+pub fn file_uri(cwd: &str) -> Option<String> {
+    let cwd = cwd.trim();
+    if !cwd.starts_with('/') {
+        return None;
+    }
+    let mut uri = String::from("file://");
+    for b in cwd.bytes() {
+        if b.is_ascii_alphanumeric() || b"/-._~".contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri.push_str(&format!("%{b:02X}"));
+        }
+    }
+    Some(uri)
+}
+
+// Adds `project_path:` beside the raw `cwd`: the working directory as a
+// `file://` URI, or a link to the project's overview note under `project_link`
+// (see `Config::vault_link`). A withheld `cwd` (`LLM_LOGS_OMIT_CWD`) gets no URI.
+pub fn upsert_project_path_field(
+    note: &str,
+    style: ProjectPathStyle,
+    cwd: Option<&str>,
+    project: &str,
+    project_link: &str,
+) -> String {
+    let value = match style {
+        ProjectPathStyle::Off => None,
+        ProjectPathStyle::File => cwd.and_then(file_uri),
+        ProjectPathStyle::Hub => {
+            let hub = OVERVIEW_FILE.trim_end_matches(".md");
+            Some(format!("[[{project_link}/{hub}|{project}]]"))
+        }
+    };
+    match value {
+        Some(value) => {
+            upsert_frontmatter_field(note, "project_path", &format!("\"{}\"", yaml_quote(&value)))
+        }
+        None => note.to_string(),
+    }
+}
+
+// Drops what `LLM_LOGS_OMIT_CWD` keeps out of notes: the `cwd:` line and a
+// `file://` `project_path:` left from an earlier export.
+pub fn remove_cwd_fields(note: &str) -> String {
+    let note = remove_frontmatter_field(note, "cwd");
+    match frontmatter_field(&note, "project_path") {
+        Some(path) if path.starts_with("file:") => remove_frontmatter_field(&note, "project_path"),
+        _ => note,
+    }
+}
+
+// Lists the note's title as an Obsidian alias so it can be found and linked by
+// name. This tool owns the field: a changed title replaces the old alias.
+pub fn upsert_aliases_field(note: &str, title: Option<&str>) -> String {
+    match title.map(str::trim).filter(|t| !t.is_empty()) {
+        Some(title) => {
+            upsert_frontmatter_field(note, "aliases", &format!("[\"{}\"]", yaml_quote(title)))
+        }
+        None => note.to_string(),
+    }
+}
+
+// Asks `llm` for a file-name title (see `Config::title_llm`), falling back to
+// `fallback_title`.
+pub fn generate_title(text: Option<&str>, llm: Option<&LlmCommand>) -> String {
+    let text = match text {
+        Some(t) if !t.trim().is_empty() => t,
+        _ => return "untitled".to_string(),
+    };
+
+    if let Some(title) = llm.and_then(|cmd| generate_title_with_llm(cmd, text)) {
+        return title;
+    }
+
+    fallback_title(text)
+}
+
+pub fn generate_title_with_llm(cmd: &LlmCommand, text: &str) -> Option<String> {
+    let prompt = format!(
+        "Generate a short filename-safe title (English, max 20 chars, lowercase, hyphens only, no spaces) for this conversation. Output ONLY the title, nothing else:\n\n{}",
+        text.chars().take(500).collect::<String>()
+    );
+
+    title_from_llm_output(&run_llm(cmd, &prompt)?)
+}
+
+// The title becomes part of a file name, so whatever the model answered must
+// come out as a single path component; anything else falls back.
+pub fn title_from_llm_output(raw: &str) -> Option<String> {
+    let title = sanitize_title(&clean_llm_title(raw));
+    is_safe_title(&title).then_some(title)
+}
+
+// `sanitize_title` already leaves only `[a-z0-9-]`; this re-checks the result
+// so a future change to the sanitizer can't let a path through.
+pub fn is_safe_title(title: &str) -> bool {
+    !title.is_empty()
+        && title.chars().count() <= 50
+        && !title.contains(['/', '\\', '\0'])
+        && !title.contains("..")
+}
+
+pub const DEFAULT_LLM_PROGRAM: &str = "codex";
+pub const DEFAULT_LLM_TIMEOUT: Duration = Duration::from_secs(120);
+
+// The program is invoked like `codex exec -c notify=[] -o <out> <prompt>` and
+// must write its answer to `<out>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LlmCommand {
+    pub program: String,
+    pub timeout: Duration,
+}
+
+impl Default for LlmCommand {
+    fn default() -> Self {
+        Self {
+            program: DEFAULT_LLM_PROGRAM.to_string(),
+            timeout: DEFAULT_LLM_TIMEOUT,
+        }
+    }
+}
+
+// Returns the command's answer, or `None` if it fails, times out or can't run.
+pub fn run_llm(cmd: &LlmCommand, prompt: &str) -> Option<String> {
+    static SEQ: AtomicUsize = AtomicUsize::new(0);
+    let tmp_file = std::env::temp_dir().join(format!(
+        "llm_{}_{}.txt",
+        std::process::id(),
+        SEQ.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut child = Command::new(&cmd.program)
+        .args(["exec", "-c", "notify=[]", "-o", tmp_file.to_str()?, prompt])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    let output = wait_with_timeout(&mut child, cmd.timeout)
+        .filter(|s| s.success())
+        .and_then(|_| fs::read_to_string(&tmp_file).ok());
+    let _ = fs::remove_file(&tmp_file);
+    output
+}
+
+pub fn generate_summary_with_llm(cmd: &LlmCommand, transcript: &str) -> Option<String> {
+    let prompt = format!(
+        "Summarize the following conversation between a user and an AI assistant in one short paragraph. Output ONLY the summary, nothing else:\n\n{}",
+        transcript.chars().take(8000).collect::<String>()
+    );
+
+    let summary = run_llm(cmd, &prompt)?;
+    let summary = summary.trim();
+    (!summary.is_empty()).then(|| summary.to_string())
+}
+
+pub const SUMMARY_BEGIN: &str = "<!-- BEGIN SUMMARY -->";
+pub const SUMMARY_END: &str = "<!-- END SUMMARY -->";
+
+// Replaces the summary block, or inserts it just before `anchor` (the
+// transcript's BEGIN marker) so it sits between frontmatter and transcript.
+pub fn upsert_summary_block(note: &str, summary: &str, anchor: &str) -> String {
+    let block = format!(
+        "{SUMMARY_BEGIN}\n## Summary\n\n{}\n{SUMMARY_END}",
+        summary.trim()
+    );
+
+    let bounds = (
+        find_marker(note, SUMMARY_BEGIN),
+        find_marker(note, SUMMARY_END),
+    );
+    if let (Some(b), Some(e)) = bounds {
+        if e >= b {
+            return format!("{}{block}{}", &note[..b], &note[e + SUMMARY_END.len()..]);
+        }
+    }
+
+    match find_marker(note, anchor) {
+        Some(i) => format!("{}{block}\n\n{}", &note[..i], &note[i..]),
+        None => format!("{}\n\n{block}\n", note.trim_end()),
+    }
+}
+
+pub const FOOTER_BEGIN: &str = "<!-- BEGIN AUTO FOOTER -->";
+pub const FOOTER_END: &str = "<!-- END AUTO FOOTER -->";
+
+// `project_link` is the project folder as a vault-relative wikilink path.
+pub fn build_footer_block(project: &str, project_link: &str) -> String {
+    format!(
+        "{FOOTER_BEGIN}\n---\n[[{project_link}/_overview|Overview]] · \
+         [[{project_link}/{project}|Back to {project}]]\n{FOOTER_END}"
+    )
+}
+
+pub fn upsert_footer_block(note: &str, block: &str) -> String {
+    upsert_marked_block(note, FOOTER_BEGIN, FOOTER_END, block)
+}
+
+// The tool activity block `claude_tool_to_obsidian` keeps in a session note;
+// exports carry it over when they rewrite the note.
+pub const TOOL_EVENTS_BEGIN: &str = "<!-- BEGIN AUTO TOOL EVENTS -->";
+pub const TOOL_EVENTS_END: &str = "<!-- END AUTO TOOL EVENTS -->";
+
+// The session note of the exploded layout (`LLM_LOGS_LAYOUT=exploded`), next to
+// its message files in a folder named after the session.
+pub const EXPLODED_INDEX_FILE: &str = "index.md";
+
+// Replaces the `begin`..`end` block (markers included in `block`) in place, or
+// appends it at the end of the note.
+pub fn upsert_marked_block(note: &str, begin: &str, end: &str, block: &str) -> String {
+    if let (Some(b), Some(e)) = (find_marker(note, begin), find_marker(note, end)) {
+        if e >= b {
+            return format!("{}{block}{}", &note[..b], &note[e + end.len()..]);
+        }
+    }
+    format!("{}\n\n{block}\n", note.trim_end())
+}
+
+// The `begin`..`end` block of `note`, markers included.
+pub fn marked_block<'a>(note: &'a str, begin: &str, end: &str) -> Option<&'a str> {
+    match (find_marker(note, begin), find_marker(note, end)) {
+        (Some(b), Some(e)) if e >= b => Some(&note[b..e + end.len()]),
+        _ => None,
+    }
+}
+
+// Drops the `begin`..`end` block, markers included, and the blank lines it
+// leaves behind. A note without the block is returned unchanged.
+pub fn remove_marked_block(note: &str, begin: &str, end: &str) -> String {
+    match (find_marker(note, begin), find_marker(note, end)) {
+        (Some(b), Some(e)) if e >= b => {
+            let pre = note[..b].trim_end_matches('\n');
+            let post = note[e + end.len()..].trim_start_matches('\n');
+            match (pre.is_empty(), post.is_empty()) {
+                (true, _) => post.to_string(),
+                (false, true) => format!("{pre}\n"),
+                (false, false) => format!("{pre}\n\n{post}"),
+            }
+        }
+        _ => note.to_string(),
+    }
+}
+
+// Extracts the title from a chatty LLM response: skips code fence lines,
+// takes the first non-empty line and strips surrounding quotes/backticks.
+pub fn clean_llm_title(raw: &str) -> String {
+    const WRAPPERS: &[char] = &['"', '\'', '`', '“', '”', '‘', '’'];
+
+    raw.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !is_fence_line(line))
+        .map(|line| line.trim_matches(WRAPPERS).trim())
+        .find(|line| !line.is_empty())
+        .unwrap_or("")
+        .to_string()
+}
+
+// A line opening or closing a fenced block (```` ``` ```` or ```` ```text ````),
+// as opposed to an inline-fenced title such as ```` ```fix-login``` ````.
+fn is_fence_line(line: &str) -> bool {
+    line.strip_prefix("```")
+        .is_some_and(|rest| !rest.trim_start_matches('`').contains('`'))
+}
+
+pub fn sanitize_title(s: &str) -> String {
+    let title: String = s
+        .trim()
+        .chars()
+        .take(30)
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '-' => c,
+            'A'..='Z' => c.to_ascii_lowercase(),
+            ' ' | '_' => '-',
+            _ => '-',
+        })
+        .collect();
+
+    let mut result = String::new();
+    let mut prev_hyphen = false;
+    for c in title.chars() {
+        if c == '-' {
+            if !prev_hyphen && !result.is_empty() {
+                result.push('-');
+            }
+            prev_hyphen = true;
+        } else {
+            result.push(c);
+            prev_hyphen = false;
+        }
+    }
+
+    result.trim_matches('-').to_string()
+}
+
+pub fn fallback_title(text: &str) -> String {
+    sanitize_title(&text.chars().take(40).collect::<String>())
+}
+
+// Notes may have been touched by other tools; invalid UTF-8 is replaced rather
+// than failing the whole hook.
+pub fn read_to_string_lossy(path: &Path) -> io::Result<String> {
+    let bytes = fs::read(path)?;
+    match String::from_utf8(bytes) {
+        Ok(s) => Ok(s),
+        Err(e) => {
+            log::warn(|| {
+                format!(
+                    "{} contains invalid UTF-8; reading it lossily",
+                    path.display()
+                )
+            });
+            Ok(String::from_utf8_lossy(e.as_bytes()).into_owned())
+        }
+    }
+}
+
+// Appends `line` to a JSONL file unless it is byte-identical to the current
+// last line. Only the tail is read, so large logs stay cheap to append to.
+// Returns whether the line was written.
+//
+// Concurrent appenders take a lock next to the log, so the tail check and the
+// write can't interleave with another process's partial line.
+pub fn append_jsonl_line_dedup(path: &Path, line: &str) -> Result<bool> {
+    let line = line.trim_end_matches('\n');
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create dir: {}", parent.display()))?;
+    }
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("log");
+    let lock_path = path.with_file_name(format!(".lock_{name}"));
+    with_lock_file(&lock_path, || append_line_if_new(path, line))
+}
+
+fn append_line_if_new(path: &Path, line: &str) -> Result<bool> {
+    let mut f = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .with_context(|| format!("failed to open raw log: {}", path.display()))?;
+
+    let len = f.metadata().context("failed to stat raw log")?.len();
+    let needed = line.len() as u64 + 1;
+    if len >= needed {
+        // Read the previous newline too (when present) so a longer last line
+        // that merely ends with `line` is not mistaken for a duplicate.
+        let tail_len = needed.saturating_add(1).min(len);
+        let mut tail = vec![0u8; tail_len as usize];
+        f.seek(SeekFrom::Start(len - tail_len))
+            .context("failed to seek raw log")?;
+        f.read_exact(&mut tail)
+            .context("failed to read raw log tail")?;
+
+        let starts_line = tail_len == needed || tail[0] == b'\n';
+        let last = &tail[(tail_len - needed) as usize..];
+        if starts_line && last.ends_with(b"\n") && &last[..last.len() - 1] == line.as_bytes() {
+            return Ok(false);
+        }
+    }
+
+    let needs_newline = len > 0 && {
+        let mut last = [0u8; 1];
+        f.seek(SeekFrom::Start(len - 1))
+            .context("failed to seek raw log")?;
+        f.read_exact(&mut last)
+            .context("failed to read raw log tail")?;
+        last[0] != b'\n'
+    };
+
+    let mut out = String::with_capacity(line.len() + 2);
+    if needs_newline {
+        out.push('\n');
+    }
+    out.push_str(line);
+    out.push('\n');
+    f.write_all(out.as_bytes())
+        .with_context(|| format!("failed to append raw log: {}", path.display()))?;
+    Ok(true)
+}
+
+// Removes the lock when dropped, so a panicking action doesn't leave it behind
+// for every later run to wait out.
+struct LockGuard<'a>(&'a Path);
+
+impl Drop for LockGuard<'_> {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(self.0);
+    }
+}
+
+pub fn with_lock_file<T, F>(lock_path: &Path, action: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T>,
+{
+    const TIMEOUT: Duration = Duration::from_secs(10);
+    const RETRY_DELAY: Duration = Duration::from_millis(50);
+    const STALE_AFTER: Duration = Duration::from_secs(120);
+
+    let started = Instant::now();
+
+    loop {
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(lock_path)
+        {
+            Ok(mut f) => {
+                let _ = writeln!(f, "pid={}", std::process::id());
+                let _ = f.flush();
+                drop(f);
+
+                let _guard = LockGuard(lock_path);
+                return action();
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                if is_stale_lock(lock_path, STALE_AFTER) {
+                    let _ = fs::remove_file(lock_path);
+                    continue;
+                }
+
+                if started.elapsed() > TIMEOUT {
+                    return Err(anyhow!(
+                        "timeout waiting for lock file: {}",
+                        lock_path.display()
+                    ));
+                }
+
+                thread::sleep(RETRY_DELAY);
+            }
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to create lock file: {}", lock_path.display()))
+            }
+        }
+    }
+}
+
+fn is_stale_lock(lock_path: &Path, stale_after: Duration) -> bool {
+    let meta = match fs::metadata(lock_path) {
+        Ok(m) => m,
+        Err(_) => return false,
+    };
+
+    let modified = match meta.modified() {
+        Ok(m) => m,
+        Err(_) => return false,
+    };
+
+    SystemTime::now()
+        .duration_since(modified)
+        .unwrap_or(Duration::ZERO)
+        > stale_after
+}
+
+// Where the `n`th batch of turns rolled out of `md_path` goes (1-based).
+pub fn turn_archive_path(md_path: &Path, n: usize) -> PathBuf {
+    let stem = md_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("note");
+    md_path.with_file_name(format!("{stem}_archive_{n}.md"))
+}
+
+// Turn archives carry the thread id in their name too, but are never the
+// thread's note.
+pub fn is_turn_archive_name(name: &str) -> bool {
+    let stem = name.strip_suffix(".md").unwrap_or(name);
+    stem.rsplit_once("_archive_")
+        .is_some_and(|(_, n)| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+pub fn find_md_file_containing_id(root: &Path, id: &str) -> Option<PathBuf> {
+    if id.trim().is_empty() {
+        return None;
+    }
+    if !root.exists() {
+        return None;
+    }
+    let compact = compact_id(id);
+
+    let mut stack = vec![root.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(e) => e,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let file_type = match entry.file_type() {
+                Ok(t) => t,
+                Err(_) => continue,
+            };
+
+            // Archived notes are done with; a session resumed after `--archive`
+            // starts a new note.
+            if file_type.is_dir() {
+                if entry.file_name() != ARCHIVE_DIR {
+                    stack.push(path);
+                }
+                continue;
+            }
+
+            if !file_type.is_file() {
+                continue;
+            }
+
+            let file_name = entry.file_name();
+            let name = match file_name.to_str() {
+                Some(s) => s,
+                None => continue,
+            };
+
+            let matches = name.contains(id) || name.contains(&compact);
+            if name.ends_with(".md") && matches && !is_turn_archive_name(name) {
+                return Some(path);
+            }
+        }
+    }
+
+    None
+}
+
+// A note's identity that survives renames and layout changes: derived from
+// the tool and session id only, and stored in frontmatter as `uid:`.
+pub fn note_identity(tool: &str, session_id: &str) -> String {
+    format!(
+        "{:016x}",
+        fnv1a_64(&format!("{}\n{}", tool.trim(), session_id.trim()))
+    )
+}
+
+// Finds a session's note under `root`: by the id in its filename, else by the
+// `uid:` a renamed note keeps in its frontmatter.
+pub fn find_session_note(root: &Path, id: &str, uid: &str) -> Option<PathBuf> {
+    find_md_file_containing_id(root, id).or_else(|| find_md_file_by_uid(root, uid))
+}
+
+// Every note of the session under `threads_dir`, the one `--archive` moved
+// into `ARCHIVE_DIR` included; for removing them.
+pub fn find_session_notes(threads_dir: &Path, id: &str, uid: &str) -> Vec<PathBuf> {
+    [threads_dir.to_path_buf(), threads_dir.join(ARCHIVE_DIR)]
+        .iter()
+        .filter_map(|root| find_session_note(root, id, uid))
+        .collect()
+}
+
+// The leading `---` frontmatter of the note at `path`, delimiters included.
+// Reading stops at its closing line, so the transcript below is never loaded.
+pub fn read_frontmatter(path: &Path) -> Option<String> {
+    let mut reader = io::BufReader::new(fs::File::open(path).ok()?);
+    let mut frontmatter = String::new();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line).ok()? == 0 {
+            return None;
+        }
+        let text = String::from_utf8_lossy(&line);
+        if frontmatter.is_empty() && text != "---\n" {
+            return None;
+        }
+        let closing = !frontmatter.is_empty() && text.trim_end() == "---";
+        frontmatter.push_str(&text);
+        if closing {
+            return Some(frontmatter);
+        }
+    }
+}
+
+// The note under `root` whose frontmatter `uid:` is `uid`. Every note's
+// frontmatter is read, so callers try `find_md_file_containing_id` first.
+pub fn find_md_file_by_uid(root: &Path, uid: &str) -> Option<PathBuf> {
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                if entry.file_name() != ARCHIVE_DIR {
+                    stack.push(path);
+                }
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.ends_with(".md") || is_turn_archive_name(&name) {
+                continue;
+            }
+            let frontmatter = match read_frontmatter(&path) {
+                Some(frontmatter) => frontmatter,
+                None => continue,
+            };
+            if frontmatter_field(&frontmatter, "uid").as_deref() == Some(uid) {
+                return Some(path);
+            }
+        }
+    }
+    None
+}
+
+pub const DEFAULT_DATE_FMT: &str = "%Y-%m-%d";
+pub const DEFAULT_TIME_FMT: &str = "%Y-%m-%d %H:%M:%S %z";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DateFormats {
+    // Only an explicit `LLM_LOGS_DATE_FMT` prefixes new note filenames, so
+    // existing layouts are unchanged by default.
+    pub date: Option<String>,
+    pub time: String,
+}
+
+impl Default for DateFormats {
+    fn default() -> Self {
+        Self {
+            date: None,
+            time: DEFAULT_TIME_FMT.to_string(),
+        }
+    }
+}
+
+impl DateFormats {
+    pub fn filename_date(&self) -> &str {
+        self.date.as_deref().unwrap_or(DEFAULT_DATE_FMT)
+    }
+}
+
+// chrono panics when formatting with an invalid specifier, so formats are
+// checked up front.
+pub fn validate_time_fmt(fmt: &str) -> Result<()> {
+    if StrftimeItems::new(fmt).any(|item| item == Item::Error) {
+        return Err(anyhow!("unsupported strftime format: {fmt:?}"));
+    }
+    Ok(())
+}
+
+// Filenames and digest names are formatted from a date alone, so the format
+// must name the day and must not ask for a time of day.
+pub fn validate_filename_date_fmt(fmt: &str) -> Result<()> {
+    use std::fmt::Write as _;
+
+    validate_time_fmt(fmt)?;
+    if fmt.contains(['/', '\\']) {
+        return Err(anyhow!(
+            "date format must not contain path separators: {fmt:?}"
+        ));
+    }
+    let render = |y, m, d| {
+        let mut out = String::new();
+        let date = NaiveDate::from_ymd_opt(y, m, d).expect("valid date");
+        write!(out, "{}", date.format(fmt)).ok().map(|_| out)
+    };
+    match (render(2001, 2, 3), render(2004, 11, 27)) {
+        (Some(a), Some(b)) if a != b => Ok(()),
+        (Some(_), Some(_)) => Err(anyhow!("date format has no date component: {fmt:?}")),
+        _ => Err(anyhow!("date format must not contain time fields: {fmt:?}")),
+    }
+}
+
+pub fn note_filename(formats: &DateFormats, date: NaiveDate, title: &str, id: &str) -> String {
+    match &formats.date {
+        Some(fmt) => format!("{}_{title}_{id}.md", date.format(fmt)),
+        None => format!("{title}_{id}.md"),
+    }
+}
+
+// The title part of a note built by `note_filename` (possibly shortened by
+// `note_filename_within`). `untitled` notes and names in another shape have none.
+pub fn note_title(md_path: &Path, id: &str, formats: &DateFormats) -> Option<String> {
+    let stem = md_path.file_stem()?.to_str()?;
+    let rest = stem
+        .strip_suffix(&format!("_{id}"))
+        .or_else(|| stem.strip_suffix(&format!("_{}", compact_id(id))))?;
+    let title = match &formats.date {
+        Some(fmt) => {
+            let dated = rest
+                .match_indices('_')
+                .find(|&(i, _)| heading_date(&rest[..i], fmt).is_some());
+            match dated {
+                Some((i, _)) => &rest[i + 1..],
+                None => rest.split_once('_')?.1,
+            }
+        }
+        None => rest,
+    };
+    let title = title.trim();
+    if title.is_empty() || title == "untitled" {
+        return None;
+    }
+    Some(title.to_string())
+}
+
+// Longest file name most filesystems accept, in bytes.
+pub const MAX_FILE_NAME_LEN: usize = 255;
+
+// Longest full path, in bytes, that the platform accepts.
+pub const MAX_PATH_LEN: usize = if cfg!(windows) {
+    259
+} else if cfg!(target_os = "macos") {
+    1023
+} else {
+    4095
+};
+
+// Titles are cut before the id is; below this many bytes the id is compacted too.
+const MIN_TITLE_LEN: usize = 8;
+
+// Bytes left for a file name in `dir` when the full path may be `max_path` long.
+pub fn filename_budget(dir: &Path, max_path: usize) -> usize {
+    MAX_FILE_NAME_LEN.min(max_path.saturating_sub(dir.as_os_str().len() + 1))
+}
+
+// Stand-in for an id too long to fit a file name: a readable prefix plus a hash
+// of the whole id. `find_md_file_containing_id` also matches this form.
+pub fn compact_id(id: &str) -> String {
+    let prefix: String = id.chars().take(8).collect();
+    format!("{prefix}-{:016x}", fnv1a_64(id))
+}
+
+// Folder under `Threads/` for a note started on `date`: `YYYY/mm/DD`, or a
+// single `YYYY-MM` folder when `monthly` (`LLM_LOGS_MONTHLY_FOLDERS`).
+pub fn dated_thread_dir(md_dir: &Path, date: NaiveDate, monthly: bool) -> PathBuf {
+    if monthly {
+        return md_dir.join(date.format("%Y-%m").to_string());
+    }
+    md_dir
+        .join(date.format("%Y").to_string())
+        .join(date.format("%m").to_string())
+        .join(date.format("%d").to_string())
+}
+
+// `note_filename`, shortened to at most `budget` bytes: the title is cut
+// first, and if that is not enough the id is replaced by `compact_id`.
+pub fn note_filename_within(
+    formats: &DateFormats,
+    date: NaiveDate,
+    title: &str,
+    id: &str,
+    budget: usize,
+) -> String {
+    let full = note_filename(formats, date, title, id);
+    if full.len() <= budget {
+        return full;
+    }
+
+    let mut id = id.to_string();
+    if note_filename(formats, date, "", &id).len() + MIN_TITLE_LEN > budget {
+        id = compact_id(&id);
+    }
+    let fixed = note_filename(formats, date, "", &id).len();
+    let mut short = String::new();
+    for c in title.chars() {
+        if fixed + short.len() + c.len_utf8() > budget {
+            break;
+        }
+        short.push(c);
+    }
+    note_filename(formats, date, short.trim_end(), &id)
+}
+
+// Recovers the date from a heading timestamp written with `fmt`, trying the
+// most specific chrono parser first.
+pub fn heading_date(ts: &str, fmt: &str) -> Option<NaiveDate> {
+    let ts = ts.trim();
+    DateTime::parse_from_str(ts, fmt)
+        .map(|dt| dt.date_naive())
+        .or_else(|_| NaiveDateTime::parse_from_str(ts, fmt).map(|dt| dt.date()))
+        .or_else(|_| NaiveDate::parse_from_str(ts, fmt))
+        .ok()
+}
+
+// Normalizes a note to exactly one `begin`...`end` pair. Duplicate pairs and
+// content stranded between stray markers are merged into one block, a missing
+// `end` closes the block at EOF, and a lone `end` gets an empty block. Text
+// between a closed pair and the next `begin` was written by the user, so it
+// is kept and moved after the block. Notes with no markers at all are left
+// alone. Returns whether anything changed.
+pub fn repair_markers_with(md: &str, begin: &str, end: &str) -> (String, bool) {
+    let mut markers: Vec<(usize, bool)> = marker_indices(md, begin)
+        .into_iter()
+        .map(|i| (i, true))
+        .chain(marker_indices(md, end).into_iter().map(|i| (i, false)))
+        .collect();
+    markers.sort_unstable();
+
+    match markers.as_slice() {
+        [] => return (md.to_string(), false),
+        [(_, true), (_, false)] => return (md.to_string(), false),
+        _ => {}
+    }
+
+    let (first, _) = markers[0];
+    let (last, last_is_begin) = markers[markers.len() - 1];
+    let region_end = if last_is_begin {
+        md.len()
+    } else {
+        last + end.len()
+    };
+
+    let mut pieces = Vec::new();
+    let mut user = Vec::new();
+    let mut cursor = first;
+    let mut open = false;
+    // Whether the last marker was an `end` that closed an open block.
+    let mut closed = false;
+    for &(pos, is_begin) in &markers {
+        let piece = md[cursor..pos].trim();
+        if closed && is_begin {
+            user.push(piece);
+        } else {
+            pieces.push(piece);
+        }
+        closed = !is_begin && open;
+        open = is_begin;
+        cursor = pos + if is_begin { begin.len() } else { end.len() };
+    }
+    pieces.push(md[cursor..region_end].trim());
+    let join = |pieces: Vec<&str>| {
+        pieces
+            .into_iter()
+            .filter(|p| !p.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    };
+    let body = join(pieces);
+    let user = join(user);
+
+    let mut out = md[..first].to_string();
+    out.push_str(begin);
+    out.push('\n');
+    if !body.is_empty() {
+        out.push_str(&body);
+        out.push('\n');
+    }
+    out.push_str(end);
+    out.push('\n');
+    let rest = md[region_end..].trim_start_matches('\n');
+    if !user.is_empty() {
+        out.push('\n');
+        out.push_str(&user);
+        out.push('\n');
+        if !rest.is_empty() {
+            out.push('\n');
+        }
+    }
+    out.push_str(rest);
+
+    let changed = out != md;
+    (out, changed)
+}
+
+// Result notes live outside `Threads` so `find_md_file_containing_id` never
+// mistakes one for the full note. An answer longer than `max_chars` is cut
+// there and ends with a link to the full note, which keeps the whole answer.
+pub fn write_result_note(
+    config: &Config,
+    base_dir: &Path,
+    note_path: &Path,
+    frontmatter: &[(&str, &str)],
+    answer: &str,
+    max_chars: Option<usize>,
+) -> Result<PathBuf> {
+    let stem = note_path
+        .file_stem()
+        .and_then(|n| n.to_str())
+        .context("note path has no file name")?;
+    let path = result_note_path(base_dir, note_path).context("note path has no file name")?;
+    fs::create_dir_all(base_dir.join("Results")).context("failed to create Results dir")?;
+
+    let mut out = String::from("---\n");
+    for (key, value) in frontmatter {
+        out.push_str(&format!("{key}: \"{}\"\n", yaml_quote(value)));
+    }
+    out.push_str(&format!("source_note: \"[[{}]]\"\n", yaml_quote(stem)));
+    out.push_str("tags:\n  - ai-log\n  - ai-result\n---\n\n");
+    let answer = answer.trim();
+    match max_chars {
+        Some(max) if answer.chars().count() > max => {
+            out.push_str(&truncate_markdown(answer, max));
+            out.push_str(&format!("\n\n… [[{stem}|see full note]]\n"));
+        }
+        _ => {
+            out.push_str(answer);
+            out.push('\n');
+        }
+    }
+
+    config
+        .write_output(&path, &normalize_markdown(&out))
+        .context("failed to write result note")?;
+    Ok(path)
+}
+
+// `Results/<note stem>_result.md` under the project folder.
+pub fn result_note_path(base_dir: &Path, note_path: &Path) -> Option<PathBuf> {
+    let stem = note_path.file_stem()?.to_str()?;
+    Some(base_dir.join("Results").join(format!("{stem}_result.md")))
+}
+
+// The first `max` characters of `text`, with a code fence left open by the cut
+// closed again so the rest of the note doesn't render as code.
+pub fn truncate_markdown(text: &str, max: usize) -> String {
+    let cut: String = text.chars().take(max).collect();
+    let mut fence = None;
+    for line in cut.lines() {
+        match fence {
+            Some(open) if closes_fence(line, open) => fence = None,
+            Some(_) => {}
+            None => fence = fence_delimiter(line),
+        }
+    }
+    let mut out = cut.trim_end().to_string();
+    if let Some(open) = fence {
+        out.push('\n');
+        out.push_str(open);
+    }
+    out
+}
+
+fn parse_flag(value: &str) -> bool {
+    matches!(value.trim(), "1" | "true" | "yes")
+}
+
+// How a session is stored under `Threads/`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    // One note per session with an AUTO block that is rewritten in place.
+    #[default]
+    Note,
+    // A folder per session holding one file per message plus `index.md`.
+    Exploded,
+}
+
+impl Layout {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "note" => Ok(Self::Note),
+            "exploded" => Ok(Self::Exploded),
+            other => Err(anyhow!(
+                "invalid LLM_LOGS_LAYOUT: {other:?} (expected note or exploded)"
+            )),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Note => "note",
+            Self::Exploded => "exploded",
+        }
+    }
+}
+
+// The Markdown dialect Claude Code notes are written in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Flavor {
+    // YAML frontmatter and AUTO blocks that are rewritten in place.
+    #[default]
+    Obsidian,
+    // A Logseq page: `key:: value` properties, then one block per message.
+    Logseq,
+}
+
+impl Flavor {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "obsidian" => Ok(Self::Obsidian),
+            "logseq" => Ok(Self::Logseq),
+            other => Err(anyhow!(
+                "invalid LLM_LOGS_FLAVOR: {other:?} (expected obsidian or logseq)"
+            )),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Obsidian => "obsidian",
+            Self::Logseq => "logseq",
+        }
+    }
+}
+
+// What the `project_path:` frontmatter field points at.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProjectPathStyle {
+    // No `project_path:` field.
+    #[default]
+    Off,
+    // A `file://` URI of the session's working directory.
+    File,
+    // A link to the project's `_overview` note.
+    Hub,
+}
+
+impl ProjectPathStyle {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "file" => Ok(Self::File),
+            "hub" => Ok(Self::Hub),
+            other => Err(anyhow!(
+                "invalid LLM_LOGS_PROJECT_PATH: {other:?} (expected off, file or hub)"
+            )),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::File => "file",
+            Self::Hub => "hub",
+        }
+    }
+}
+
+// Which user message a new note's title is generated from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TitleSource {
+    #[default]
+    First,
+    // Resumed sessions often open with a stale resume prompt; the latest
+    // message tends to name what the session ended up being about.
+    Last,
+    Longest,
+}
+
+impl TitleSource {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "first" => Ok(Self::First),
+            "last" => Ok(Self::Last),
+            "longest" => Ok(Self::Longest),
+            other => Err(anyhow!(
+                "invalid LLM_LOGS_TITLE_SOURCE: {other:?} (expected first, last or longest)"
+            )),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::First => "first",
+            Self::Last => "last",
+            Self::Longest => "longest",
+        }
+    }
+
+    // Blank messages are skipped; among equally long ones the earliest wins.
+    pub fn pick<'a>(self, user_msgs: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+        let mut msgs = user_msgs.into_iter().filter(|m| !m.trim().is_empty());
+        match self {
+            Self::First => msgs.next(),
+            Self::Last => msgs.last(),
+            Self::Longest => msgs.fold(None, |best: Option<&str>, m| match best {
+                Some(b) if b.trim().chars().count() >= m.trim().chars().count() => Some(b),
+                _ => Some(m),
+            }),
+        }
+    }
+}
+
+// What happens to entries Claude Code sub-agents write (`isSidechain: true`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SidechainMode {
+    // Rendered inline with the main conversation.
+    Include,
+    // Rendered in their own `## Sub-agent` section after the transcript.
+    #[default]
+    Section,
+    Skip,
+}
+
+impl SidechainMode {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "include" => Ok(Self::Include),
+            "section" => Ok(Self::Section),
+            "skip" => Ok(Self::Skip),
+            other => Err(anyhow!(
+                "invalid LLM_LOGS_SIDECHAIN: {other:?} (expected include, section or skip)"
+            )),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Include => "include",
+            Self::Section => "section",
+            Self::Skip => "skip",
+        }
+    }
+}
+
+pub const DEFAULT_CLAUDE_DIR: &str = "Claude Code";
+pub const DEFAULT_CODEX_DIR: &str = "Codex";
+pub const DEFAULT_WINDSURF_DIR: &str = "Windsurf";
+
+// A tool folder override is a single folder name: separators are replaced like
+// in project names, and `.`/`..` would escape the AI root.
+fn tool_dir_override(var: &str, value: &str) -> Result<String> {
+    let name = safe_name(value);
+    if name.is_empty() || matches!(value.trim(), "." | "..") {
+        return Err(anyhow!("invalid {var}: {value:?} (expected a folder name)"));
+    }
+    Ok(name)
+}
+
+// The delimiter (a run of three or more `` ` `` or `~`) a line opening or
+// closing a fenced code block starts with, also inside a `> ` quote or
+// callout.
+pub fn fence_delimiter(line: &str) -> Option<&str> {
+    let mut line = line.trim_start();
+    while let Some(rest) = line.strip_prefix('>') {
+        line = rest.trim_start();
+    }
+    let c = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = line.len() - line.trim_start_matches(c).len();
+    (len >= 3).then(|| &line[..len])
+}
+
+// Whether `line` closes a fence opened with `open`: a run of the same
+// character at least as long, with nothing after it. A shorter run, like
+// ```` ``` ```` inside a ```` ```` ```` block, is part of the code.
+pub fn closes_fence(line: &str, open: &str) -> bool {
+    match fence_delimiter(line) {
+        Some(d) => {
+            d.as_bytes()[0] == open.as_bytes()[0]
+                && d.len() >= open.len()
+                && line.trim_end().ends_with(d)
+        }
+        None => false,
+    }
+}
+
+// `text` as a Markdown quote, for the body of a callout: every line gets a
+// `> ` prefix, and a blank line becomes a bare `>` so the quote isn't broken.
+pub fn quote_lines(text: &str) -> String {
+    let mut out = String::new();
+    for line in text.lines() {
+        if line.is_empty() {
+            out.push_str(">\n");
+        } else {
+            out.push_str(&format!("> {line}\n"));
+        }
+    }
+    out
+}
+
+// Final pass before a note is written, so repeated upserts don't pile up
+// whitespace: trailing whitespace is trimmed from each line, runs of three or
+// more blank lines become one, and the text ends with exactly one newline.
+// Lines inside fenced code blocks are left exactly as they are.
+pub fn normalize_markdown(text: &str) -> String {
+    let mut out: Vec<&str> = Vec::new();
+    let mut fence = None;
+    let mut blank_run = 0;
+    for line in text.lines() {
+        if let Some(open) = fence {
+            if closes_fence(line, open) {
+                fence = None;
+            }
+            out.push(line);
+            continue;
+        }
+        fence = fence_delimiter(line);
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_run += 1;
+        } else {
+            if blank_run >= 3 {
+                out.truncate(out.len() - (blank_run - 1));
+            }
+            blank_run = 0;
+        }
+        out.push(line);
+    }
+    while out.last().is_some_and(|l| l.trim().is_empty()) {
+        out.pop();
+    }
+    if out.is_empty() {
+        return String::new();
+    }
+    let mut normalized = out.join("\n");
+    normalized.push('\n');
+    normalized
+}
+
+pub const DEFAULT_TURN_SPACING: usize = 1;
+
+pub const DEFAULT_INLINE_RAW_MAX_BYTES: u64 = 32 * 1024;
+
+// The separator that leaves `blank_lines` empty lines after a line of text.
+pub fn section_gap(blank_lines: usize) -> String {
+    "\n".repeat(blank_lines + 1)
+}
+
+pub fn parse_rfc3339_local(s: &str) -> Option<DateTime<Local>> {
+    DateTime::parse_from_rfc3339(s)
+        .ok()
+        .map(|dt| dt.with_timezone(&Local))
+}
+
+// RFC 3339, or a timestamp without an offset read in `zone` (see
+// `parse_naive_in`).
+pub fn parse_timestamp_in(s: &str, zone: AssumedZone) -> Option<DateTime<Local>> {
+    parse_rfc3339_local(s).or_else(|| parse_naive_in(s, zone))
+}
+
+// The zone offset-less timestamps are read in (`LLM_LOGS_ASSUME_TZ`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AssumedZone {
+    #[default]
+    Local,
+    Fixed(FixedOffset),
+}
+
+// `local` (or empty), `UTC`/`Z`, or a fixed offset such as `+09:00`.
+pub fn parse_assumed_zone(raw: &str) -> Option<AssumedZone> {
+    let raw = raw.trim();
+    if raw.is_empty() || raw.eq_ignore_ascii_case("local") {
+        return Some(AssumedZone::Local);
+    }
+    if raw.eq_ignore_ascii_case("utc") || raw.eq_ignore_ascii_case("z") {
+        return FixedOffset::east_opt(0).map(AssumedZone::Fixed);
+    }
+    raw.parse::<FixedOffset>().ok().map(AssumedZone::Fixed)
+}
+
+// `2024-01-01T10:00:00` (optionally with fractional seconds, or a space instead
+// of `T`) read as a wall-clock time in `zone`. A local time skipped by a DST
+// change has no reading and yields `None`; an ambiguous one takes the earlier.
+pub fn parse_naive_in(s: &str, zone: AssumedZone) -> Option<DateTime<Local>> {
+    let s = s.trim();
+    let naive = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|fmt| NaiveDateTime::parse_from_str(s, fmt).ok())?;
+    match zone {
+        AssumedZone::Local => Local.from_local_datetime(&naive).earliest(),
+        AssumedZone::Fixed(offset) => offset
+            .from_local_datetime(&naive)
+            .single()
+            .map(|dt| dt.with_timezone(&Local)),
+    }
+}
+
+// Tries, in order: top-level `timestamp`, `message.timestamp` (RFC 3339, or
+// without an offset in `zone`), then a numeric `timestamp`/`created_at` epoch
+// on either object.
+pub fn extract_timestamp(obj: &Value, zone: AssumedZone) -> Option<DateTime<Local>> {
+    let message = obj.get("message");
+
+    let rfc3339 = [Some(obj), message]
+        .into_iter()
+        .flatten()
+        .filter_map(|o| o.get("timestamp").and_then(|v| v.as_str()))
+        .find_map(|s| parse_timestamp_in(s, zone));
+    if rfc3339.is_some() {
+        return rfc3339;
+    }
+
+    [Some(obj), message]
+        .into_iter()
+        .flatten()
+        .flat_map(|o| ["timestamp", "created_at"].map(|k| o.get(k)))
+        .flatten()
+        .find_map(parse_epoch_local)
+}
+
+// Values past 1e11 can't be seconds in any realistic range, so they are
+// treated as milliseconds.
+fn parse_epoch_local(v: &Value) -> Option<DateTime<Local>> {
+    let n = v.as_f64()?;
+    if !n.is_finite() || n < 0.0 {
+        return None;
+    }
+    let millis = if n >= 1e11 { n } else { n * 1000.0 };
+    DateTime::from_timestamp_millis(millis as i64).map(|dt| dt.with_timezone(&Local))
+}
+
+// A note's `created`: the earliest known message or turn time, else now.
+pub fn resolve_created(
+    times: impl IntoIterator<Item = Option<DateTime<Local>>>,
+) -> DateTime<Local> {
+    times.into_iter().flatten().min().unwrap_or_else(Local::now)
+}
+
+fn mirror_path(vault: &Path, mirror_dir: &Path, path: &Path) -> Result<()> {
+    let rel = path
+        .strip_prefix(vault)
+        .context("path is outside the vault")?;
+    let target = mirror_dir.join(rel);
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            mirror_path(vault, mirror_dir, &entry?.path())?;
+        }
+        return Ok(());
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(path, &target)?;
+    Ok(())
+}
+
+// Every setting the binaries read from the environment, resolved once.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    pub vault: Option<PathBuf>,
+    pub ai_root: Option<PathBuf>,
+    pub formats: DateFormats,
+    // Heading text for messages without a timestamp; the time is left out if unset.
+    pub missing_time: Option<String>,
+    pub format: String,
+    pub layout: Layout,
+    pub flavor: Flavor,
+    pub project_path: ProjectPathStyle,
+    // Leave `cwd` out of notes (frontmatter, `file://` project paths, transcript
+    // paths); it is still used to resolve the project.
+    pub omit_cwd: bool,
+    pub title_source: TitleSource,
+    // New notes are named by `fallback_title` without running the LLM.
+    pub plain_titles: bool,
+    pub fold_turns: bool,
+    pub skip_meta: bool,
+    pub coalesce_assistant: bool,
+    pub daily_digest: bool,
+    pub result_note: bool,
+    // Result notes cut longer answers; the main note always has them in full.
+    pub result_max_chars: Option<usize>,
+    pub codex_debounce: Option<Duration>,
+    pub summary: bool,
+    pub llm: LlmCommand,
+    // New notes need at least this many messages; existing notes always update.
+    pub min_messages: usize,
+    pub archive_days: Option<u64>,
+    // Codex notes keep at most this many turns; older ones roll into archives.
+    pub max_turns: Option<usize>,
+    // Replacement for path-hostile characters in project folder names; `_` if unset.
+    pub slug_sep: Option<char>,
+    pub copy_refs: bool,
+    // Content part types read as text; empty means `DEFAULT_TEXT_TYPES`.
+    pub text_types: Vec<String>,
+    // Extra `alias -> role` names on top of `DEFAULT_ROLE_ALIASES`.
+    pub role_aliases: Vec<(String, String)>,
+    pub footer_nav: bool,
+    pub code_callouts: bool,
+    // Runs of identical assistant messages render once with a `(×N)` count.
+    pub collapse_dupes: bool,
+    // Assistant messages end with a `<sub>` line of their token usage.
+    pub show_usage: bool,
+    pub monthly_folders: bool,
+    // review_session writes proposals into the session note instead of `skill_proposals/`.
+    pub proposals_inline: bool,
+    // Tool folder names under the AI root; `DEFAULT_CLAUDE_DIR` and friends if unset.
+    pub claude_dir: Option<String>,
+    pub codex_dir: Option<String>,
+    pub windsurf_dir: Option<String>,
+    // Blank lines between rendered messages and turns, 1 or 2; see `blank_lines`.
+    pub turn_spacing: Option<usize>,
+    pub hide_slash: bool,
+    pub sidechain: SidechainMode,
+    // Transcripts keep only the lines of the session being exported.
+    pub split_sessions: bool,
+    // Mirror every exported message into `index::INDEX_FILE` under the AI root.
+    pub sqlite: bool,
+    // Ignored sessions also lose the note and raw log written before they were listed.
+    pub ignore_purge: bool,
+    // Claude notes get fresh frontmatter and transcript on the next export.
+    pub force_rebuild: bool,
+    // Mask secrets in rendered output; `redact_raw` also masks the Codex raw log.
+    pub redact_secrets: bool,
+    pub redact_raw: bool,
+    // Backup copy of everything written, laid out like the vault.
+    pub mirror_dir: Option<PathBuf>,
+    // Small Claude transcripts are embedded in the note; see `inline_raw_limit`.
+    pub inline_raw: bool,
+    pub inline_raw_max_bytes: Option<u64>,
+    // A `<session>.csv` of per-message lengths is rewritten next to the note.
+    pub emit_csv: bool,
+    // Debug messages are logged without `-v`; see `log::set_debug`.
+    pub debug: bool,
+    pub debug_unknown_blocks: bool,
+    pub max_blocks_per_msg: Option<usize>,
+    pub assume_tz: AssumedZone,
+}
+
+impl Config {
+    // Also applies `LLM_LOGS_DEBUG` to the process's log.
+    pub fn from_env() -> Result<Self> {
+        let config = Self::from_lookup(|name| std::env::var(name).ok())?;
+        log::set_debug(config.debug);
+        Ok(config)
+    }
+
+    // Unset and blank variables are treated alike.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let var = |name: &str| lookup(name).filter(|v| !v.trim().is_empty());
+        let flag = |name: &str| var(name).is_some_and(|v| parse_flag(&v));
+
+        let ai_root = var("OBSIDIAN_AI_ROOT")
+            .map(|raw| normalize_ai_root(&raw))
+            .transpose()?;
+
+        let date = var("LLM_LOGS_DATE_FMT")
+            .map(|fmt| {
+                validate_filename_date_fmt(&fmt).context("invalid LLM_LOGS_DATE_FMT")?;
+                Ok::<_, anyhow::Error>(fmt)
+            })
+            .transpose()?;
+        let time = match var("LLM_LOGS_TIME_FMT") {
+            Some(fmt) => {
+                validate_time_fmt(&fmt).context("invalid LLM_LOGS_TIME_FMT")?;
+                fmt
+            }
+            None => DEFAULT_TIME_FMT.to_string(),
+        };
+
+        let codex_debounce = match var("LLM_LOGS_CODEX_DEBOUNCE_SECS") {
+            Some(v) => {
+                let secs: u64 = v
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("invalid LLM_LOGS_CODEX_DEBOUNCE_SECS: {v:?}"))?;
+                (secs > 0).then(|| Duration::from_secs(secs))
+            }
+            None => None,
+        };
+
+        let min_messages = match var("LLM_LOGS_MIN_MESSAGES") {
+            Some(v) => v
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid LLM_LOGS_MIN_MESSAGES: {v:?}"))?,
+            None => 0,
+        };
+
+        let archive_days = var("LLM_LOGS_ARCHIVE_DAYS")
+            .map(|v| {
+                v.trim()
+                    .parse::<u64>()
+                    .with_context(|| format!("invalid LLM_LOGS_ARCHIVE_DAYS: {v:?}"))
+            })
+            .transpose()?;
+
+        let turn_spacing = var("LLM_LOGS_TURN_SPACING")
+            .map(|v| match v.trim().parse::<usize>() {
+                Ok(n @ 1..=2) => Ok(n),
+                _ => Err(anyhow!(
+                    "invalid LLM_LOGS_TURN_SPACING: {v:?} (expected 1 or 2)"
+                )),
+            })
+            .transpose()?;
+
+        let inline_raw_max_bytes = var("LLM_LOGS_INLINE_RAW_MAX_BYTES")
+            .map(|v| {
+                v.trim()
+                    .parse::<u64>()
+                    .with_context(|| format!("invalid LLM_LOGS_INLINE_RAW_MAX_BYTES: {v:?}"))
+            })
+            .transpose()?;
+
+        let result_max_chars = var("LLM_LOGS_RESULT_MAX_CHARS")
+            .map(|v| {
+                v.trim()
+                    .parse::<usize>()
+                    .with_context(|| format!("invalid LLM_LOGS_RESULT_MAX_CHARS: {v:?}"))
+            })
+            .transpose()?
+            .filter(|n| *n > 0);
+
+        let max_turns = var("LLM_LOGS_MAX_TURNS")
+            .map(|v| {
+                v.trim()
+                    .parse::<usize>()
+                    .with_context(|| format!("invalid LLM_LOGS_MAX_TURNS: {v:?}"))
+            })
+            .transpose()?
+            .filter(|n| *n > 0);
+
+        let max_blocks_per_msg = var("LLM_LOGS_MAX_BLOCKS_PER_MSG")
+            .map(|v| {
+                v.trim()
+                    .parse::<usize>()
+                    .with_context(|| format!("invalid LLM_LOGS_MAX_BLOCKS_PER_MSG: {v:?}"))
+            })
+            .transpose()?
+            .filter(|n| *n > 0);
+
+        let slug_sep = var("LLM_LOGS_SLUG_SEP")
+            .map(|v| {
+                let mut chars = v.trim().chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if !SLUG_FORBIDDEN.contains(&c) => Ok(c),
+                    _ => Err(anyhow!(
+                        "invalid LLM_LOGS_SLUG_SEP: {v:?} (expected one character)"
+                    )),
+                }
+            })
+            .transpose()?;
+
+        let llm = LlmCommand {
+            program: var("LLM_LOGS_LLM_CMD")
+                .map(|v| v.trim().to_string())
+                .unwrap_or_else(|| DEFAULT_LLM_PROGRAM.to_string()),
+            timeout: match var("LLM_LOGS_LLM_TIMEOUT_SECS") {
+                Some(v) => Duration::from_secs(
+                    v.trim()
+                        .parse()
+                        .with_context(|| format!("invalid LLM_LOGS_LLM_TIMEOUT_SECS: {v:?}"))?,
+                ),
+                None => DEFAULT_LLM_TIMEOUT,
+            },
+        };
+
+        Ok(Self {
+            vault: var("OBSIDIAN_VAULT").map(|v| expand_tilde(&v)),
+            ai_root,
+            formats: DateFormats { date, time },
+            missing_time: var("LLM_LOGS_MISSING_TIME").map(|v| v.trim().to_string()),
+            format: var("LLM_LOGS_FORMAT").unwrap_or_default(),
+            layout: var("LLM_LOGS_LAYOUT")
+                .map(|v| Layout::parse(&v))
+                .transpose()?
+                .unwrap_or_default(),
+            flavor: var("LLM_LOGS_FLAVOR")
+                .map(|v| Flavor::parse(&v))
+                .transpose()?
+                .unwrap_or_default(),
+            project_path: var("LLM_LOGS_PROJECT_PATH")
+                .map(|v| ProjectPathStyle::parse(&v))
+                .transpose()?
+                .unwrap_or_default(),
+            omit_cwd: flag("LLM_LOGS_OMIT_CWD"),
+            title_source: var("LLM_LOGS_TITLE_SOURCE")
+                .map(|v| TitleSource::parse(&v))
+                .transpose()?
+                .unwrap_or_default(),
+            plain_titles: flag("LLM_LOGS_PLAIN_TITLES"),
+            fold_turns: flag("LLM_LOGS_FOLD_TURNS"),
+            skip_meta: flag("LLM_LOGS_SKIP_META"),
+            coalesce_assistant: flag("LLM_LOGS_COALESCE_ASSISTANT"),
+            daily_digest: flag("LLM_LOGS_DAILY_DIGEST"),
+            result_note: flag("LLM_LOGS_RESULT_NOTE"),
+            result_max_chars,
+            codex_debounce,
+            summary: flag("LLM_LOGS_SUMMARY"),
+            llm,
+            min_messages,
+            archive_days,
+            max_turns,
+            slug_sep,
+            copy_refs: flag("LLM_LOGS_COPY_REFS"),
+            text_types: var("LLM_LOGS_TEXT_TYPES")
+                .map(|v| {
+                    v.split(',')
+                        .map(str::trim)
+                        .filter(|t| !t.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            role_aliases: var("LLM_LOGS_ROLE_ALIASES")
+                .map(|v| parse_role_aliases(&v))
+                .transpose()?
+                .unwrap_or_default(),
+            footer_nav: flag("LLM_LOGS_FOOTER_NAV"),
+            code_callouts: flag("LLM_LOGS_CODE_CALLOUTS"),
+            collapse_dupes: flag("LLM_LOGS_COLLAPSE_DUPES"),
+            show_usage: flag("LLM_LOGS_SHOW_USAGE"),
+            monthly_folders: flag("LLM_LOGS_MONTHLY_FOLDERS"),
+            proposals_inline: flag("LLM_LOGS_PROPOSALS_INLINE"),
+            claude_dir: var("LLM_LOGS_CLAUDE_DIR")
+                .map(|v| tool_dir_override("LLM_LOGS_CLAUDE_DIR", &v))
+                .transpose()?,
+            codex_dir: var("LLM_LOGS_CODEX_DIR")
+                .map(|v| tool_dir_override("LLM_LOGS_CODEX_DIR", &v))
+                .transpose()?,
+            windsurf_dir: var("LLM_LOGS_WINDSURF_DIR")
+                .map(|v| tool_dir_override("LLM_LOGS_WINDSURF_DIR", &v))
+                .transpose()?,
+            turn_spacing,
+            hide_slash: flag("LLM_LOGS_HIDE_SLASH"),
+            sidechain: var("LLM_LOGS_SIDECHAIN")
+                .map(|v| SidechainMode::parse(&v))
+                .transpose()?
+                .unwrap_or_default(),
+            split_sessions: flag("LLM_LOGS_SPLIT_SESSIONS"),
+            sqlite: flag("LLM_LOGS_SQLITE"),
+            ignore_purge: flag("LLM_LOGS_IGNORE_PURGE"),
+            force_rebuild: flag("LLM_LOGS_FORCE_REBUILD"),
+            redact_secrets: flag("LLM_LOGS_REDACT_SECRETS"),
+            redact_raw: flag("LLM_LOGS_REDACT_RAW"),
+            mirror_dir: var("LLM_LOGS_MIRROR_DIR").map(|v| expand_tilde(&v)),
+            inline_raw: flag("LLM_LOGS_INLINE_RAW"),
+            inline_raw_max_bytes,
+            emit_csv: flag("LLM_LOGS_EMIT_CSV"),
+            debug: flag("LLM_LOGS_DEBUG"),
+            debug_unknown_blocks: flag("LLM_LOGS_DEBUG_UNKNOWN_BLOCKS"),
+            max_blocks_per_msg,
+            assume_tz: var("LLM_LOGS_ASSUME_TZ")
+                .map(|v| {
+                    parse_assumed_zone(&v).with_context(|| {
+                        format!("invalid LLM_LOGS_ASSUME_TZ: {v:?} (expected local, UTC or +09:00)")
+                    })
+                })
+                .transpose()?
+                .unwrap_or_default(),
+        })
+    }
+
+    pub fn text_options(&self) -> TextOptions {
+        TextOptions {
+            types: self.text_types.clone(),
+            dump_unknown: self.debug_unknown_blocks,
+            max_blocks: self.max_blocks_per_msg,
+        }
+    }
+
+    // The command new note titles come from; `None` with `LLM_LOGS_PLAIN_TITLES`.
+    pub fn title_llm(&self) -> Option<&LlmCommand> {
+        (!self.plain_titles).then_some(&self.llm)
+    }
+
+    // Blank lines between messages and turns; `DEFAULT_TURN_SPACING` if unset.
+    pub fn blank_lines(&self) -> usize {
+        self.turn_spacing.unwrap_or(DEFAULT_TURN_SPACING)
+    }
+
+    // Largest transcript, in bytes, embedded by `LLM_LOGS_INLINE_RAW`;
+    // `DEFAULT_INLINE_RAW_MAX_BYTES` if unset.
+    pub fn inline_raw_limit(&self) -> u64 {
+        self.inline_raw_max_bytes
+            .unwrap_or(DEFAULT_INLINE_RAW_MAX_BYTES)
+    }
+
+    // `$OBSIDIAN_VAULT/$OBSIDIAN_AI_ROOT`, the folder every note is written under.
+    pub fn ai_dir(&self) -> Result<PathBuf> {
+        let vault = self
+            .vault
+            .as_ref()
+            .context("Missing OBSIDIAN_VAULT env var")?;
+        let ai_root = self
+            .ai_root
+            .as_ref()
+            .context("Missing OBSIDIAN_AI_ROOT env var")?;
+        Ok(vault.join(ai_root))
+    }
+
+    // Copies `path`, a file or folder in the vault, to the same place under
+    // `LLM_LOGS_MIRROR_DIR`. Best effort: a failure is logged as a warning and
+    // never fails the export.
+    // Files written outside the vault (a `--slice-out` folder) have no place
+    // in the mirror and are skipped.
+    pub fn mirror(&self, path: &Path) {
+        let (vault, mirror_dir) = match (&self.vault, &self.mirror_dir) {
+            (Some(vault), Some(mirror_dir)) if path.starts_with(vault) => (vault, mirror_dir),
+            _ => return,
+        };
+        if let Err(e) = mirror_path(vault, mirror_dir, path) {
+            log::warn(|| format!("failed to mirror {}: {e:#}", path.display()));
+        }
+    }
+
+    // Drops the mirror copy of a vault file or folder that was removed.
+    pub fn unmirror(&self, path: &Path) {
+        let (vault, mirror_dir) = match (&self.vault, &self.mirror_dir) {
+            (Some(vault), Some(mirror_dir)) => (vault, mirror_dir),
+            _ => return,
+        };
+        let target = match path.strip_prefix(vault) {
+            Ok(rel) => mirror_dir.join(rel),
+            Err(_) => return,
+        };
+        let removed = if target.is_dir() {
+            fs::remove_dir_all(&target)
+        } else if target.exists() {
+            fs::remove_file(&target)
+        } else {
+            return;
+        };
+        if let Err(e) = removed {
+            log::warn(|| format!("failed to remove mirror of {}: {e}", path.display()));
+        }
+    }
+
+    // Writes an exported file (atomically, see `write_atomic`) and mirrors it.
+    // Every output goes through here so the mirror never lags behind the vault.
+    pub fn write_output(&self, path: &Path, contents: &str) -> Result<()> {
+        write_atomic(path, contents)?;
+        self.mirror(path);
+        Ok(())
+    }
+
+    // Moves an exported file and its mirror copy.
+    pub fn move_output(&self, from: &Path, to: &Path) -> Result<()> {
+        fs::rename(from, to).with_context(|| format!("failed to move {}", from.display()))?;
+        self.unmirror(from);
+        self.mirror(to);
+        Ok(())
+    }
+
+    // Removes an exported file or folder along with its mirror copy.
+    pub fn remove_output(&self, path: &Path) -> Result<()> {
+        if path.is_dir() {
+            fs::remove_dir_all(path)
+        } else if path.exists() {
+            fs::remove_file(path)
+        } else {
+            Ok(())
+        }
+        .with_context(|| format!("failed to remove {}", path.display()))?;
+        self.unmirror(path);
+        Ok(())
+    }
+
+    // Resolves a `--merge-into` target. Relative paths start at the vault root;
+    // the result must be a Markdown file inside the vault.
+    pub fn vault_note_path(&self, raw: &str) -> Result<PathBuf> {
+        let vault = self
+            .vault
+            .as_ref()
+            .context("Missing OBSIDIAN_VAULT env var")?;
+        let path = expand_tilde(raw.trim());
+        let path = if path.is_absolute() {
+            path
+        } else {
+            vault.join(path)
+        };
+        if path
+            .components()
+            .any(|c| matches!(c, std::path::Component::ParentDir))
+        {
+            return Err(anyhow!("merge target must not contain `..`: {raw:?}"));
+        }
+        if !path.starts_with(vault) || path == *vault {
+            return Err(anyhow!("merge target is outside the vault: {raw:?}"));
+        }
+        if path.extension().and_then(|e| e.to_str()) != Some("md") {
+            return Err(anyhow!("merge target must be a .md note: {raw:?}"));
+        }
+        Ok(path)
+    }
+
+    // The transcript path a note cites as its source. `omit_cwd` keeps only the
+    // file name, since Claude Code's project folders spell out the cwd.
+    pub fn source_label(&self, path: &Path) -> String {
+        match path.file_name().filter(|_| self.omit_cwd) {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => path.display().to_string(),
+        }
+    }
+
+    // `path` relative to the vault with `/` separators, for wikilinks. Paths
+    // outside the vault (or with no vault set) fall back to their last component.
+    pub fn vault_link(&self, path: &Path) -> String {
+        let rel = self
+            .vault
+            .as_ref()
+            .and_then(|v| path.strip_prefix(v).ok())
+            .filter(|rel| !rel.as_os_str().is_empty())
+            .unwrap_or_else(|| path.file_name().map_or(path, Path::new));
+        rel.components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    // Rebuilds every project's `OVERVIEW_FILE` and the AI root's
+    // `MANIFEST_FILE` from the frontmatter of the notes on disk.
+    pub fn reindex(&self) -> Result<ReindexReport> {
+        let ai_dir = self.ai_dir()?;
+        let mut report = ReindexReport::default();
+        let mut manifest = Vec::new();
+        let mut project_dirs: Vec<PathBuf> = fs::read_dir(&ai_dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|tool| tool.path())
+            .filter(|p| p.is_dir())
+            .flat_map(|tool| fs::read_dir(tool).into_iter().flatten().flatten())
+            .map(|project| project.path())
+            .filter(|p| p.join("Threads").is_dir())
+            .collect();
+        project_dirs.sort();
+
+        for project_dir in project_dirs {
+            let notes = collect_project_notes(&ai_dir, &project_dir);
+            self.write_overview(&project_dir, &notes)?;
+            report.projects += 1;
+            report.notes += notes.len();
+            manifest.extend(notes.into_iter().map(|(_, entry)| entry));
+        }
+
+        let json = serde_json::to_string_pretty(&manifest).context("failed to encode manifest")?;
+        self.write_output(&ai_dir.join(MANIFEST_FILE), &json)
+            .context("failed to write manifest")?;
+        Ok(report)
+    }
+
+    fn write_overview(&self, project_dir: &Path, notes: &[(PathBuf, ManifestEntry)]) -> Result<()> {
+        let links: Vec<(String, &ManifestEntry)> = notes
+            .iter()
+            .map(|(path, entry)| (self.vault_link(&path.with_extension("")), entry))
+            .collect();
+        let name = project_dir
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+        let overview = normalize_markdown(&build_overview(name, &links));
+        self.write_output(&project_dir.join(OVERVIEW_FILE), &overview)
+            .context("failed to write project overview")
+    }
+
+    // Lists a just-exported note in its project's overview and in the
+    // manifest when it isn't there yet, by rebuilding the project's share of
+    // both. The overview is kept once something links to it (the footer or
+    // `project_path=hub`) or it exists; the manifest only once `--reindex`
+    // created it. Like `mirror`, a failure is only a warning.
+    pub fn refresh_overview(&self, project_dir: &Path, note: &Path) {
+        if let Err(e) = self.try_refresh_overview(project_dir, note) {
+            log::warn(|| format!("failed to update overview: {e:#}"));
+        }
+    }
+
+    fn try_refresh_overview(&self, project_dir: &Path, note: &Path) -> Result<()> {
+        // Without an AI root there is no overview or manifest to keep current.
+        let ai_dir = match self.ai_dir() {
+            Ok(dir) => dir,
+            Err(_) => return Ok(()),
+        };
+        if !note.starts_with(project_dir.join("Threads")) {
+            return Ok(());
+        }
+        let overview_path = project_dir.join(OVERVIEW_FILE);
+        let linked = self.footer_nav || self.project_path == ProjectPathStyle::Hub;
+        let link = format!("[[{}|", self.vault_link(&note.with_extension("")));
+        let overview_stale = (linked || overview_path.exists())
+            && !fs::read_to_string(&overview_path)
+                .unwrap_or_default()
+                .contains(&link);
+        let manifest_path = ai_dir.join(MANIFEST_FILE);
+        let rel = slash_path(note.strip_prefix(&ai_dir).unwrap_or(note));
+        let manifest: Option<Vec<ManifestEntry>> = fs::read_to_string(&manifest_path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok());
+        let manifest_stale = manifest
+            .as_ref()
+            .is_some_and(|entries| !entries.iter().any(|e| e.path == rel));
+        if !overview_stale && !manifest_stale {
+            return Ok(());
+        }
+
+        let notes = collect_project_notes(&ai_dir, project_dir);
+        if overview_stale {
+            self.write_overview(project_dir, &notes)?;
+        }
+        if let Some(mut entries) = manifest.filter(|_| manifest_stale) {
+            let project = format!("{}/", slash_path(project_dir.strip_prefix(&ai_dir)?));
+            entries.retain(|e| !e.path.starts_with(&project));
+            entries.extend(notes.into_iter().map(|(_, entry)| entry));
+            // Stable, so each project's notes stay oldest first.
+            entries.sort_by_key(|e| e.path.splitn(3, '/').take(2).collect::<Vec<_>>().join("/"));
+            let json =
+                serde_json::to_string_pretty(&entries).context("failed to encode manifest")?;
+            self.write_output(&manifest_path, &json)
+                .context("failed to write manifest")?;
+        }
+        Ok(())
+    }
+
+    // Whether `.llmlogsignore` in the vault root or in `cwd` lists `id`.
+    pub fn is_ignored(&self, id: &str, cwd: &str) -> bool {
+        let cwd = Path::new(cwd);
+        let dirs: Vec<&Path> = self.vault.as_deref().into_iter().chain([cwd]).collect();
+        is_ignored_id(&read_ignore_patterns(&dirs), id)
+    }
+
+    // The secret masker when `LLM_LOGS_REDACT_SECRETS` is on, with any extra
+    // patterns from `redact::REDACT_FILE` in the vault root.
+    pub fn redactor(&self) -> Result<Option<redact::SecretRedactor>> {
+        if !self.redact_secrets {
+            return Ok(None);
+        }
+        let dirs: Vec<&Path> = self.vault.as_deref().into_iter().collect();
+        let extra = read_pattern_files(&dirs, redact::REDACT_FILE);
+        redact::SecretRedactor::new(&extra).map(Some)
+    }
+
+    // Project folder name for a hook's `cwd`.
+    pub fn project_name(&self, cwd: &str) -> String {
+        safe_name_with(&git_project_name(cwd), self.slug_sep.unwrap_or('_'))
+    }
+
+    // Folder under the AI root holding Claude Code notes.
+    pub fn claude_dir_name(&self) -> &str {
+        self.claude_dir.as_deref().unwrap_or(DEFAULT_CLAUDE_DIR)
+    }
+
+    // Folder under the AI root holding Codex notes.
+    pub fn codex_dir_name(&self) -> &str {
+        self.codex_dir.as_deref().unwrap_or(DEFAULT_CODEX_DIR)
+    }
+
+    // Folder under the AI root holding Windsurf (Cascade) notes.
+    pub fn windsurf_dir_name(&self) -> &str {
+        self.windsurf_dir.as_deref().unwrap_or(DEFAULT_WINDSURF_DIR)
+    }
+
+    // Moves old notes of one tool folder (see `claude_dir_name`) into `_archive`.
+    pub fn archive(&self, tool: &str) -> Result<Vec<PathBuf>> {
+        let days = self
+            .archive_days
+            .context("set LLM_LOGS_ARCHIVE_DAYS to use --archive")?;
+        let today = chrono::Local::now().date_naive();
+        archive_tool_dir(self, &self.ai_dir()?.join(tool), today, days)
+    }
+
+    // Human-readable dump for `--print-config`; `cwd` stands in for the hook's cwd.
+    pub fn describe(&self, cwd: &str) -> String {
+        fn path_or_unset(p: &Option<PathBuf>) -> String {
+            p.as_ref()
+                .map_or_else(|| "(unset)".to_string(), |p| p.display().to_string())
+        }
+
+        let rows = [
+            ("OBSIDIAN_VAULT", path_or_unset(&self.vault)),
+            ("OBSIDIAN_AI_ROOT", path_or_unset(&self.ai_root)),
+            (
+                "project",
+                format!("{} (cwd: {cwd})", self.project_name(cwd)),
+            ),
+            (
+                "LLM_LOGS_DATE_FMT",
+                self.formats
+                    .date
+                    .clone()
+                    .unwrap_or_else(|| "(unset)".to_string()),
+            ),
+            ("LLM_LOGS_TIME_FMT", self.formats.time.clone()),
+            (
+                "LLM_LOGS_MISSING_TIME",
+                self.missing_time
+                    .clone()
+                    .unwrap_or_else(|| "(unset)".to_string()),
+            ),
+            (
+                "LLM_LOGS_FORMAT",
+                if self.format.is_empty() {
+                    "(markdown only)".to_string()
+                } else {
+                    self.format.clone()
+                },
+            ),
+            ("LLM_LOGS_LAYOUT", self.layout.as_str().to_string()),
+            ("LLM_LOGS_FLAVOR", self.flavor.as_str().to_string()),
+            (
+                "LLM_LOGS_PROJECT_PATH",
+                self.project_path.as_str().to_string(),
+            ),
+            ("LLM_LOGS_OMIT_CWD", self.omit_cwd.to_string()),
+            (
+                "LLM_LOGS_TITLE_SOURCE",
+                self.title_source.as_str().to_string(),
+            ),
+            ("LLM_LOGS_PLAIN_TITLES", self.plain_titles.to_string()),
+            ("LLM_LOGS_FOLD_TURNS", self.fold_turns.to_string()),
+            ("LLM_LOGS_SKIP_META", self.skip_meta.to_string()),
+            (
+                "LLM_LOGS_COALESCE_ASSISTANT",
+                self.coalesce_assistant.to_string(),
+            ),
+            ("LLM_LOGS_DAILY_DIGEST", self.daily_digest.to_string()),
+            ("LLM_LOGS_RESULT_NOTE", self.result_note.to_string()),
+            (
+                "LLM_LOGS_CODEX_DEBOUNCE_SECS",
+                self.codex_debounce.map_or(0, |d| d.as_secs()).to_string(),
+            ),
+            ("LLM_LOGS_SUMMARY", self.summary.to_string()),
+            ("LLM_LOGS_LLM_CMD", self.llm.program.clone()),
+            (
+                "LLM_LOGS_LLM_TIMEOUT_SECS",
+                self.llm.timeout.as_secs().to_string(),
+            ),
+            ("LLM_LOGS_MIN_MESSAGES", self.min_messages.to_string()),
+            (
+                "LLM_LOGS_SLUG_SEP",
+                self.slug_sep.unwrap_or('_').to_string(),
+            ),
+            ("LLM_LOGS_COPY_REFS", self.copy_refs.to_string()),
+            ("LLM_LOGS_FOOTER_NAV", self.footer_nav.to_string()),
+            ("LLM_LOGS_CODE_CALLOUTS", self.code_callouts.to_string()),
+            ("LLM_LOGS_COLLAPSE_DUPES", self.collapse_dupes.to_string()),
+            ("LLM_LOGS_SHOW_USAGE", self.show_usage.to_string()),
+            ("LLM_LOGS_MONTHLY_FOLDERS", self.monthly_folders.to_string()),
+            (
+                "LLM_LOGS_PROPOSALS_INLINE",
+                self.proposals_inline.to_string(),
+            ),
+            ("LLM_LOGS_CLAUDE_DIR", self.claude_dir_name().to_string()),
+            ("LLM_LOGS_CODEX_DIR", self.codex_dir_name().to_string()),
+            (
+                "LLM_LOGS_WINDSURF_DIR",
+                self.windsurf_dir_name().to_string(),
+            ),
+            ("LLM_LOGS_TURN_SPACING", self.blank_lines().to_string()),
+            ("LLM_LOGS_HIDE_SLASH", self.hide_slash.to_string()),
+            ("LLM_LOGS_SIDECHAIN", self.sidechain.as_str().to_string()),
+            ("LLM_LOGS_SPLIT_SESSIONS", self.split_sessions.to_string()),
+            ("LLM_LOGS_SQLITE", self.sqlite.to_string()),
+            ("LLM_LOGS_IGNORE_PURGE", self.ignore_purge.to_string()),
+            ("LLM_LOGS_FORCE_REBUILD", self.force_rebuild.to_string()),
+            ("LLM_LOGS_REDACT_SECRETS", self.redact_secrets.to_string()),
+            ("LLM_LOGS_REDACT_RAW", self.redact_raw.to_string()),
+            ("LLM_LOGS_INLINE_RAW", self.inline_raw.to_string()),
+            (
+                "LLM_LOGS_INLINE_RAW_MAX_BYTES",
+                self.inline_raw_limit().to_string(),
+            ),
+            ("LLM_LOGS_EMIT_CSV", self.emit_csv.to_string()),
+            ("LLM_LOGS_DEBUG", self.debug.to_string()),
+            (
+                "LLM_LOGS_DEBUG_UNKNOWN_BLOCKS",
+                self.debug_unknown_blocks.to_string(),
+            ),
+            (
+                "LLM_LOGS_MAX_BLOCKS_PER_MSG",
+                self.max_blocks_per_msg
+                    .map_or_else(|| "(unset)".to_string(), |n| n.to_string()),
+            ),
+            (
+                "LLM_LOGS_ASSUME_TZ",
+                match self.assume_tz {
+                    AssumedZone::Local => "local".to_string(),
+                    AssumedZone::Fixed(offset) => offset.to_string(),
+                },
+            ),
+            (
+                "LLM_LOGS_MIRROR_DIR",
+                self.mirror_dir
+                    .as_ref()
+                    .map_or_else(|| "(unset)".to_string(), |d| d.display().to_string()),
+            ),
+            (
+                "LLM_LOGS_TEXT_TYPES",
+                if self.text_types.is_empty() {
+                    DEFAULT_TEXT_TYPES.join(",")
+                } else {
+                    self.text_types.join(",")
+                },
+            ),
+            (
+                "LLM_LOGS_ROLE_ALIASES",
+                self.role_aliases
+                    .iter()
+                    .map(|(alias, role)| (alias.as_str(), role.as_str()))
+                    .chain(DEFAULT_ROLE_ALIASES.iter().copied())
+                    .map(|(alias, role)| format!("{alias}={role}"))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            (
+                "LLM_LOGS_ARCHIVE_DAYS",
+                self.archive_days
+                    .map_or_else(|| "(unset)".to_string(), |d| d.to_string()),
+            ),
+            (
+                "LLM_LOGS_MAX_TURNS",
+                self.max_turns
+                    .map_or_else(|| "(unset)".to_string(), |n| n.to_string()),
+            ),
+            (
+                "LLM_LOGS_RESULT_MAX_CHARS",
+                self.result_max_chars
+                    .map_or_else(|| "(unset)".to_string(), |n| n.to_string()),
+            ),
+        ];
+
+        rows.iter()
+            .map(|(key, value)| format!("{key}: {value}\n"))
+            .collect()
+    }
+}
+
+pub fn digest_begin(session_id: &str) -> String {
+    format!("<!-- BEGIN AUTO DIGEST session:{session_id} -->")
+}
+
+pub fn digest_end(session_id: &str) -> String {
+    format!("<!-- END AUTO DIGEST session:{session_id} -->")
+}
+
+// Each session owns a marker-delimited section in `<dir>/<date>.md`: re-exports
+// replace it in place and new sessions are inserted in order of `first`, the
+// time of their first message on that date.
+pub fn append_to_daily_digest(
+    config: &Config,
+    dir: &Path,
+    date_str: &str,
+    session_id: &str,
+    first: Option<DateTime<Local>>,
+    block: &str,
+) -> Result<PathBuf> {
+    fs::create_dir_all(dir).context("failed to create daily digest dir")?;
+
+    let digest_path = dir.join(format!("{date_str}.md"));
+    let lock_path = dir.join(format!(".lock_{date_str}"));
+
+    with_lock_file(&lock_path, || {
+        let existing = if digest_path.exists() {
+            read_to_string_lossy(&digest_path).context("failed to read daily digest")?
+        } else {
+            format!("# {date_str}\n\n")
+        };
+
+        let section = upsert_digest_section(&existing, session_id, first, block);
+        let updated = normalize_markdown(&section);
+        if updated != existing {
+            config
+                .write_output(&digest_path, &updated)
+                .context("failed to write daily digest")?;
+        }
+        Ok(())
+    })?;
+
+    Ok(digest_path)
+}
+
+// Sits right after a section's BEGIN marker and holds its sort key.
+const DIGEST_FIRST: &str = "<!-- digest-first:";
+
+pub fn upsert_digest_section(
+    existing: &str,
+    session_id: &str,
+    first: Option<DateTime<Local>>,
+    block: &str,
+) -> String {
+    let begin = digest_begin(session_id);
+    let end = digest_end(session_id);
+    let first = first.map(|t| t.format("%Y-%m-%dT%H:%M:%S").to_string());
+    let key = match &first {
+        Some(first) => format!("{DIGEST_FIRST}{first} -->\n"),
+        None => String::new(),
+    };
+    let section = format!("{begin}\n{key}{}\n{end}\n", block.trim());
+
+    match (find_marker(existing, &begin), find_marker(existing, &end)) {
+        (Some(bi), Some(ei)) if ei >= bi => {
+            let pre = &existing[..bi];
+            let post = existing[ei + end.len()..].trim_start_matches('\n');
+            format!("{pre}{section}{post}")
+        }
+        _ => match first.and_then(|first| digest_insert_at(existing, &first)) {
+            Some(at) => format!("{}{section}\n{}", &existing[..at], &existing[at..]),
+            None => {
+                let mut s = existing.trim_end().to_string();
+                s.push_str("\n\n");
+                s.push_str(&section);
+                s
+            }
+        },
+    }
+}
+
+// Where a section starting at `first` goes: before the first section that
+// starts later. Sections without a sort key are left where they are.
+fn digest_insert_at(existing: &str, first: &str) -> Option<usize> {
+    let mut offset = 0;
+    let mut begin_at = None;
+    for line in existing.split_inclusive('\n') {
+        if line.starts_with("<!-- BEGIN AUTO DIGEST session:") {
+            begin_at = Some(offset);
+        } else if let (Some(at), Some(key)) = (begin_at.take(), line.strip_prefix(DIGEST_FIRST)) {
+            if key.trim_end().trim_end_matches("-->").trim() > first {
+                return Some(at);
+            }
+        }
+        offset += line.len();
+    }
+    None
+}
+
+// Takes the session's section out of every digest in `dir`. Returns the
+// digests that changed.
+pub fn remove_from_daily_digests(
+    config: &Config,
+    dir: &Path,
+    session_id: &str,
+) -> Result<Vec<PathBuf>> {
+    let begin = digest_begin(session_id);
+    let end = digest_end(session_id);
+    let mut changed = Vec::new();
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("md") {
+            continue;
+        }
+        let stem = path
+            .file_stem()
+            .and_then(|n| n.to_str())
+            .unwrap_or("digest");
+        let lock_path = dir.join(format!(".lock_{stem}"));
+        let updated = with_lock_file(&lock_path, || {
+            let existing = read_to_string_lossy(&path).context("failed to read daily digest")?;
+            let updated = remove_marked_block(&existing, &begin, &end);
+            if updated == existing {
+                return Ok(false);
+            }
+            config
+                .write_output(&path, &normalize_markdown(&updated))
+                .context("failed to write daily digest")?;
+            Ok(true)
+        })?;
+        if updated {
+            changed.push(path);
+        }
+    }
+    Ok(changed)
+}
+
+pub const ARCHIVE_DIR: &str = "_archive";
+
+// Notes modified this recently are assumed to still be appended to.
+const ARCHIVE_QUIET_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+
+// A note's date: frontmatter `created`, else the `YYYY/MM/DD` folders it sits
+// in below `Threads/`.
+pub fn note_created_date(
+    threads_dir: &Path,
+    path: &Path,
+    frontmatter: &str,
+    formats: &DateFormats,
+) -> Option<NaiveDate> {
+    if let Some(created) = frontmatter_field(frontmatter, "created") {
+        if let Ok(dt) = DateTime::parse_from_rfc3339(&created) {
+            return Some(dt.date_naive());
+        }
+    }
+
+    let rel = path.strip_prefix(threads_dir).ok()?;
+    let parts: Vec<&str> = rel.iter().filter_map(|c| c.to_str()).take(3).collect();
+    let from_folder = match parts.as_slice() {
+        [y, m, d] => y
+            .parse()
+            .ok()
+            .zip(m.parse().ok())
+            .zip(d.parse().ok())
+            .and_then(|((y, m), d)| NaiveDate::from_ymd_opt(y, m, d)),
+        _ => None,
+    };
+    // `note_filename` puts the date before the first `_` when one is configured.
+    let from_filename = || {
+        let fmt = formats.date.as_deref()?;
+        let stem = path.file_stem()?.to_str()?;
+        stem.match_indices('_')
+            .find_map(|(i, _)| heading_date(&stem[..i], fmt))
+    };
+    from_folder.or_else(from_filename)
+}
+
+// Whether `path`, or for a folder any file in it, changed within the
+// archive's quiet period.
+fn recently_modified(path: &Path, now: SystemTime) -> bool {
+    let recent = |p: &Path| {
+        fs::metadata(p)
+            .and_then(|m| m.modified())
+            .map(|m| now.duration_since(m).unwrap_or(Duration::ZERO) < ARCHIVE_QUIET_PERIOD)
+            .unwrap_or(true)
+    };
+    if path.is_dir() {
+        fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .any(|e| recent(&e.path()))
+    } else {
+        recent(path)
+    }
+}
+
+// Today's notes are never old enough, even with `days == 0`.
+pub fn is_archivable(created: NaiveDate, today: NaiveDate, days: u64) -> bool {
+    let age = (today - created).num_days();
+    age > 0 && age as u64 >= days
+}
+
+// Moves notes older than `days` from `threads_dir` to
+// `threads_dir/_archive/{year}/`, keeping their path below the year folder and
+// taking its sidecar files (see `note_sidecars`) along; mirror copies move
+// with them. An exploded session's folder moves as one, dated by its index.
+// Returns the new paths.
+pub fn archive_old_notes(
+    config: &Config,
+    threads_dir: &Path,
+    today: NaiveDate,
+    days: u64,
+) -> Result<Vec<PathBuf>> {
+    let archive_root = threads_dir.join(ARCHIVE_DIR);
+    let now = SystemTime::now();
+    let mut moved = Vec::new();
+    let mut stack = vec![threads_dir.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(e) => e,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let note = if path.is_dir() {
+                let index = path.join(EXPLODED_INDEX_FILE);
+                if path != archive_root && !index.is_file() {
+                    stack.push(path);
+                    continue;
+                }
+                if path == archive_root {
+                    continue;
+                }
+                index
+            } else if path.extension().and_then(|e| e.to_str()) != Some("md")
+                || path.file_name().and_then(|n| n.to_str()) == Some(EXPLODED_INDEX_FILE)
+            {
+                continue;
+            } else {
+                path.clone()
+            };
+
+            if recently_modified(&path, now) {
+                continue;
+            }
+
+            let frontmatter = read_frontmatter(&note).unwrap_or_default();
+            let created = match note_created_date(threads_dir, &path, &frontmatter, &config.formats)
+            {
+                Some(d) if is_archivable(d, today, days) => d,
+                _ => continue,
+            };
+
+            let year = created.format("%Y").to_string();
+            let rel = path.strip_prefix(threads_dir).unwrap_or(&path);
+            let rel = rel.strip_prefix(&year).unwrap_or(rel);
+            let dest = archive_root.join(&year).join(rel);
+            if path.is_dir() {
+                let dest_dir = dest.parent().context("archive path has no parent")?;
+                fs::create_dir_all(dest_dir).context("failed to create archive folder")?;
+                config
+                    .move_output(&path, &dest)
+                    .context("failed to archive session folder")?;
+                moved.push(dest);
+            } else {
+                moved.push(move_with_sidecars(config, &path, &dest)?);
+            }
+        }
+    }
+    Ok(moved)
+}
+
+// Removes a session's note and what was exported along with it: sidecars,
+// the result note, its daily digest sections and its SQLite rows. Mirror
+// copies go too. The digests are keyed by `session_id_safe`, the index by the
+// raw `session_id`.
+pub fn purge_exported_session(
+    config: &Config,
+    base_dir: &Path,
+    notes: &[PathBuf],
+    session_id: &str,
+    session_id_safe: &str,
+) -> Result<()> {
+    for note in notes {
+        for sidecar in note_sidecars(note) {
+            config.remove_output(&sidecar)?;
+        }
+        if let Some(result) = result_note_path(base_dir, note) {
+            config.remove_output(&result)?;
+        }
+        config.remove_output(note)?;
+    }
+    remove_from_daily_digests(config, &base_dir.join("Daily"), session_id_safe)?;
+    // Without an AI folder nothing was ever indexed.
+    let db_path = match config.ai_dir() {
+        Ok(ai_dir) => index::index_path(&ai_dir),
+        Err(_) => return Ok(()),
+    };
+    if db_path.exists() {
+        index::remove_session(&db_path, session_id)?;
+    }
+    Ok(())
+}
+
+// Files written next to a note under its name: `<stem>.html`, `<stem>.txt`,
+// `<stem>.messages.jsonl`, `<stem>.csv`.
+pub fn note_sidecars(note: &Path) -> Vec<PathBuf> {
+    let (dir, stem) = match (note.parent(), note.file_stem().and_then(|n| n.to_str())) {
+        (Some(dir), Some(stem)) => (dir, format!("{stem}.")),
+        _ => return Vec::new(),
+    };
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|p| p != note && p.is_file())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(&stem))
+        })
+        .collect()
+}
+
+fn move_with_sidecars(config: &Config, path: &Path, dest: &Path) -> Result<PathBuf> {
+    let dest_dir = dest.parent().context("archive path has no parent")?;
+    fs::create_dir_all(dest_dir).context("failed to create archive folder")?;
+    let sidecars = note_sidecars(path);
+    config
+        .move_output(path, dest)
+        .context("failed to archive note")?;
+
+    for sidecar in sidecars {
+        if let Some(name) = sidecar.file_name() {
+            let _ = config.move_output(&sidecar, &dest_dir.join(name));
+        }
+    }
+    Ok(dest.to_path_buf())
+}
+
+// Runs `archive_old_notes` for every project under a tool folder such as
+// `$OBSIDIAN_VAULT/$OBSIDIAN_AI_ROOT/Codex`.
+pub fn archive_tool_dir(
+    config: &Config,
+    tool_dir: &Path,
+    today: NaiveDate,
+    days: u64,
+) -> Result<Vec<PathBuf>> {
+    let mut moved = Vec::new();
+    let projects = match fs::read_dir(tool_dir) {
+        Ok(p) => p,
+        Err(_) => return Ok(moved),
+    };
+    for project in projects.flatten() {
+        let threads_dir = project.path().join("Threads");
+        if threads_dir.is_dir() {
+            moved.extend(archive_old_notes(config, &threads_dir, today, days)?);
+        }
+    }
+    Ok(moved)
+}
+
+// State file, under a tool folder, recording transcripts a batch import has
+// already exported.
+pub const PROCESSED_INDEX_FILE: &str = ".processed_index.json";
+
+// Size and modification time of a raw file when it was processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStamp {
+    pub size: u64,
+    pub mtime_ms: u64,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> io::Result<Self> {
+        let meta = fs::metadata(path)?;
+        let mtime_ms = meta
+            .modified()?
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_millis();
+        Ok(Self {
+            size: meta.len(),
+            mtime_ms: u64::try_from(mtime_ms).unwrap_or(u64::MAX),
+        })
+    }
+}
+
+// Raw files already exported, keyed by path. A file is re-exported once its
+// size or mtime differs from what was recorded.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProcessedIndex {
+    files: BTreeMap<String, FileStamp>,
+}
+
+impl ProcessedIndex {
+    // A missing or unreadable index just means everything is processed again.
+    pub fn load(path: &Path) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json =
+            serde_json::to_string_pretty(self).context("failed to encode processed index")?;
+        write_atomic(path, &json).context("failed to write processed index")
+    }
+
+    pub fn is_unchanged(&self, file: &Path, stamp: FileStamp) -> bool {
+        self.files.get(&file.to_string_lossy().into_owned()) == Some(&stamp)
+    }
+
+    pub fn record(&mut self, file: &Path, stamp: FileStamp) {
+        self.files
+            .insert(file.to_string_lossy().into_owned(), stamp);
+    }
+}
+
+// Writes to a temporary sibling and renames it over `path`, so a crash leaves
+// either the old contents or the new ones.
+pub fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, contents).with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("failed to replace {}", path.display()))
+}
+
+// Per-project note list, the target of the footer's `Overview` link.
+pub const OVERVIEW_FILE: &str = "_overview.md";
+
+// Every exported note under the AI root, one entry per session or thread.
+pub const MANIFEST_FILE: &str = "_manifest.json";
+
+// A note as listed in the manifest, read back from its frontmatter.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub tool: String,
+    pub project: String,
+    // `session_id` for Claude Code, `thread_id` for Codex.
+    pub id: String,
+    pub created: Option<String>,
+    // Relative to the AI root, with `/` separators.
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReindexReport {
+    pub notes: usize,
+    pub projects: usize,
+}
+
+// The notes below one project's `Threads/`, oldest first. Turn archives and
+// exploded message files have no entry of their own; an exploded session is
+// listed by its `index.md`.
+pub fn collect_project_notes(ai_dir: &Path, project_dir: &Path) -> Vec<(PathBuf, ManifestEntry)> {
+    let mut notes = Vec::new();
+    let mut stack = vec![project_dir.join("Threads")];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                stack.push(path);
+                continue;
+            }
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            if !name.ends_with(".md") || is_turn_archive_name(name) {
+                continue;
+            }
+            let header = match read_note_header(&path) {
+                Some(header) => header,
+                None => continue,
+            };
+            let id =
+                header_field(&header, "session_id").or_else(|| header_field(&header, "thread_id"));
+            let (tool, id) = match (header_field(&header, "tool"), id) {
+                (Some(tool), Some(id)) => (tool, id),
+                _ => continue,
+            };
+            let entry = ManifestEntry {
+                tool,
+                project: header_field(&header, "project").unwrap_or_default(),
+                id,
+                created: header_field(&header, "created"),
+                path: slash_path(path.strip_prefix(ai_dir).unwrap_or(&path)),
+            };
+            notes.push((path, entry));
+        }
+    }
+    notes.sort_by(|(_, a), (_, b)| (&a.created, &a.path).cmp(&(&b.created, &b.path)));
+    notes
+}
+
+fn slash_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// A note's leading properties: its `---` frontmatter, or the `key:: value`
+// lines that open a Logseq page. Only that header is read from disk.
+pub fn read_note_header(path: &Path) -> Option<String> {
+    if let Some(frontmatter) = read_frontmatter(path) {
+        return Some(frontmatter);
+    }
+    let reader = io::BufReader::new(fs::File::open(path).ok()?);
+    let properties: Vec<String> = reader
+        .lines()
+        .map_while(Result::ok)
+        .take_while(|line| {
+            line.split_once(":: ")
+                .is_some_and(|(key, _)| !key.contains(' '))
+        })
+        .collect();
+    (!properties.is_empty()).then(|| properties.join("\n"))
+}
+
+// `key` from a header read by `read_note_header`, in either form.
+pub fn header_field(header: &str, key: &str) -> Option<String> {
+    frontmatter_field(header, key).or_else(|| {
+        let prefix = format!("{key}:: ");
+        header
+            .lines()
+            .find_map(|l| l.strip_prefix(&prefix))
+            .map(|v| v.trim().to_string())
+    })
+}
+
+// The `_overview.md` of a project: one line per note, oldest first, each
+// linking to the note by its vault path.
+pub fn build_overview(project: &str, notes: &[(String, &ManifestEntry)]) -> String {
+    let mut out = format!("# {project}\n\n");
+    for (link, entry) in notes {
+        let date = entry
+            .created
+            .as_deref()
+            .and_then(|c| DateTime::parse_from_rfc3339(c).ok())
+            .map(|dt| format!("{} ", dt.format("%Y-%m-%d")))
+            .unwrap_or_default();
+        let stem = link.rsplit('/').next().unwrap_or(link);
+        out.push_str(&format!("- {date}[[{link}|{stem}]] ({})\n", entry.tool));
+    }
+    out
+}
+
+// A message as rendered in a note: `### <timestamp> User|Assistant` plus body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteMessage {
+    pub role: &'static str,
+    pub timestamp: String,
+    pub text: String,
+}
+
+fn message_heading(line: &str) -> Option<(&'static str, &str)> {
+    let rest = line.strip_prefix("### ")?.trim_end();
+    if let Some(ts) = rest.strip_suffix("User") {
+        return Some(("user", ts.trim()));
+    }
+    rest.strip_suffix("Assistant")
+        .map(|ts| ("assistant", ts.trim()))
+}
+
+// Messages of a Claude Code or Codex note, in order. Headings inside fenced
+// code are part of the message; marker comments (`<!-- ... -->` lines) end it.
+pub fn parse_note(md: &str) -> Vec<NoteMessage> {
+    let mut msgs: Vec<NoteMessage> = Vec::new();
+    let mut current: Option<NoteMessage> = None;
+    let mut in_code = false;
+
+    for line in md.lines() {
+        if !in_code {
+            if let Some((role, ts)) = message_heading(line) {
+                msgs.extend(current.take());
+                current = Some(NoteMessage {
+                    role,
+                    timestamp: ts.to_string(),
+                    text: String::new(),
+                });
+                continue;
+            }
+            if line.starts_with("<!-- ") {
+                msgs.extend(current.take());
+                continue;
+            }
+        }
+        if is_fence_line(line.trim()) {
+            in_code = !in_code;
+        }
+        if let Some(m) = current.as_mut() {
+            m.text.push_str(line);
+            m.text.push('\n');
+        }
+    }
+    msgs.extend(current);
+
+    for m in &mut msgs {
+        m.text = m.text.trim().to_string();
+    }
+    msgs
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+// One message that differs between two exports; `index` is its 0-based
+// position and `preview` the start of its (new, or removed) text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    pub index: usize,
+    pub role: &'static str,
+    pub preview: String,
+}
+
+pub const CHANGE_PREVIEW_CHARS: usize = 60;
+
+fn change_preview(text: &str) -> String {
+    let flat = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if flat.chars().count() > CHANGE_PREVIEW_CHARS {
+        let cut: String = flat.chars().take(CHANGE_PREVIEW_CHARS).collect();
+        format!("{cut}…")
+    } else {
+        flat
+    }
+}
+
+// Compares two renderings of the same session message by message.
+// Transcripts only grow at the end, so new turns come out as `Added`; an
+// edited message is `Modified` and a truncated tail `Removed`.
+// Timestamps are ignored since a different time format is not a change.
+pub fn diff_transcripts(old_md: &str, new_md: &str) -> Vec<Change> {
+    let old = parse_note(old_md);
+    let new = parse_note(new_md);
+    let mut changes = Vec::new();
+
+    for i in 0..old.len().max(new.len()) {
+        let (kind, msg) = match (old.get(i), new.get(i)) {
+            (Some(o), Some(n)) if o.role == n.role && o.text == n.text => continue,
+            (Some(_), Some(n)) => (ChangeKind::Modified, n),
+            (None, Some(n)) => (ChangeKind::Added, n),
+            (Some(o), None) => (ChangeKind::Removed, o),
+            (None, None) => break,
+        };
+        changes.push(Change {
+            kind,
+            index: i,
+            role: msg.role,
+            preview: change_preview(&msg.text),
+        });
+    }
+    changes
 }
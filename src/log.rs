@@ -0,0 +1,109 @@
+//! Stderr diagnostics shared by the binaries. `-q`/`--quiet` silences them,
+//! `-v`/`--verbose` adds informational and debug messages, and only warnings
+//! are printed by default. Errors returned from `main` are not affected.
+
+use crate::take_flag;
+use std::{
+    io::{self, Write},
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    #[default]
+    Normal,
+    Verbose,
+}
+
+// How important a message is; the `Verbosity` decides which get printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Verbosity {
+    pub fn allows(self, level: Level) -> bool {
+        match level {
+            Level::Warn => self >= Self::Normal,
+            Level::Info | Level::Debug => self >= Self::Verbose,
+        }
+    }
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+static DEBUG: AtomicBool = AtomicBool::new(false);
+
+// `LLM_LOGS_DEBUG` (`Config::debug`) turns the debug messages on without `-v`;
+// `-q` still wins.
+pub fn set_debug(on: bool) {
+    DEBUG.store(on, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        2 => Verbosity::Verbose,
+        _ if DEBUG.load(Ordering::Relaxed) => Verbosity::Verbose,
+        _ => Verbosity::Normal,
+    }
+}
+
+// Pulls `-q`/`--quiet` and `-v`/`--verbose` out of the arguments wherever they
+// appear; quiet wins when both are given.
+pub fn take_verbosity(args: &[String]) -> (Verbosity, Vec<String>) {
+    let (q, args) = take_flag(args, "-q");
+    let (quiet, args) = take_flag(&args, "--quiet");
+    let (v, args) = take_flag(&args, "-v");
+    let (verbose, args) = take_flag(&args, "--verbose");
+    let verbosity = if q || quiet {
+        Verbosity::Quiet
+    } else if v || verbose {
+        Verbosity::Verbose
+    } else {
+        Verbosity::Normal
+    };
+    (verbosity, args)
+}
+
+// The seam behind `warn`/`info`/`debug`: `message` is only built, and written
+// to `out`, when `verbosity` allows `level`. Returns whether it was written.
+pub fn write_log(
+    out: &mut impl Write,
+    verbosity: Verbosity,
+    level: Level,
+    message: impl FnOnce() -> String,
+) -> bool {
+    if !verbosity.allows(level) {
+        return false;
+    }
+    let prefix = match level {
+        Level::Warn => "warning: ",
+        Level::Info => "",
+        Level::Debug => "debug: ",
+    };
+    writeln!(out, "{prefix}{}", message()).is_ok()
+}
+
+fn log(level: Level, message: impl FnOnce() -> String) {
+    write_log(&mut io::stderr().lock(), verbosity(), level, message);
+}
+
+pub fn warn(message: impl FnOnce() -> String) {
+    log(Level::Warn, message);
+}
+
+pub fn info(message: impl FnOnce() -> String) {
+    log(Level::Info, message);
+}
+
+pub fn debug(message: impl FnOnce() -> String) {
+    log(Level::Debug, message);
+}
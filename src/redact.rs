@@ -0,0 +1,103 @@
+//! Masks API keys and tokens in message text before it is written to the
+//! vault (`LLM_LOGS_REDACT_SECRETS=1`).
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde_json::Value;
+use std::sync::OnceLock;
+
+pub const REDACTED: &str = "[REDACTED]";
+
+// Extra patterns, one regex per line, are read from this file in the vault root.
+pub const REDACT_FILE: &str = ".llmlogsredact";
+
+// When a pattern has a capture group, only the group is masked, so the key name
+// (`Authorization: Bearer`, `api_key=`) stays readable; otherwise the whole
+// match is. Context-free patterns require a known prefix, and the generic one
+// requires a key-like name, so ordinary hashes and ids are left alone.
+pub const DEFAULT_SECRET_PATTERNS: &[&str] = &[
+    // AWS access key ids.
+    r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b",
+    // OpenAI / Anthropic style keys.
+    r"\bsk-(?:ant-|proj-)?[A-Za-z0-9_-]{20,}",
+    // GitHub tokens.
+    r"\bgh[pousr]_[A-Za-z0-9]{36,}\b",
+    r"\bgithub_pat_[A-Za-z0-9_]{22,}\b",
+    // Slack tokens.
+    r"\bxox[abprs]-[A-Za-z0-9-]{10,}",
+    // Google API keys.
+    r"\bAIza[0-9A-Za-z_-]{35}\b",
+    r"(?i)\bbearer\s+([A-Za-z0-9._~+/-]{16,}=*)",
+    // `api_key = "…"`, `SECRET: …`, `password=…` and friends.
+    concat!(
+        r"(?i)\b(?:[a-z0-9]+[_-])*(?:api[_-]?key|secret|token|passw(?:or)?d|access[_-]?key)",
+        r#"["']?\s*[:=]\s*["']?([A-Za-z0-9+/_.=-]{12,})"#,
+    ),
+    r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----",
+];
+
+#[derive(Debug, Clone)]
+pub struct SecretRedactor {
+    patterns: Vec<Regex>,
+}
+
+impl SecretRedactor {
+    // `DEFAULT_SECRET_PATTERNS` plus `extra`; an invalid extra pattern is an error.
+    pub fn new(extra: &[String]) -> Result<Self> {
+        let mut patterns: Vec<Regex> = DEFAULT_SECRET_PATTERNS
+            .iter()
+            .map(|p| Regex::new(p).expect("built-in secret pattern"))
+            .collect();
+        for p in extra {
+            let re = Regex::new(p).with_context(|| format!("invalid secret pattern: {p:?}"))?;
+            patterns.push(re);
+        }
+        Ok(Self { patterns })
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut out = text.to_string();
+        for re in &self.patterns {
+            if !re.is_match(&out) {
+                continue;
+            }
+            out = re
+                .replace_all(&out, |caps: &regex::Captures| {
+                    let whole = caps.get(0).expect("match");
+                    match caps.get(1) {
+                        Some(secret) => {
+                            let start = secret.start() - whole.start();
+                            let end = secret.end() - whole.start();
+                            let m = whole.as_str();
+                            format!("{}{REDACTED}{}", &m[..start], &m[end..])
+                        }
+                        None => REDACTED.to_string(),
+                    }
+                })
+                .into_owned();
+        }
+        out
+    }
+
+    // Every string in `v`, keys excepted, passed through `redact`.
+    pub fn redact_json(&self, v: &Value) -> Value {
+        match v {
+            Value::String(s) => Value::String(self.redact(s)),
+            Value::Array(arr) => Value::Array(arr.iter().map(|x| self.redact_json(x)).collect()),
+            Value::Object(obj) => Value::Object(
+                obj.iter()
+                    .map(|(k, x)| (k.clone(), self.redact_json(x)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+}
+
+// `text` with the built-in secret patterns masked.
+pub fn redact_secrets(text: &str) -> String {
+    static DEFAULT: OnceLock<SecretRedactor> = OnceLock::new();
+    DEFAULT
+        .get_or_init(|| SecretRedactor::new(&[]).expect("built-in secret patterns"))
+        .redact(text)
+}
@@ -0,0 +1,289 @@
+//! Transcript messages and their Markdown rendering, shared by
+//! `claude_session_to_obsidian`, `windsurf_to_obsidian` and any tool that
+//! turns a chat log into a note.
+
+use crate::{
+    elide_data_uris, escape_marker_lines, fence_delimiter, quote_lines, section_gap, Config,
+    DEFAULT_TIME_FMT, DEFAULT_TURN_SPACING,
+};
+use chrono::{DateTime, Local};
+use std::borrow::Cow;
+
+pub const BEGIN: &str = "<!-- BEGIN AUTO TRANSCRIPT -->";
+pub const END: &str = "<!-- END AUTO TRANSCRIPT -->";
+
+#[derive(Debug, Clone)]
+pub struct Msg {
+    pub role: &'static str,
+    pub text: String,
+    pub ts: Option<DateTime<Local>>,
+    // The transcript was compacted or resumed right before this message.
+    pub resumed: bool,
+    // Tokens of the assistant entries behind this message, including tool-only
+    // entries since the previous assistant message.
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub input: u64,
+    pub output: u64,
+}
+
+impl Usage {
+    pub fn total(self) -> u64 {
+        self.input + self.output
+    }
+}
+
+impl std::ops::Add for Usage {
+    type Output = Usage;
+
+    fn add(self, other: Usage) -> Usage {
+        Usage {
+            input: self.input + other.input,
+            output: self.output + other.output,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub time_fmt: String,
+    // Stands in for the time of messages without one (`LLM_LOGS_MISSING_TIME`).
+    pub missing_time: Option<String>,
+    // Wrap each user→assistant turn in a collapsed Obsidian callout.
+    pub fold_turns: bool,
+    // Blank lines between messages, or between turns when folded.
+    pub blank_lines: usize,
+    // Assistant messages with a fenced code block go in a `> [!code]` callout.
+    pub code_callouts: bool,
+    // Consecutive byte-identical assistant messages render once, headed `(×N)`.
+    pub collapse_dupes: bool,
+    // Assistant messages with token usage get a `<sub>↑in ↓out tokens</sub>` footer.
+    pub show_usage: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            time_fmt: DEFAULT_TIME_FMT.to_string(),
+            missing_time: None,
+            fold_turns: false,
+            blank_lines: DEFAULT_TURN_SPACING,
+            code_callouts: false,
+            collapse_dupes: false,
+            show_usage: false,
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            time_fmt: config.formats.time.clone(),
+            missing_time: config.missing_time.clone(),
+            fold_turns: config.fold_turns,
+            blank_lines: config.blank_lines(),
+            code_callouts: config.code_callouts,
+            collapse_dupes: config.collapse_dupes,
+            show_usage: config.show_usage,
+        }
+    }
+}
+
+pub fn build_transcript_block(
+    exported: &str,
+    source: &str,
+    msgs: &[Msg],
+    opts: &RenderOptions,
+) -> String {
+    format!(
+        "{BEGIN}\n{}{END}\n",
+        build_transcript_body(exported, source, msgs, opts)
+    )
+}
+
+pub fn build_transcript_body(
+    exported: &str,
+    source: &str,
+    msgs: &[Msg],
+    opts: &RenderOptions,
+) -> String {
+    let mut out = String::new();
+    out.push_str("## Transcript (auto)\n");
+    out.push_str(&format!("- Exported: {exported}\n"));
+    out.push_str(&format!("- Source transcript: {source}\n\n"));
+
+    let msgs = with_code_callouts(msgs, opts);
+    if opts.fold_turns {
+        out.push_str(&render_turn_callouts(&msgs, opts));
+    } else {
+        out.push_str(&render_messages(&msgs, opts));
+    }
+    out
+}
+
+pub fn has_code_fence(text: &str) -> bool {
+    text.lines().any(|l| fence_delimiter(l).is_some())
+}
+
+// With `code_callouts`, assistant answers containing code are quoted into a
+// `> [!code]` callout so they stand out from prose, which is left as it is.
+pub fn with_code_callouts<'a>(msgs: &'a [Msg], opts: &RenderOptions) -> Cow<'a, [Msg]> {
+    if !opts.code_callouts {
+        return Cow::Borrowed(msgs);
+    }
+    let wrap = |m: &Msg| {
+        let mut m = m.clone();
+        if m.role == "assistant" && has_code_fence(&m.text) {
+            m.text = code_callout(&m.text);
+        }
+        m
+    };
+    Cow::Owned(msgs.iter().map(wrap).collect())
+}
+
+pub fn code_callout(text: &str) -> String {
+    format!("> [!code]\n{}", quote_lines(text.trim_end()))
+}
+
+const RESUMED_CALLOUT: &str = "---\n\n> [!info] Session resumed";
+
+// `### <time> User`, or `### User` when the message has no timestamp and no
+// placeholder is configured.
+pub fn message_heading(m: &Msg, time_fmt: &str, missing_time: Option<&str>) -> String {
+    let who = if m.role == "user" {
+        "User"
+    } else {
+        "Assistant"
+    };
+    let time = match m.ts {
+        Some(t) => t.format(time_fmt).to_string(),
+        None => missing_time.unwrap_or_default().to_string(),
+    };
+    if time.trim().is_empty() {
+        format!("### {who}\n")
+    } else {
+        format!("### {time} {who}\n")
+    }
+}
+
+pub fn render_messages(msgs: &[Msg], opts: &RenderOptions) -> String {
+    let blank_lines = opts.blank_lines;
+    let mut out = String::new();
+    let mut i = 0;
+    while i < msgs.len() {
+        let m = &msgs[i];
+        let repeats = if opts.collapse_dupes {
+            repeat_count(&msgs[i..])
+        } else {
+            1
+        };
+        i += repeats;
+        if m.resumed {
+            out.push_str(RESUMED_CALLOUT);
+            out.push_str(&section_gap(blank_lines));
+        }
+        // Slash-commands get a quiet one-line quote instead of a full turn heading.
+        if m.role == "user" && is_slash_command(&m.text) {
+            out.push_str(&format!("> {}", m.text.trim()));
+            out.push_str(&section_gap(blank_lines));
+            continue;
+        }
+        let heading = message_heading(m, &opts.time_fmt, opts.missing_time.as_deref());
+        if repeats > 1 {
+            out.push_str(&format!("{} (×{repeats})\n", heading.trim_end()));
+        } else {
+            out.push_str(&heading);
+        }
+        out.push_str(escape_marker_lines(&elide_data_uris(&m.text)).trim_end());
+        if let Some(usage) = m.usage.filter(|_| opts.show_usage) {
+            out.push_str(&format!(
+                "\n\n<sub>↑{} ↓{} tokens</sub>",
+                usage.input, usage.output
+            ));
+        }
+        out.push_str(&section_gap(blank_lines));
+    }
+    out
+}
+
+// How many messages at the start of `msgs` repeat the first one: an assistant
+// message followed by byte-identical assistant messages, none of them resumed.
+pub fn repeat_count(msgs: &[Msg]) -> usize {
+    match msgs.split_first() {
+        Some((first, rest)) if first.role == "assistant" => {
+            1 + rest
+                .iter()
+                .take_while(|m| m.role == "assistant" && !m.resumed && m.text == first.text)
+                .count()
+        }
+        Some(_) => 1,
+        None => 0,
+    }
+}
+
+// Groups messages into turns: user messages plus the assistant replies that
+// follow them. Assistant messages before any user message form their own turn,
+// and a trailing user message without a reply is kept as a user-only turn.
+pub fn pair_turns(msgs: &[Msg]) -> Vec<&[Msg]> {
+    let mut turns = Vec::new();
+    let mut start = 0;
+    for i in 1..msgs.len() {
+        if msgs[i].role == "user" && msgs[i - 1].role != "user" {
+            turns.push(&msgs[start..i]);
+            start = i;
+        }
+    }
+    if start < msgs.len() {
+        turns.push(&msgs[start..]);
+    }
+    turns
+}
+
+// Each turn is rendered like the unfolded transcript, headings included, and
+// quoted into a collapsed callout; a resume shows above the turn instead.
+fn render_turn_callouts(msgs: &[Msg], opts: &RenderOptions) -> String {
+    let mut out = String::new();
+    for (i, turn) in pair_turns(msgs).into_iter().enumerate() {
+        if turn.iter().any(|m| m.resumed) {
+            out.push_str(RESUMED_CALLOUT);
+            out.push_str(&section_gap(opts.blank_lines));
+        }
+        // Like `message_heading`: the placeholder, or nothing, without a time.
+        let time = match turn.iter().find_map(|m| m.ts) {
+            Some(t) => t.format(&opts.time_fmt).to_string(),
+            None => opts.missing_time.clone().unwrap_or_default(),
+        };
+        if time.trim().is_empty() {
+            out.push_str(&format!("> [!example]- Turn {}\n", i + 1));
+        } else {
+            out.push_str(&format!("> [!example]- Turn {} ({time})\n", i + 1));
+        }
+
+        let turn: Vec<Msg> = turn
+            .iter()
+            .map(|m| Msg {
+                resumed: false,
+                ..m.clone()
+            })
+            .collect();
+        out.push_str(&quote_lines(render_messages(&turn, opts).trim_end()));
+        out.push_str(&"\n".repeat(opts.blank_lines));
+    }
+    out
+}
+
+// A user message that is nothing but a command such as `/compact` or
+// `/project:deploy`; a prompt that merely mentions a path or command isn't one.
+pub fn is_slash_command(text: &str) -> bool {
+    let rest = match text.trim().strip_prefix('/') {
+        Some(rest) => rest,
+        None => return false,
+    };
+    rest.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && rest
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | ':' | '.'))
+}
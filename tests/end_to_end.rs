@@ -0,0 +1,512 @@
+// Runs the built hook binaries the way Claude Code and Codex CLI do and checks
+// what ends up in the vault.
+
+use assert_cmd::Command;
+use serde_json::json;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tempfile::TempDir;
+
+const VAULT_AI_ROOT: &str = "llms";
+
+// A throwaway vault plus a working directory named `proj`. `PATH` points at an
+// empty folder so neither `git` nor the title LLM can leak in from the host.
+struct Sandbox {
+    dir: TempDir,
+}
+
+impl Sandbox {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("vault")).unwrap();
+        fs::create_dir_all(dir.path().join("proj")).unwrap();
+        fs::create_dir_all(dir.path().join("bin")).unwrap();
+        Self { dir }
+    }
+
+    fn path(&self, rel: &str) -> PathBuf {
+        self.dir.path().join(rel)
+    }
+
+    fn command(&self, bin: &str) -> Command {
+        let mut cmd = Command::cargo_bin(bin).unwrap();
+        cmd.env_clear()
+            .env("PATH", self.path("bin"))
+            .env("OBSIDIAN_VAULT", self.path("vault"))
+            .env("OBSIDIAN_AI_ROOT", VAULT_AI_ROOT)
+            .env("LLM_LOGS_PLAIN_TITLES", "1");
+        cmd
+    }
+
+    // Puts a `git` on the sandbox `PATH` that runs `body` as a shell script.
+    #[cfg(unix)]
+    fn fake_git(&self, body: &str) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = self.path("bin").join("git");
+        fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    fn tool_dir(&self, tool: &str) -> PathBuf {
+        self.path("vault")
+            .join(VAULT_AI_ROOT)
+            .join(tool)
+            .join("proj")
+    }
+}
+
+fn md_files(dir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                stack.push(path);
+            } else if path.extension().is_some_and(|e| e == "md") {
+                found.push(path);
+            }
+        }
+    }
+    found
+}
+
+fn claude_line(kind: &str, ts: &str, text: &str) -> String {
+    json!({
+        "type": kind,
+        "timestamp": ts,
+        "cwd": "/work/proj",
+        "message": {"role": kind, "content": [{"type": "text", "text": text}]},
+    })
+    .to_string()
+}
+
+#[test]
+fn claude_hook_writes_note_and_upserts_on_rerun() {
+    let sandbox = Sandbox::new();
+    let transcript = sandbox.path("session.jsonl");
+    let mut lines = vec![
+        claude_line("user", "2024-05-01T10:00:00Z", "Fix the login bug"),
+        claude_line("assistant", "2024-05-01T10:00:05Z", "Fixed in auth.rs"),
+    ];
+    fs::write(&transcript, lines.join("\n")).unwrap();
+    let payload = json!({
+        "session_id": "e2e-session",
+        "transcript_path": transcript,
+        "cwd": sandbox.path("proj"),
+    })
+    .to_string();
+
+    sandbox
+        .command("claude_session_to_obsidian")
+        .write_stdin(payload.clone())
+        .assert()
+        .success();
+
+    let threads = sandbox.tool_dir("Claude Code").join("Threads");
+    let notes = md_files(&threads);
+    assert_eq!(notes.len(), 1);
+    let note = fs::read_to_string(&notes[0]).unwrap();
+    assert!(note.starts_with("---\ntool: \"Claude Code\"\n"));
+    assert!(note.contains("session_id: \"e2e-session\""));
+    assert!(note.contains("<!-- BEGIN AUTO TRANSCRIPT -->"));
+    assert!(note.contains("Fix the login bug"));
+    assert!(note.contains("Fixed in auth.rs"));
+
+    // A later Stop hook for the same session rewrites the block in place.
+    fs::write(notes[0].as_path(), format!("{note}\nmy own notes\n")).unwrap();
+    lines.push(claude_line(
+        "user",
+        "2024-05-01T10:01:00Z",
+        "Add a test too",
+    ));
+    fs::write(&transcript, lines.join("\n")).unwrap();
+    sandbox
+        .command("claude_session_to_obsidian")
+        .write_stdin(payload)
+        .assert()
+        .success();
+
+    assert_eq!(md_files(&threads), notes);
+    let note = fs::read_to_string(&notes[0]).unwrap();
+    assert_eq!(note.matches("<!-- BEGIN AUTO TRANSCRIPT -->").count(), 1);
+    assert_eq!(note.matches("Fix the login bug").count(), 1);
+    assert!(note.contains("Add a test too"));
+    assert!(note.contains("my own notes"));
+}
+
+#[test]
+fn codex_notify_writes_raw_log_and_note_and_dedupes_rerun() {
+    let sandbox = Sandbox::new();
+    let notification = |turn: &str, input: &str| {
+        json!({
+            "type": "agent-turn-complete",
+            "thread-id": "e2e-thread",
+            "turn-id": turn,
+            "cwd": sandbox.path("proj"),
+            "input-messages": [input],
+            "last-assistant-message": format!("done: {input}"),
+        })
+        .to_string()
+    };
+
+    let first = notification("turn-1", "Rename the module");
+    sandbox
+        .command("codex_notify_to_obsidian")
+        .arg(&first)
+        .assert()
+        .success();
+
+    let base = sandbox.tool_dir("Codex");
+    let raw = base.join("_raw").join("notify").join("e2e-thread.jsonl");
+    assert_eq!(fs::read_to_string(&raw).unwrap(), format!("{first}\n"));
+
+    let notes = md_files(&base.join("Threads"));
+    assert_eq!(notes.len(), 1);
+    let note = fs::read_to_string(&notes[0]).unwrap();
+    assert!(note.starts_with("---\ntool: \"Codex CLI\"\n"));
+    assert!(note.contains("thread_id: \"e2e-thread\""));
+    assert!(note.contains("<!-- turn-id:turn-1 -->"));
+    assert!(note.contains("- Rename the module"));
+    assert!(note.contains("done: Rename the module"));
+
+    // Codex may deliver the same turn twice; each turn still lands in the note once.
+    for payload in [&first, &notification("turn-2", "Update the docs")] {
+        sandbox
+            .command("codex_notify_to_obsidian")
+            .arg(payload)
+            .assert()
+            .success();
+    }
+
+    assert_eq!(md_files(&base.join("Threads")), notes);
+    let note = fs::read_to_string(&notes[0]).unwrap();
+    assert_eq!(note.matches("<!-- turn-id:turn-1 -->").count(), 1);
+    assert_eq!(note.matches("<!-- turn-id:turn-2 -->").count(), 1);
+    // The repeated notification was the raw log's last line, so it isn't logged twice.
+    assert_eq!(fs::read_to_string(&raw).unwrap().lines().count(), 2);
+}
+
+fn run_claude_hook(sandbox: &Sandbox) {
+    let transcript = sandbox.path("session.jsonl");
+    let line = claude_line("user", "2024-05-01T10:00:00Z", "Where does this land?");
+    fs::write(&transcript, line).unwrap();
+    let payload = json!({
+        "session_id": "git-session",
+        "transcript_path": transcript,
+        "cwd": sandbox.path("proj"),
+    })
+    .to_string();
+    sandbox
+        .command("claude_session_to_obsidian")
+        .write_stdin(payload)
+        .assert()
+        .success();
+}
+
+#[cfg(unix)]
+#[test]
+fn project_name_comes_from_git_toplevel() {
+    let sandbox = Sandbox::new();
+    sandbox.fake_git("echo /src/checkout/repo-name");
+
+    run_claude_hook(&sandbox);
+
+    let project = sandbox
+        .path("vault")
+        .join(VAULT_AI_ROOT)
+        .join("Claude Code")
+        .join("repo-name");
+    assert_eq!(md_files(&project).len(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn git_output_larger_than_the_pipe_buffer_is_read() {
+    let sandbox = Sandbox::new();
+    sandbox.fake_git("head -c 200000 /dev/zero | tr '\\0' ' '; echo /src/checkout/big-repo");
+
+    run_claude_hook(&sandbox);
+
+    let project = sandbox
+        .path("vault")
+        .join(VAULT_AI_ROOT)
+        .join("Claude Code")
+        .join("big-repo");
+    assert_eq!(md_files(&project).len(), 1);
+}
+
+#[cfg(unix)]
+#[test]
+fn hung_or_failing_git_falls_back_to_cwd_name() {
+    for body in [
+        "exec /bin/sleep 30",
+        "echo 'fatal: not a git repository' >&2; exit 128",
+    ] {
+        let sandbox = Sandbox::new();
+        sandbox.fake_git(body);
+
+        let started = std::time::Instant::now();
+        run_claude_hook(&sandbox);
+
+        assert!(
+            started.elapsed() < std::time::Duration::from_secs(20),
+            "{body}"
+        );
+        assert_eq!(
+            md_files(&sandbox.tool_dir("Claude Code")).len(),
+            1,
+            "{body}"
+        );
+    }
+}
+
+#[test]
+fn validate_payload_prints_target_without_writing() {
+    let sandbox = Sandbox::new();
+    let transcript = sandbox.path("session.jsonl");
+    let line = claude_line("user", "2024-05-01T10:00:00Z", "Check the hook");
+    fs::write(&transcript, line).unwrap();
+    let payload = json!({
+        "session_id": "validate-session",
+        "transcript_path": transcript,
+        "cwd": sandbox.path("proj"),
+    })
+    .to_string();
+
+    let out = sandbox
+        .command("claude_session_to_obsidian")
+        .arg("--validate-payload")
+        .write_stdin(payload)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("session_id: validate-session\n"), "{out}");
+    assert!(
+        out.contains(&format!("cwd: {}\n", sandbox.path("proj").display())),
+        "{out}"
+    );
+    assert!(out.contains("project: proj\n"), "{out}");
+    let threads = sandbox.tool_dir("Claude Code").join("Threads");
+    let day = threads.join("2024").join("05");
+    assert!(out.contains(&format!("note: {}", day.display())), "{out}");
+    assert!(out.contains("status: export\n"), "{out}");
+
+    let notification = json!({
+        "type": "agent-turn-complete",
+        "thread-id": "validate-thread",
+        "cwd": sandbox.path("proj"),
+        "input-messages": ["hi"],
+        "last-assistant-message": "hello",
+    })
+    .to_string();
+    let out = sandbox
+        .command("codex_notify_to_obsidian")
+        .args(["--validate-payload", &notification])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("thread_id: validate-thread\n"), "{out}");
+    assert!(out.contains("project: proj\n"), "{out}");
+    assert!(out.contains("(new note)\nstatus: export\n"), "{out}");
+
+    assert!(md_files(&sandbox.path("vault")).is_empty());
+    assert!(!sandbox.tool_dir("Codex").exists());
+}
+
+#[test]
+fn review_warnings_follow_verbosity_flags() {
+    let sandbox = Sandbox::new();
+    let payload = json!({"session_id": "no-such-session", "cwd": sandbox.path("proj")}).to_string();
+    fs::create_dir_all(sandbox.tool_dir("Claude Code").join("Threads")).unwrap();
+
+    let stderr = |args: &[&str]| {
+        let out = sandbox
+            .command("review_session")
+            .args(args)
+            .write_stdin(payload.clone())
+            .assert()
+            .success()
+            .get_output()
+            .stderr
+            .clone();
+        String::from_utf8(out).unwrap()
+    };
+
+    let warning = "warning: MD file not found for session: no-such-session\n";
+    assert_eq!(stderr(&[]), warning);
+    assert_eq!(stderr(&["-v"]), warning);
+    assert_eq!(stderr(&["--quiet"]), "");
+}
+
+#[test]
+fn misconfiguration_fails_while_no_ops_exit_zero() {
+    let sandbox = Sandbox::new();
+    let transcript = sandbox.path("session.jsonl");
+    fs::write(
+        &transcript,
+        claude_line("user", "2024-05-01T10:00:00Z", "hello"),
+    )
+    .unwrap();
+    let payload = json!({
+        "session_id": "exit-session",
+        "transcript_path": transcript,
+        "cwd": sandbox.path("proj"),
+    })
+    .to_string();
+
+    let out = sandbox
+        .command("claude_session_to_obsidian")
+        .env_remove("OBSIDIAN_VAULT")
+        .write_stdin(payload)
+        .assert()
+        .failure()
+        .get_output()
+        .stderr
+        .clone();
+    assert!(String::from_utf8(out).unwrap().contains("OBSIDIAN_VAULT"));
+
+    for bin in ["claude_session_to_obsidian", "review_session"] {
+        sandbox
+            .command(bin)
+            .env_remove("OBSIDIAN_VAULT")
+            .write_stdin("  \n")
+            .assert()
+            .success();
+    }
+    let other_type = json!({"type": "something-else", "thread-id": "t"}).to_string();
+    sandbox
+        .command("codex_notify_to_obsidian")
+        .env_remove("OBSIDIAN_VAULT")
+        .arg(other_type)
+        .assert()
+        .success();
+
+    sandbox
+        .command("claude_session_to_obsidian")
+        .args(["--import", sandbox.path("no-such-dir").to_str().unwrap()])
+        .assert()
+        .failure();
+    assert!(md_files(&sandbox.path("vault")).is_empty());
+}
+
+#[test]
+fn tool_hooks_add_live_activity_that_survives_the_next_export() {
+    let sandbox = Sandbox::new();
+    let transcript = sandbox.path("session.jsonl");
+    fs::write(
+        &transcript,
+        claude_line("user", "2024-05-01T10:00:00Z", "Run the tests"),
+    )
+    .unwrap();
+    let stop = json!({
+        "session_id": "tool-session",
+        "transcript_path": transcript,
+        "cwd": sandbox.path("proj"),
+    })
+    .to_string();
+    let tool_hook = |event: &str| {
+        let payload = json!({
+            "hook_event_name": event,
+            "session_id": "tool-session",
+            "cwd": sandbox.path("proj"),
+            "tool_name": "Bash",
+            "tool_use_id": "toolu_e2e",
+            "tool_input": {"command": "cargo test"},
+            "tool_response": {"stdout": "ok"},
+        });
+        sandbox
+            .command("claude_tool_to_obsidian")
+            .write_stdin(payload.to_string())
+            .assert()
+            .success();
+    };
+
+    sandbox
+        .command("claude_session_to_obsidian")
+        .write_stdin(stop.clone())
+        .assert()
+        .success();
+    for event in ["PreToolUse", "PreToolUse", "PostToolUse"] {
+        tool_hook(event);
+    }
+    sandbox
+        .command("claude_session_to_obsidian")
+        .write_stdin(stop)
+        .assert()
+        .success();
+
+    let notes = md_files(&sandbox.tool_dir("Claude Code").join("Threads"));
+    assert_eq!(notes.len(), 1);
+    let note = fs::read_to_string(&notes[0]).unwrap();
+    assert_eq!(
+        note.matches("<!-- tool-use:toolu_e2e -->").count(),
+        1,
+        "{note}"
+    );
+    assert!(note.contains("**Bash** `cargo test` — done"), "{note}");
+    assert!(note.contains("Run the tests"));
+}
+
+#[test]
+fn tool_hook_before_the_first_export_creates_the_note_it_reuses() {
+    for layout in ["note", "exploded"] {
+        let sandbox = Sandbox::new();
+        let transcript = sandbox.path("session.jsonl");
+        let line = claude_line("user", "2024-05-01T10:00:00Z", "Run the tests");
+        fs::write(&transcript, line).unwrap();
+        // Neither payload carries a session id, so both synthesize the same one.
+        let tool = json!({
+            "hook_event_name": "PreToolUse",
+            "transcript_path": transcript,
+            "cwd": sandbox.path("proj"),
+            "tool_name": "Bash",
+            "tool_input": {"command": "cargo test"},
+        });
+        sandbox
+            .command("claude_tool_to_obsidian")
+            .env("LLM_LOGS_LAYOUT", layout)
+            .write_stdin(tool.to_string())
+            .assert()
+            .success();
+        let threads = sandbox.tool_dir("Claude Code").join("Threads");
+        assert_eq!(md_files(&threads).len(), 1, "{layout}");
+
+        let stop = json!({"transcript_path": transcript, "cwd": sandbox.path("proj")});
+        sandbox
+            .command("claude_session_to_obsidian")
+            .env("LLM_LOGS_LAYOUT", layout)
+            .write_stdin(stop.to_string())
+            .assert()
+            .success();
+
+        let notes: Vec<PathBuf> = md_files(&threads)
+            .into_iter()
+            .filter(|p| {
+                !p.file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .starts_with("0001-")
+            })
+            .collect();
+        assert_eq!(notes.len(), 1, "{layout}: {notes:?}");
+        let note = fs::read_to_string(&notes[0]).unwrap();
+        assert!(
+            note.contains("**Bash** `cargo test` <!-- tool-use:(missing) -->"),
+            "{note}"
+        );
+        assert_eq!(note.matches("session_id:").count(), 1, "{note}");
+        if layout == "note" {
+            assert!(note.contains("Run the tests"), "{note}");
+        }
+    }
+}